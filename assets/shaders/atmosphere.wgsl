@@ -0,0 +1,26 @@
+#import bevy_pbr::{
+    forward_io::VertexOutput,
+    mesh_view_bindings::{lights, view},
+}
+
+@group(1) @binding(0)
+var<uniform> color: vec4<f32>;
+@group(1) @binding(1)
+var<uniform> falloff: f32;
+
+// Rim glow that is strongest where the shell is seen edge on, brighter on the side facing the sun.
+// The material is additive, so the alpha of the output is 0 and the strength of the glow is in the color's alpha.
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    var normal = normalize(in.world_normal);
+    var view_direction = normalize(view.world_position - in.world_position.xyz);
+    var rim = pow(1.0 - abs(dot(normal, view_direction)), falloff);
+
+    var sunlight = 0.15;
+    if (lights.n_directional_lights > 0u) {
+        var sun = lights.directional_lights[0];
+        sunlight = max(sunlight, clamp(dot(normal, sun.direction_to_light) + 0.4, 0.0, 1.0));
+    }
+
+    return vec4<f32>(color.rgb * color.a * rim * sunlight, 0.0);
+}
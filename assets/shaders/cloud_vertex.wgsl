@@ -1,6 +1,10 @@
-#import bevy_pbr::mesh_view_bindings
-#import bevy_pbr::mesh_bindings
-#import bevy_pbr::mesh_functions
+#import bevy_pbr::{
+    forward_io::Vertex,
+    mesh_functions::{get_model_matrix, mesh_position_local_to_world, mesh_normal_local_to_world},
+    mesh_view_bindings::lights,
+    view_transformations::position_world_to_clip,
+}
+#import bevy_render::instance_index::get_instance_index
 
 @group(1) @binding(0)
 var<uniform> time: f32;
@@ -16,123 +20,93 @@ var<uniform> wave_1_dir: vec2<f32>;
 var<uniform> wave_2_dir: vec2<f32>;
 @group(1) @binding(6)
 var<uniform> wave_3_dir: vec2<f32>;
+@group(1) @binding(7)
+var<uniform> color: vec4<f32>;
+@group(1) @binding(8)
+var<uniform> coverage: f32;
 
 struct GerstnerWaveOutput {
     pt_increment: vec3<f32>,
-    new_tangent: vec3<f32>,
-    new_binormal: vec3<f32>,
-}; 
+    slope: vec3<f32>,
+    height: f32,
+};
 
-fn gerstner_wave(wave_dir: vec2<f32>, pt: vec3<f32>, tangent: vec3<f32>, binormal: vec3<f32>) -> GerstnerWaveOutput {
+// The waves travel over a sphere, so "up" is the normal of the shell and the wave direction is projected onto
+// the tangent plane at each point
+fn gerstner_wave(wave_dir: vec2<f32>, pt: vec3<f32>, up: vec3<f32>) -> GerstnerWaveOutput {
     var out: GerstnerWaveOutput;
 
     var pi: f32 = 3.14159;
 
     var k: f32 = 2.0 * pi / wavelength;
     var c: f32 = sqrt(9.8 / k);
-    var d: vec2<f32> = normalize(wave_dir.xy);
-    var f: f32 = k * (dot(d, pt.xz) - c * time);
+    var d: vec3<f32> = normalize(vec3<f32>(wave_dir.x, 0.0, wave_dir.y));
+    var f: f32 = k * (dot(d, pt) - c * speed * time);
     var a: f32 = steepness / k;
+    var along: vec3<f32> = d - up * dot(d, up);
 
-    out.new_tangent = tangent + vec3<f32>(
-				-d.x * d.x * (steepness * sin(f)),
-				d.x * (steepness * cos(f)),
-				-d.x * d.y * (steepness * sin(f))
-			);
-    
-    out.new_binormal = binormal + vec3<f32>(
-        -d.x * d.y * (steepness * sin(f)),
-        d.y * (steepness * cos(f)),
-        -d.y * d.y * (steepness * sin(f))
-    );
-
-    out.pt_increment = vec3<f32>(
-        d.x * (a * cos(f)),
-        a * sin(f),
-        d.y * (a * cos(f))
-    );
+    out.pt_increment = along * (a * cos(f)) + up * (a * sin(f));
+    out.slope = along * (steepness * cos(f));
+    out.height = sin(f);
 
     return out;
 }
 
-struct Vertex {
-    @location(0) position: vec3<f32>,
-    @location(1) normal: vec3<f32>,
-#ifdef VERTEX_UVS
-    @location(2) uv: vec2<f32>,
-#endif
-#ifdef VERTEX_TANGENTS
-    @location(3) tangent: vec4<f32>,
-#endif
-#ifdef VERTEX_COLORS
-    @location(4) color: vec4<f32>,
-#endif
-#ifdef SKINNED
-    @location(5) joint_indices: vec4<u32>,
-    @location(6) joint_weights: vec4<f32>,
-#endif
+// The fragment stage shades with the height of the waves the vertex was displaced by, instead of working the waves out
+// again from the displaced position
+struct CloudVertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_normal: vec3<f32>,
+    @location(1) height: f32,
 };
 
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) world_position: vec4<f32>,
-    @location(1) world_normal: vec3<f32>,
-#ifdef VERTEX_UVS
-    @location(2) uv: vec2<f32>,
-#endif
-#ifdef VERTEX_TANGENTS
-    @location(3) world_tangent: vec4<f32>,
-#endif
-#ifdef VERTEX_COLORS
-    @location(4) color: vec4<f32>,
-#endif
-};
-
-@vertex
-fn vertex(vertex: Vertex) -> VertexOutput {
-    var out: VertexOutput;
-    var model = mesh.model;
-
-    var pt = vertex.position;
-    var tangent = vec3<f32>(0.0, 0.0, 0.0);
-    var binormal = vec3<f32>(0.0, 0.0, 0.0);
-    var out_pt = pt;
-
-    var wave_1 = gerstner_wave(wave_1_dir, pt, tangent, binormal);
-    out_pt += wave_1.pt_increment;
-    tangent = wave_1.new_tangent;
-    binormal = wave_1.new_binormal;
-
-    var wave_2 = gerstner_wave(wave_2_dir, pt, tangent, binormal);
-    out_pt += wave_2.pt_increment;
-    tangent = wave_2.new_tangent;
-    binormal = wave_2.new_binormal;
-
-    var wave_3 = gerstner_wave(wave_3_dir, pt, tangent, binormal);
-    out_pt += wave_3.pt_increment;
-    tangent = wave_3.new_tangent;
-    binormal = wave_3.new_binormal;
+fn gerstner_waves(pt: vec3<f32>, up: vec3<f32>) -> GerstnerWaveOutput {
+    var wave_1 = gerstner_wave(wave_1_dir, pt, up);
+    var wave_2 = gerstner_wave(wave_2_dir, pt, up);
+    var wave_3 = gerstner_wave(wave_3_dir, pt, up);
 
-    out.world_position = mesh_position_local_to_world(model, vec4<f32>(out_pt, 1.0));
-
-    var normal = normalize(cross(tangent, binormal));
-    out.world_normal = mesh_normal_local_to_world(normal);
+    var out: GerstnerWaveOutput;
+    out.pt_increment = wave_1.pt_increment + wave_2.pt_increment + wave_3.pt_increment;
+    out.slope = wave_1.slope + wave_2.slope + wave_3.slope;
+    out.height = (wave_1.height + wave_2.height + wave_3.height) / 3.0;
 
-    var light = (dot(normal, vertex.normal) + 2.0) / 2.0;
+    return out;
+}
 
-#ifdef VERTEX_COLORS
-    out.color = vertex.color * (vec4<f32>(light, light, light, light));
-#endif
+@vertex
+fn vertex(vertex: Vertex) -> CloudVertexOutput {
+    var out: CloudVertexOutput;
+    var instance_index = get_instance_index(vertex.instance_index);
+    var model = get_model_matrix(instance_index);
 
-#ifdef VERTEX_TANGENTS
-    out.world_tangent = mesh_tangent_local_to_world(model, vec4<f32>(tangent, 1.0));
-#endif
+    var pt = vertex.position;
+    var up = normalize(vertex.normal);
+    var waves = gerstner_waves(pt, up);
 
-#ifdef VERTEX_UVS
-    out.uv = vertex.uv;
-#endif
+    var world_position = mesh_position_local_to_world(model, vec4<f32>(pt + waves.pt_increment, 1.0));
+    out.position = position_world_to_clip(world_position.xyz);
 
-    out.clip_position = mesh_position_world_to_clip(out.world_position);
+    var normal = normalize(up - waves.slope);
+    out.world_normal = mesh_normal_local_to_world(normal, instance_index);
+    out.height = waves.height;
 
     return out;
-}
\ No newline at end of file
+}
+
+// Wave crests are covered by clouds and troughs are clear, lit by the sun with a soft wrap around the terminator
+@fragment
+fn fragment(in: CloudVertexOutput) -> @location(0) vec4<f32> {
+    var normal = normalize(in.world_normal);
+    var density = smoothstep(1.0 - 2.0 * coverage, 1.0, in.height * 0.5 + 0.5);
+
+    // Light colors are scaled by their intensity, only their hue is used so the clouds don't saturate
+    var light = min(lights.ambient_color.rgb * 0.125, vec3<f32>(0.4));
+    if (lights.n_directional_lights > 0u) {
+        var sun = lights.directional_lights[0];
+        var sun_color = sun.color.rgb / max(max(sun.color.r, sun.color.g), max(sun.color.b, 0.0001));
+        var wrap = clamp((dot(normal, sun.direction_to_light) + 0.3) / 1.3, 0.0, 1.0);
+        light += sun_color * wrap * 0.75;
+    }
+
+    return vec4<f32>(color.rgb * min(light, vec3<f32>(1.0)), color.a * density);
+}
@@ -0,0 +1,243 @@
+// Manifest of the files the game loads from the assets directory.
+// Every file is checked on startup so missing or broken ones are reported once with a clear message,
+// and textures that can't be used are replaced by procedural fallbacks instead of failing later.
+
+use bevy::{
+    log::{error, info},
+    prelude::{AssetServer, Assets, Color, Handle, Image, Res, Resource, Vec3},
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    utils::HashMap,
+};
+use std::{fmt, fs, path::Path};
+
+use crate::noise::fbm;
+
+// CONSTANTS
+
+pub const ASSETS_DIRECTORY: &str = "assets";
+pub const ICON_PATH: &str = "icons/main_icon.png";
+pub const FONT_PATH: &str = "fonts/FiraSans-Bold.ttf";
+pub const CANNON_MODEL_PATH: &str = "models/cannon.glb";
+pub const PLANET_DIFFUSE_PATH: &str = "textures/planet/DirtRug_diffuse.png";
+pub const PLANET_NORMAL_PATH: &str = "textures/planet/DirtRug_normal.png";
+pub const PLANET_METALLIC_ROUGHNESS_PATH: &str = "textures/planet/DirtRug_metallic_roughness.png";
+pub const PLANET_ROUGHNESS_PATH: &str = "textures/planet/DirtRug_roughness.png";
+pub const ASTEROID_BASE_PATH: &str = "textures/asteroid/asteroid_base.png";
+pub const ASTEROID_NORMAL_PATH: &str = "textures/asteroid/asteroid_normal.png";
+
+pub const ASSET_MANIFEST: [(&str, AssetKind); 12] = [
+    (ICON_PATH, AssetKind::Image),
+    (FONT_PATH, AssetKind::Font),
+    (CANNON_MODEL_PATH, AssetKind::Model),
+    (PLANET_DIFFUSE_PATH, AssetKind::Image),
+    (PLANET_NORMAL_PATH, AssetKind::Image),
+    (PLANET_METALLIC_ROUGHNESS_PATH, AssetKind::Image),
+    (PLANET_ROUGHNESS_PATH, AssetKind::Image),
+    (ASTEROID_BASE_PATH, AssetKind::Image),
+    (ASTEROID_NORMAL_PATH, AssetKind::Image),
+    ("shaders/cloud_vertex.wgsl", AssetKind::Shader),
+    ("shaders/atmosphere.wgsl", AssetKind::Shader),
+    ("levels/corona.ron", AssetKind::Level),
+];
+
+const FALLBACK_TEXTURE_SIZE: u32 = 256;
+
+// RESOURCES
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AssetKind {
+    Image,
+    Font,
+    Model,
+    Shader,
+    Level,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum AssetStatus {
+    Available,
+    Missing,
+    Invalid(String),
+}
+
+// Status of every file in the manifest, checked on startup
+#[derive(Resource)]
+pub struct AssetManifest {
+    pub directory: String,
+    pub statuses: HashMap<&'static str, (AssetKind, AssetStatus)>,
+}
+
+impl fmt::Display for AssetKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AssetKind::Image => "image",
+            AssetKind::Font => "font",
+            AssetKind::Model => "model",
+            AssetKind::Shader => "shader",
+            AssetKind::Level => "level",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl AssetManifest {
+    // Checks that every file in the manifest exists and looks like the kind of asset it should be
+    pub fn validate(directory: &str) -> Self {
+        let statuses = ASSET_MANIFEST
+            .iter()
+            .map(|(path, kind)| {
+                let status = validate_asset(&Path::new(directory).join(path), *kind);
+                (*path, (*kind, status))
+            })
+            .collect();
+
+        Self {
+            directory: directory.to_string(),
+            statuses,
+        }
+    }
+
+    // Files that aren't in the manifest are assumed to be available
+    pub fn is_available(&self, path: &str) -> bool {
+        self.statuses
+            .get(path)
+            .is_none_or(|(_, status)| *status == AssetStatus::Available)
+    }
+
+    // Loads the texture if it's available, otherwise adds the fallback image if there is one
+    pub fn texture(
+        &self,
+        asset_server: &AssetServer,
+        images: &mut Assets<Image>,
+        path: &'static str,
+        fallback: impl FnOnce(&Self) -> Option<Image>,
+    ) -> Option<Handle<Image>> {
+        if self.is_available(path) {
+            Some(asset_server.load(path))
+        } else {
+            fallback(self).map(|image| images.add(image))
+        }
+    }
+}
+
+// STARTUP SYSTEMS
+
+// Logs every file that is missing or invalid once, so problems are reported before anything tries to use them
+pub fn report_asset_problems(asset_manifest: Res<AssetManifest>) {
+    let mut problems = asset_manifest
+        .statuses
+        .iter()
+        .filter(|(_, (_, status))| *status != AssetStatus::Available)
+        .collect::<Vec<_>>();
+    problems.sort_by_key(|(path, _)| **path);
+
+    for (path, (kind, status)) in problems.iter() {
+        let location = Path::new(&asset_manifest.directory).join(path);
+        match status {
+            AssetStatus::Missing => error!("Missing {} {}", kind, location.display()),
+            AssetStatus::Invalid(reason) => {
+                error!("Invalid {} {}: {}", kind, location.display(), reason)
+            }
+            AssetStatus::Available => {}
+        }
+    }
+
+    if problems.is_empty() {
+        info!(
+            "All {} assets in the manifest are available",
+            ASSET_MANIFEST.len()
+        );
+    }
+}
+
+// HELPER FUNCTIONS
+
+fn validate_asset(path: &Path, kind: AssetKind) -> AssetStatus {
+    let Ok(bytes) = fs::read(path) else {
+        return AssetStatus::Missing;
+    };
+    if bytes.is_empty() {
+        return AssetStatus::Invalid("the file is empty".to_string());
+    }
+
+    match kind {
+        AssetKind::Image => match image::guess_format(&bytes) {
+            Ok(_) => AssetStatus::Available,
+            Err(err) => AssetStatus::Invalid(err.to_string()),
+        },
+        AssetKind::Font => match bytes.get(0..4) {
+            Some([0, 1, 0, 0]) | Some(b"OTTO") | Some(b"true") => AssetStatus::Available,
+            _ => AssetStatus::Invalid("not a TrueType or OpenType font".to_string()),
+        },
+        AssetKind::Model => match bytes.get(0..4) {
+            Some(b"glTF") => AssetStatus::Available,
+            _ => AssetStatus::Invalid("not a binary glTF file".to_string()),
+        },
+        AssetKind::Shader | AssetKind::Level => match String::from_utf8(bytes) {
+            Ok(_) => AssetStatus::Available,
+            Err(_) => AssetStatus::Invalid("not a UTF-8 text file".to_string()),
+        },
+    }
+}
+
+// Mottled texture blending between two colours
+pub fn noise_texture(dark: Color, light: Color, seed: u32) -> Image {
+    let size = FALLBACK_TEXTURE_SIZE;
+    let [dark, light] = [dark, light].map(|color| Vec3::from_slice(&color.as_rgba_f32()));
+
+    let data = (0..size * size)
+        .flat_map(|index| {
+            let uv = Vec3::new((index % size) as f32, (index / size) as f32, 0.0) / size as f32;
+            let t = fbm(uv * 8.0, 4, seed) * 0.5 + 0.5;
+            let color = dark.lerp(light, t.clamp(0.0, 1.0)) * 255.0;
+            [color.x as u8, color.y as u8, color.z as u8, 255]
+        })
+        .collect();
+
+    Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+// Packs a greyscale roughness map into the green channel of a metallic roughness texture, metallic is left at full
+// so the material's metallic value is used as is
+pub fn metallic_roughness_from_roughness(
+    asset_manifest: &AssetManifest,
+    path: &str,
+) -> Option<Image> {
+    if !asset_manifest.is_available(path) {
+        return None;
+    }
+
+    let roughness = match image::open(Path::new(&asset_manifest.directory).join(path)) {
+        Ok(image) => image.into_luma8(),
+        Err(err) => {
+            error!("Failed to read the roughness map {}: {}", path, err);
+            return None;
+        }
+    };
+    let (width, height) = roughness.dimensions();
+
+    let data = roughness
+        .pixels()
+        .flat_map(|pixel| [0, pixel.0[0], 255, 255])
+        .collect();
+
+    Some(Image::new(
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8Unorm,
+    ))
+}
@@ -1,27 +1,48 @@
 // Asteroids fall from the sky and roll around the planet.
 // If they hit the player, the game ends. If they get hit by a cannon ball or another asteroid, they despawn after exploding.
+// Their lumpy rock meshes are generated while loading, and each asteroid picks one of them and a material from GameAssets.
 
 use bevy::{
+    log::warn,
     prelude::{
-        default, shape, AssetServer, Assets, Commands, Component, Mesh, PbrBundle, Res, ResMut,
-        Resource, StandardMaterial, Transform, Vec3,
+        default, shape, AssetServer, Assets, Color, Commands, Component, EulerRot, Handle, Image,
+        Mesh, PbrBundle, Quat, Res, ResMut, Resource, StandardMaterial, Transform, Vec3,
     },
+    render::mesh::VertexAttributeValues,
     time::{Time, Timer, TimerMode},
 };
 use bevy_rapier3d::prelude::{
-    ActiveEvents, CoefficientCombineRule, Collider, ColliderMassProperties, ExternalForce,
-    ExternalImpulse, Friction, GravityScale, Restitution, RigidBody,
+    ActiveEvents, Ccd, CoefficientCombineRule, Collider, ColliderMassProperties, ExternalForce,
+    ExternalImpulse, Friction, GravityScale, Restitution, RigidBody, Velocity,
 };
-use rand::{thread_rng, Rng};
+use rand::Rng;
+use std::f32::consts::TAU;
 
-use crate::common::PLANET_SIZE;
+use crate::{
+    asset_manifest::{noise_texture, AssetManifest, ASTEROID_BASE_PATH, ASTEROID_NORMAL_PATH},
+    common::{GameRng, PLANET_SIZE},
+    game_assets::GameAssets,
+    noise::{fbm, radial_surface_normal},
+    settings::Settings,
+};
 
 // CONSTANTS
 
-const ASTEROID_SIZE: f32 = 1.0;
+pub const ASTEROID_SIZE: f32 = 1.0;
+pub const ASTEROID_DENSITY: f32 = 1.0;
 const ASTEROID_IMPULSE_MAGNITUDE: f32 = 50.0;
 const ASTEROID_SPAWN_DELAY: f32 = 50.0;
 const ASTEROID_SPAWN_ALTITUDE: f32 = PLANET_SIZE * 2.0;
+const ASTEROID_VARIANTS: u32 = 8;
+const ASTEROID_SUBDIVISIONS: usize = 6;
+const ASTEROID_LUMPINESS: f32 = 0.3; // How far the surface is displaced, relative to the radius
+const ASTEROID_NOISE_FREQUENCY: f32 = 1.6;
+const ASTEROID_NOISE_OCTAVES: u32 = 3;
+const ASTEROID_TINTS: [Color; 3] = [
+    Color::WHITE,
+    Color::rgb(0.85, 0.78, 0.72),
+    Color::rgb(0.7, 0.72, 0.8),
+];
 
 // COMPONENTS
 
@@ -33,29 +54,38 @@ pub struct Asteroid {}
 #[derive(Resource)]
 pub struct AsteroidSpawnTimer(pub Timer);
 
+// Number of asteroid waves that have been spawned in the current round
+#[derive(Resource)]
+pub struct AsteroidWave(pub u32);
+
 // STARTUP SYSTEMS
 
-pub fn setup_asteroids(mut commands: Commands) {
+pub fn setup_asteroids(mut commands: Commands, settings: Res<Settings>) {
     // Insert resouce to keep track of time until the next asteroid is spawned
     commands.insert_resource(AsteroidSpawnTimer(Timer::from_seconds(
-        ASTEROID_SPAWN_DELAY,
+        ASTEROID_SPAWN_DELAY * settings.difficulty.spawn_delay_scale(),
         TimerMode::Repeating,
     )));
+
+    commands.insert_resource(AsteroidWave(0));
 }
 
 // SYSTEMS
 
 pub fn spawn_asteroids(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    game_assets: Res<GameAssets>,
     mut spawn_timer: ResMut<AsteroidSpawnTimer>,
+    mut wave: ResMut<AsteroidWave>,
+    mut rng: ResMut<GameRng>,
+    settings: Res<Settings>,
     time: Res<Time>,
 ) {
     if spawn_timer.0.tick(time.delta()).finished() {
-        // Generate random number generator
-        let mut rng = thread_rng();
+        wave.0 += 1;
+
+        // Use the round's seeded random number generator
+        let rng = &mut rng.0;
 
         // Spawn asteroid at random position above the planet
         let x = rng.gen_range(-1.0..1.0) * 10.0;
@@ -70,35 +100,34 @@ pub fn spawn_asteroids(
         let to_planet = position.normalize();
         let direction = (Vec3::new(x, y, z).cross(to_planet)).normalize();
 
+        // Random rock and material, turned randomly so asteroids sharing a rock don't look the same
+        let (mesh, collider) = game_assets.asteroid_variants
+            [rng.gen_range(0..game_assets.asteroid_variants.len())]
+        .clone();
+        let material = game_assets.asteroid_materials
+            [rng.gen_range(0..game_assets.asteroid_materials.len())]
+        .clone();
+        let rotation = Quat::from_euler(
+            EulerRot::XYZ,
+            rng.gen_range(0.0..TAU),
+            rng.gen_range(0.0..TAU),
+            rng.gen_range(0.0..TAU),
+        );
+
         commands
             .spawn(PbrBundle {
-                mesh: meshes.add(
-                    shape::Icosphere {
-                        radius: ASTEROID_SIZE / 2.0,
-                        subdivisions: 16,
-                    }
-                    .try_into()
-                    .unwrap(),
-                ),
-                material: materials.add(StandardMaterial {
-                    base_color_texture: asset_server
-                        .load("textures/asteroid/asteroid_base.png")
-                        .into(),
-                    normal_map_texture: asset_server
-                        .load("textures/asteroid/asteroid_normal.png")
-                        .into(),
-                    perceptual_roughness: 1.0,
-                    metallic: 1.0,
-                    ..default()
-                }),
-                transform: Transform::from_translation(position),
+                mesh,
+                material,
+                transform: Transform::from_translation(position).with_rotation(rotation),
                 ..default()
             })
             .insert(Asteroid {})
-            .insert(Collider::ball(ASTEROID_SIZE / 2.0))
+            .insert(collider)
             .insert(ActiveEvents::COLLISION_EVENTS)
             .insert(RigidBody::Dynamic)
-            .insert(ColliderMassProperties::Density(1.0))
+            // Asteroids fall fast enough to pass through the planet's surface between two steps
+            .insert(Ccd::enabled())
+            .insert(ColliderMassProperties::Density(ASTEROID_DENSITY))
             .insert(GravityScale(0.0))
             .insert(Friction {
                 coefficient: 1.0,
@@ -108,13 +137,113 @@ pub fn spawn_asteroids(
                 coefficient: 0.0,
                 combine_rule: CoefficientCombineRule::Max,
             })
+            .insert(Velocity::default())
             .insert(ExternalForce {
                 force: Vec3::ZERO,
                 torque: Vec3::ZERO,
             })
             .insert(ExternalImpulse {
-                impulse: direction * ASTEROID_IMPULSE_MAGNITUDE,
+                impulse: direction
+                    * ASTEROID_IMPULSE_MAGNITUDE
+                    * settings.difficulty.impulse_scale(),
                 torque_impulse: Vec3::ZERO,
             });
     }
 }
+
+// HELPER FUNCTIONS
+
+// Asteroid rocks generated from the first seeds, shared by every asteroid
+pub fn asteroid_variants(meshes: &mut Assets<Mesh>) -> Vec<(Handle<Mesh>, Collider)> {
+    (0..ASTEROID_VARIANTS)
+        .map(|seed| {
+            let (mesh, collider) = generate_asteroid(seed);
+            (meshes.add(mesh), collider)
+        })
+        .collect()
+}
+
+// One material per tint sharing the same textures, missing textures are replaced by fallbacks
+pub fn asteroid_materials(
+    asset_server: &AssetServer,
+    asset_manifest: &AssetManifest,
+    images: &mut Assets<Image>,
+) -> Vec<StandardMaterial> {
+    let base_color_texture =
+        asset_manifest.texture(asset_server, images, ASTEROID_BASE_PATH, |_| {
+            Some(noise_texture(
+                Color::rgb(0.2, 0.2, 0.2),
+                Color::rgb(0.5, 0.48, 0.45),
+                1,
+            ))
+        });
+    let normal_map_texture =
+        asset_manifest.texture(asset_server, images, ASTEROID_NORMAL_PATH, |_| None);
+
+    ASTEROID_TINTS
+        .iter()
+        .map(|tint| StandardMaterial {
+            base_color: *tint,
+            base_color_texture: base_color_texture.clone(),
+            normal_map_texture: normal_map_texture.clone(),
+            perceptual_roughness: 1.0,
+            metallic: 1.0,
+            ..default()
+        })
+        .collect()
+}
+
+// Lumpy rock made by displacing an icosphere with noise, with a convex hull collider around it
+pub fn generate_asteroid(seed: u32) -> (Mesh, Collider) {
+    let radius_at = |direction: Vec3| {
+        ASTEROID_SIZE / 2.0
+            * (1.0
+                + ASTEROID_LUMPINESS
+                    * fbm(
+                        direction * ASTEROID_NOISE_FREQUENCY,
+                        ASTEROID_NOISE_OCTAVES,
+                        seed,
+                    ))
+    };
+
+    let mut mesh = Mesh::try_from(shape::Icosphere {
+        radius: 1.0,
+        subdivisions: ASTEROID_SUBDIVISIONS,
+    })
+    .unwrap_or_else(|_| Mesh::from(shape::UVSphere::default()));
+
+    let directions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+        Some(VertexAttributeValues::Float32x3(positions)) => positions
+            .iter()
+            .map(|position| Vec3::from(*position).normalize())
+            .collect::<Vec<_>>(),
+        _ => Vec::new(),
+    };
+    let positions = directions
+        .iter()
+        .map(|direction| *direction * radius_at(*direction))
+        .collect::<Vec<_>>();
+    let normals = directions
+        .iter()
+        .map(|direction| radial_surface_normal(*direction, radius_at).to_array())
+        .collect::<Vec<_>>();
+
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        positions
+            .iter()
+            .map(|position| position.to_array())
+            .collect::<Vec<_>>(),
+    );
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+
+    // Tangents are needed for the normal map
+    if let Err(err) = mesh.generate_tangents() {
+        warn!("Failed to generate an asteroid's tangents: {}", err);
+    }
+
+    let collider =
+        Collider::convex_hull(&positions).unwrap_or_else(|| Collider::ball(ASTEROID_SIZE / 2.0));
+
+    (mesh, collider)
+}
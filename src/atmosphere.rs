@@ -0,0 +1,183 @@
+// Atmosphere and day/night cycle.
+// A glowing shell around the planet gives it a rim of atmosphere, brighter on the side facing the sun.
+// The sun orbits the planet over the level's day length, and its colour and the ambient light follow the time of day
+// where the player is, so the light changes as the player's side of the planet turns away from the sun.
+
+use bevy::{
+    pbr::{MaterialMeshBundle, NotShadowCaster},
+    prelude::{
+        default, shape, AlphaMode, AmbientLight, Asset, Assets, Color, Commands, Component,
+        DirectionalLight, Material, Mesh, Name, Quat, Query, Res, ResMut, Resource, Time,
+        Transform, Vec3, With, Without,
+    },
+    reflect::TypePath,
+    render::render_resource::{AsBindGroup, ShaderRef},
+};
+use std::f32::consts::TAU;
+
+use crate::{
+    common::{Sun, PLANET_SIZE},
+    level::LevelConfig,
+    player::{PlayerCollider, PlayerId},
+};
+
+// CONSTANTS
+
+const ATMOSPHERE_SHELL_SUBDIVISIONS: usize = 16;
+const SUN_ORBIT_AXIS: Vec3 = Vec3::X;
+const DAY_SUN_COLOR: Vec3 = Vec3::new(1.0, 0.7, 0.7);
+const DUSK_SUN_COLOR: Vec3 = Vec3::new(1.0, 0.45, 0.2);
+const NIGHT_SUN_COLOR: Vec3 = Vec3::new(0.4, 0.45, 0.7);
+const DAY_ILLUMINANCE: f32 = 100_000.0;
+const NIGHT_ILLUMINANCE: f32 = 5_000.0;
+const DAY_AMBIENT_COLOR: Vec3 = Vec3::new(1.0, 1.0, 0.8);
+const NIGHT_AMBIENT_COLOR: Vec3 = Vec3::new(0.35, 0.4, 0.7);
+const DAY_AMBIENT_BRIGHTNESS: f32 = 2.0;
+const NIGHT_AMBIENT_BRIGHTNESS: f32 = 0.5;
+
+// COMPONENTS
+
+#[derive(Component)]
+pub struct AtmosphereShell {}
+
+// RESOURCES
+
+// Time of day between 0 and 1, at 0 the sun is straight above +Y like at the start of a round
+#[derive(Resource, Default)]
+pub struct DayNightCycle {
+    pub time_of_day: f32,
+}
+
+// MATERIALS
+
+#[derive(Asset, TypePath, AsBindGroup, Clone, Debug)]
+pub struct AtmosphereMaterial {
+    #[uniform(0)]
+    pub color: Color,
+    #[uniform(1)]
+    pub falloff: f32,
+}
+
+impl Material for AtmosphereMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/atmosphere.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Add
+    }
+}
+
+// STARTUP SYSTEMS
+
+pub fn setup_atmosphere(
+    mut commands: Commands,
+    level: Res<LevelConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<AtmosphereMaterial>>,
+) {
+    commands.insert_resource(DayNightCycle::default());
+
+    let atmosphere = &level.atmosphere;
+    if !atmosphere.enabled {
+        return;
+    }
+
+    let Ok(mesh) = Mesh::try_from(shape::Icosphere {
+        radius: PLANET_SIZE + atmosphere.altitude,
+        subdivisions: ATMOSPHERE_SHELL_SUBDIVISIONS,
+    }) else {
+        return;
+    };
+    let [red, green, blue, alpha] = atmosphere.color;
+
+    commands
+        .spawn(MaterialMeshBundle {
+            mesh: meshes.add(mesh),
+            material: materials.add(AtmosphereMaterial {
+                color: Color::rgba(red, green, blue, alpha),
+                falloff: atmosphere.falloff,
+            }),
+            ..default()
+        })
+        .insert(Name::new("Atmosphere"))
+        .insert(AtmosphereShell {})
+        .insert(NotShadowCaster);
+}
+
+// SYSTEMS
+
+// Moves the sun along its orbit and lights the scene for the time of day where the player is
+pub fn update_day_night(
+    time: Res<Time>,
+    level: Res<LevelConfig>,
+    mut day_night_cycle: ResMut<DayNightCycle>,
+    mut ambient_light: ResMut<AmbientLight>,
+    mut sun_query: Query<(&mut DirectionalLight, &mut Transform), With<Sun>>,
+    player_query: Query<(&PlayerId, &Transform), (With<PlayerCollider>, Without<Sun>)>,
+) {
+    if level.day_length > 0.0 {
+        day_night_cycle.time_of_day =
+            (day_night_cycle.time_of_day + time.delta_seconds() / level.day_length).fract();
+    }
+
+    let direction = sun_direction(day_night_cycle.time_of_day);
+    // The light is shared by every player, so it follows the time of day where the first player is
+    let elevation = player_query
+        .iter()
+        .find(|(player_id, _)| **player_id == PlayerId::FIRST)
+        .map_or(1.0, |(_, transform)| {
+            direction.dot(transform.translation.normalize())
+        });
+    let lighting = sky_lighting(elevation);
+
+    for (mut directional_light, mut transform) in sun_query.iter_mut() {
+        *transform = Transform::IDENTITY.looking_to(-direction, SUN_ORBIT_AXIS);
+        directional_light.color = lighting.sun_color;
+        directional_light.illuminance = lighting.sun_illuminance;
+    }
+
+    ambient_light.color = lighting.ambient_color;
+    ambient_light.brightness = lighting.ambient_brightness;
+}
+
+// HELPER FUNCTIONS
+
+// Sun and ambient light for a time of day
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SkyLighting {
+    pub sun_color: Color,
+    pub sun_illuminance: f32,
+    pub ambient_color: Color,
+    pub ambient_brightness: f32,
+}
+
+// Direction from the centre of the planet towards the sun
+pub fn sun_direction(time_of_day: f32) -> Vec3 {
+    Quat::from_axis_angle(SUN_ORBIT_AXIS, TAU * time_of_day) * Vec3::Y
+}
+
+// Lighting for a sun at the given elevation above the horizon, between -1 (midnight) and 1 (noon)
+pub fn sky_lighting(elevation: f32) -> SkyLighting {
+    let day = smoothstep(-0.2, 0.3, elevation);
+    // Strongest when the sun is on the horizon
+    let dusk = 1.0 - (elevation.abs() / 0.35).min(1.0);
+
+    let sun_color = NIGHT_SUN_COLOR
+        .lerp(DAY_SUN_COLOR, day)
+        .lerp(DUSK_SUN_COLOR, dusk * 0.7);
+    let ambient_color = NIGHT_AMBIENT_COLOR.lerp(DAY_AMBIENT_COLOR, day);
+
+    SkyLighting {
+        sun_color: Color::rgb(sun_color.x, sun_color.y, sun_color.z),
+        sun_illuminance: NIGHT_ILLUMINANCE + (DAY_ILLUMINANCE - NIGHT_ILLUMINANCE) * day,
+        ambient_color: Color::rgb(ambient_color.x, ambient_color.y, ambient_color.z),
+        ambient_brightness: NIGHT_AMBIENT_BRIGHTNESS
+            + (DAY_AMBIENT_BRIGHTNESS - NIGHT_AMBIENT_BRIGHTNESS) * day,
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -0,0 +1,552 @@
+// Sound effects for gameplay events.
+// Gameplay events are routed to sound cues first, which keeps what plays when testable without an audio device,
+// then the cues are played from a pool of synthesized variants with a random pitch, panned relative to the
+// primary camera, scaled by the settings' volume buses and limited to a number of concurrent voices.
+// The plugin also drives the adaptive music from the music module.
+
+use bevy::{
+    audio::{
+        AddAudioSource, AudioSink, AudioSinkPlayback, AudioSourceBundle, PlaybackMode,
+        PlaybackSettings, SpatialAudioSink, SpatialScale, Volume,
+    },
+    prelude::{
+        in_state, resource_changed, resource_exists_and_changed, App, Assets, Commands, Component,
+        Condition, Entity, Event, EventReader, EventWriter, Handle, IntoSystemConfigs, Local,
+        OnEnter, OnExit, Or, Plugin, Query, Res, ResMut, Resource, Startup, Transform,
+        TransformBundle, Update, Vec3, With,
+    },
+    utils::{HashMap, HashSet},
+};
+use rand::{thread_rng, Rng};
+
+use crate::{
+    asteroids::AsteroidWave,
+    common::{AsteroidDestroyedEvent, GameState, PauseState, PLANET_SIZE},
+    input::{InputDevice, PlayerInput, ShootEvent, ShootTimer},
+    music::{
+        crossfade_music, play_game_over_stinger, play_wave_stinger, setup_music_tracks,
+        start_music, update_music_intensity, MusicLayer, MusicStinger,
+    },
+    settings::Settings,
+    synth::{envelope, filtered_noise, oscillator, render, SynthSound},
+};
+
+// CONSTANTS
+
+pub const MAX_SFX_VOICES: usize = 16;
+pub const SFX_VARIANTS: usize = 4; // Number of differently seeded versions of each sound effect
+pub const LISTENER_EAR_GAP: f32 = PLANET_SIZE * 2.0;
+const SPATIAL_SCALE: f32 = 1.0 / (PLANET_SIZE * 3.0); // Sounds near the player are at full volume
+
+// PLUGINS
+
+pub struct GameAudioPlugin;
+
+impl Plugin for GameAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<SynthSound>()
+            .insert_resource(SpatialScale::new(SPATIAL_SCALE))
+            .add_event::<SfxEvent>()
+            .add_systems(Startup, (setup_sound_effects, setup_music_tracks))
+            .add_systems(OnEnter(GameState::Playing), start_music)
+            .add_systems(
+                Update,
+                (
+                    (route_shoot_sfx, route_asteroid_sfx, route_reload_sfx).chain(),
+                    play_sound_effects,
+                    apply_sfx_volume.run_if(resource_changed::<Settings>()),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    (update_music_intensity, crossfade_music).chain().run_if(
+                        in_state(GameState::Playing).and_then(in_state(PauseState::Running)),
+                    ),
+                    play_wave_stinger.run_if(resource_exists_and_changed::<AsteroidWave>()),
+                ),
+            )
+            .add_systems(
+                OnEnter(GameState::GameOver),
+                (route_game_over_sfx, play_game_over_stinger),
+            )
+            .add_systems(
+                OnEnter(PauseState::Paused),
+                pause_game_audio.run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(OnExit(PauseState::Paused), resume_game_audio);
+    }
+}
+
+// COMPONENTS
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SfxCue {
+    Shoot,
+    Explosion,
+    Collision,
+    ReloadComplete,
+    GameOver,
+}
+
+// A playing sound effect, despawned when it finishes
+#[derive(Component)]
+pub struct SfxVoice {
+    pub volume: f32, // Volume before the settings' volume buses are applied
+}
+
+// RESOURCES
+
+#[derive(Resource)]
+pub struct SfxLibrary {
+    pub sounds: HashMap<SfxCue, Vec<Handle<SynthSound>>>,
+}
+
+// EVENTS
+
+#[derive(Event, Clone, Copy, PartialEq, Debug)]
+pub struct SfxEvent {
+    pub cue: SfxCue,
+    pub position: Option<Vec3>, // Sounds without a position aren't panned
+}
+
+impl SfxCue {
+    pub const ALL: [SfxCue; 5] = [
+        SfxCue::Shoot,
+        SfxCue::Explosion,
+        SfxCue::Collision,
+        SfxCue::ReloadComplete,
+        SfxCue::GameOver,
+    ];
+
+    pub fn volume(&self) -> f32 {
+        match self {
+            SfxCue::Shoot => 0.8,
+            SfxCue::Explosion => 1.0,
+            SfxCue::Collision => 0.5,
+            SfxCue::ReloadComplete => 0.4,
+            SfxCue::GameOver => 0.9,
+        }
+    }
+
+    // Range of the random playback speed, which changes the pitch
+    pub fn pitch_range(&self) -> (f32, f32) {
+        match self {
+            SfxCue::Shoot => (0.9, 1.1),
+            SfxCue::Explosion => (0.8, 1.2),
+            SfxCue::Collision => (0.7, 1.3),
+            SfxCue::ReloadComplete => (0.97, 1.03),
+            SfxCue::GameOver => (1.0, 1.0),
+        }
+    }
+
+    fn synthesize(&self, seed: u64) -> SynthSound {
+        match self {
+            SfxCue::Shoot => synthesize_shoot(seed),
+            SfxCue::Explosion => synthesize_explosion(seed),
+            SfxCue::Collision => synthesize_collision(seed),
+            SfxCue::ReloadComplete => synthesize_reload(seed),
+            SfxCue::GameOver => synthesize_game_over(),
+        }
+    }
+}
+
+// STARTUP SYSTEMS
+
+// Synthesizes the pool of sound effect variants
+pub fn setup_sound_effects(mut commands: Commands, mut sounds: ResMut<Assets<SynthSound>>) {
+    let sounds = SfxCue::ALL
+        .iter()
+        .map(|cue| {
+            let variants = (0..SFX_VARIANTS as u64)
+                .map(|seed| sounds.add(cue.synthesize(seed)))
+                .collect();
+            (*cue, variants)
+        })
+        .collect();
+
+    commands.insert_resource(SfxLibrary { sounds });
+}
+
+// SYSTEMS
+
+pub fn route_shoot_sfx(mut ev_shoot: EventReader<ShootEvent>, mut ev_sfx: EventWriter<SfxEvent>) {
+    for ev in ev_shoot.read() {
+        ev_sfx.send(SfxEvent {
+            cue: SfxCue::Shoot,
+            position: Some(ev.position),
+        });
+    }
+}
+
+// Asteroids destroyed by the player explode, asteroids colliding with each other make a smaller sound
+pub fn route_asteroid_sfx(
+    mut ev_asteroid_destroyed: EventReader<AsteroidDestroyedEvent>,
+    mut ev_sfx: EventWriter<SfxEvent>,
+) {
+    for ev in ev_asteroid_destroyed.read() {
+        ev_sfx.send(SfxEvent {
+            cue: if ev.points > 0 {
+                SfxCue::Explosion
+            } else {
+                SfxCue::Collision
+            },
+            position: Some(ev.position),
+        });
+    }
+}
+
+// The shoot timers are only ticked while reloading, so a player has finished reloading when their timer goes from
+// not finished to finished. Only the local human players hear it, not bots or players driven by a server
+pub fn route_reload_sfx(
+    shoot_timer_query: Query<(Entity, &ShootTimer, &PlayerInput)>,
+    mut reloading_players: Local<HashSet<Entity>>,
+    mut ev_sfx: EventWriter<SfxEvent>,
+) {
+    reloading_players.retain(|entity| shoot_timer_query.contains(*entity));
+
+    for (entity, shoot_timer, player_input) in shoot_timer_query.iter() {
+        if player_input.device == InputDevice::Bot {
+            continue;
+        }
+
+        if !shoot_timer.0.finished() {
+            reloading_players.insert(entity);
+        } else if reloading_players.remove(&entity) {
+            ev_sfx.send(SfxEvent {
+                cue: SfxCue::ReloadComplete,
+                position: None,
+            });
+        }
+    }
+}
+
+pub fn route_game_over_sfx(mut ev_sfx: EventWriter<SfxEvent>) {
+    ev_sfx.send(SfxEvent {
+        cue: SfxCue::GameOver,
+        position: None,
+    });
+}
+
+// Plays the queued cues, dropping them once the voice cap has been reached
+pub fn play_sound_effects(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    sfx_library: Option<Res<SfxLibrary>>,
+    mut ev_sfx: EventReader<SfxEvent>,
+    voice_query: Query<&SfxVoice>,
+) {
+    let Some(sfx_library) = sfx_library else {
+        ev_sfx.clear();
+        return;
+    };
+
+    let mut rng = thread_rng();
+    let mut voices = voice_query.iter().len();
+
+    for ev in ev_sfx.read() {
+        if voices >= MAX_SFX_VOICES {
+            continue;
+        }
+
+        let Some(variants) = sfx_library.sounds.get(&ev.cue) else {
+            continue;
+        };
+        if variants.is_empty() {
+            continue;
+        }
+
+        let (min_pitch, max_pitch) = ev.cue.pitch_range();
+        let speed = if min_pitch < max_pitch {
+            rng.gen_range(min_pitch..max_pitch)
+        } else {
+            min_pitch
+        };
+
+        commands
+            .spawn(AudioSourceBundle {
+                source: variants[rng.gen_range(0..variants.len())].clone(),
+                settings: PlaybackSettings {
+                    mode: PlaybackMode::Despawn,
+                    volume: Volume::new_relative(ev.cue.volume() * settings.sfx_gain()),
+                    speed,
+                    paused: false,
+                    spatial: ev.position.is_some(),
+                },
+            })
+            .insert(TransformBundle::from(Transform::from_translation(
+                ev.position.unwrap_or(Vec3::ZERO),
+            )))
+            .insert(SfxVoice {
+                volume: ev.cue.volume(),
+            });
+
+        voices += 1;
+    }
+}
+
+// Runs only when the settings have changed, applies the volume buses to the sound effects that are playing
+pub fn apply_sfx_volume(
+    settings: Res<Settings>,
+    voice_query: Query<(&SfxVoice, Option<&AudioSink>, Option<&SpatialAudioSink>)>,
+) {
+    for (voice, sink, spatial_sink) in voice_query.iter() {
+        let volume = voice.volume * settings.sfx_gain();
+        if let Some(sink) = sink {
+            sink.set_volume(volume);
+        }
+        if let Some(spatial_sink) = spatial_sink {
+            spatial_sink.set_volume(volume);
+        }
+    }
+}
+
+// Sound effects and music are paused together with the game
+pub fn pause_game_audio(
+    voice_query: Query<
+        (Option<&AudioSink>, Option<&SpatialAudioSink>),
+        Or<(With<SfxVoice>, With<MusicLayer>, With<MusicStinger>)>,
+    >,
+) {
+    for (sink, spatial_sink) in voice_query.iter() {
+        if let Some(sink) = sink {
+            sink.pause();
+        }
+        if let Some(spatial_sink) = spatial_sink {
+            spatial_sink.pause();
+        }
+    }
+}
+
+pub fn resume_game_audio(
+    voice_query: Query<
+        (Option<&AudioSink>, Option<&SpatialAudioSink>),
+        Or<(With<SfxVoice>, With<MusicLayer>, With<MusicStinger>)>,
+    >,
+) {
+    for (sink, spatial_sink) in voice_query.iter() {
+        if let Some(sink) = sink {
+            sink.play();
+        }
+        if let Some(spatial_sink) = spatial_sink {
+            spatial_sink.play();
+        }
+    }
+}
+
+// HELPER FUNCTIONS
+
+// Deep boom with a falling pitch and a short burst of noise
+fn synthesize_shoot(seed: u64) -> SynthSound {
+    let mut noise = filtered_noise(seed, 2500.0);
+    let mut boom = oscillator();
+
+    SynthSound::normalized(
+        render(0.5, |t| {
+            let frequency = 40.0 + 90.0 * (-t / 0.08).exp();
+            boom(frequency) * envelope(t, 0.002, 0.12) + noise() * 4.0 * envelope(t, 0.001, 0.04)
+        }),
+        0.9,
+    )
+}
+
+// Long rumble made of two layers of noise
+fn synthesize_explosion(seed: u64) -> SynthSound {
+    let mut crack = filtered_noise(seed, 1200.0);
+    let mut rumble = filtered_noise(seed + 1000, 150.0);
+
+    SynthSound::normalized(
+        render(1.5, |t| {
+            crack() * envelope(t, 0.003, 0.15) + rumble() * 6.0 * envelope(t, 0.01, 0.5)
+        }),
+        0.9,
+    )
+}
+
+// Short dull thud
+fn synthesize_collision(seed: u64) -> SynthSound {
+    let mut noise = filtered_noise(seed, 400.0);
+    let mut thud = oscillator();
+
+    SynthSound::normalized(
+        render(0.4, |t| {
+            noise() * 3.0 * envelope(t, 0.002, 0.06) + thud(80.0) * 0.5 * envelope(t, 0.002, 0.1)
+        }),
+        0.8,
+    )
+}
+
+// Two quick mechanical clicks
+fn synthesize_reload(seed: u64) -> SynthSound {
+    let mut noise = filtered_noise(seed, 5000.0);
+    let mut tone = oscillator();
+
+    SynthSound::normalized(
+        render(0.2, |t| {
+            let second = (t - 0.09).max(0.0);
+            let click = if t < 0.09 {
+                envelope(t, 0.001, 0.015)
+            } else {
+                envelope(second, 0.001, 0.02)
+            };
+            let frequency = if t < 0.09 { 1400.0 } else { 1900.0 };
+
+            (tone(frequency) * 0.6 + noise() * 2.0) * click
+        }),
+        0.7,
+    )
+}
+
+// Three descending notes
+fn synthesize_game_over() -> SynthSound {
+    let mut tone = oscillator();
+    let notes = [440.0, 349.23, 261.63];
+    let note_length = 0.35;
+
+    SynthSound::normalized(
+        render(note_length * notes.len() as f32 + 0.6, |t| {
+            let index = ((t / note_length) as usize).min(notes.len() - 1);
+            let note_t = t - index as f32 * note_length;
+            let sustain = if index == notes.len() - 1 { 0.5 } else { 0.2 };
+
+            tone(notes[index]) * envelope(note_t, 0.01, sustain)
+        }),
+        0.8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{
+        ecs::event::{Events, ManualEventReader},
+        prelude::{NextState, State},
+        MinimalPlugins,
+    };
+
+    // App with only the routing systems, so no audio device is needed
+    fn routing_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_state::<GameState>()
+            .add_event::<ShootEvent>()
+            .add_event::<AsteroidDestroyedEvent>()
+            .add_event::<SfxEvent>()
+            .add_systems(
+                Update,
+                (route_shoot_sfx, route_asteroid_sfx, route_reload_sfx).chain(),
+            )
+            .add_systems(OnEnter(GameState::GameOver), route_game_over_sfx);
+        app
+    }
+
+    fn emitted_cues(app: &App, reader: &mut ManualEventReader<SfxEvent>) -> Vec<SfxCue> {
+        let events = app.world.resource::<Events<SfxEvent>>();
+        reader.read(events).map(|ev| ev.cue).collect()
+    }
+
+    #[test]
+    fn gameplay_events_emit_cues() {
+        let mut app = routing_app();
+        let mut reader = ManualEventReader::<SfxEvent>::default();
+
+        app.update();
+        assert!(emitted_cues(&app, &mut reader).is_empty());
+
+        app.world.send_event(ShootEvent {
+            shooter: Entity::PLACEHOLDER,
+            position: Vec3::X,
+            direction: Vec3::Y,
+        });
+        app.world.send_event(AsteroidDestroyedEvent {
+            position: Vec3::Z,
+            points: 2,
+        });
+        app.world.send_event(AsteroidDestroyedEvent {
+            position: Vec3::Y,
+            points: 0,
+        });
+        app.update();
+
+        let events = app.world.resource::<Events<SfxEvent>>();
+        let emitted: Vec<SfxEvent> = reader.read(events).copied().collect();
+        assert_eq!(
+            emitted,
+            vec![
+                SfxEvent {
+                    cue: SfxCue::Shoot,
+                    position: Some(Vec3::X),
+                },
+                SfxEvent {
+                    cue: SfxCue::Explosion,
+                    position: Some(Vec3::Z),
+                },
+                SfxEvent {
+                    cue: SfxCue::Collision,
+                    position: Some(Vec3::Y),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reload_completion_emits_cue_once_for_human_players() {
+        let mut app = routing_app();
+        let mut reader = ManualEventReader::<SfxEvent>::default();
+
+        let mut timer = bevy::prelude::Timer::from_seconds(0.5, bevy::time::TimerMode::Once);
+        let players = [InputDevice::Mouse, InputDevice::Bot].map(|device| {
+            app.world
+                .spawn((
+                    ShootTimer(timer.clone()),
+                    PlayerInput {
+                        device,
+                        last_valid_cursor_pos: None,
+                    },
+                ))
+                .id()
+        });
+        app.update();
+        assert!(emitted_cues(&app, &mut reader).is_empty());
+
+        timer.tick(timer.duration());
+        for player in players {
+            app.world
+                .entity_mut(player)
+                .insert(ShootTimer(timer.clone()));
+        }
+        app.update();
+        app.update();
+        assert_eq!(
+            emitted_cues(&app, &mut reader),
+            vec![SfxCue::ReloadComplete]
+        );
+    }
+
+    #[test]
+    fn game_over_emits_cue() {
+        let mut app = routing_app();
+        let mut reader = ManualEventReader::<SfxEvent>::default();
+
+        app.world
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::GameOver);
+        app.update();
+
+        assert_eq!(
+            *app.world.resource::<State<GameState>>().get(),
+            GameState::GameOver
+        );
+        assert_eq!(emitted_cues(&app, &mut reader), vec![SfxCue::GameOver]);
+    }
+
+    #[test]
+    fn sound_effects_are_synthesized() {
+        for cue in SfxCue::ALL {
+            let sound = cue.synthesize(0);
+            assert!(!sound.samples.is_empty());
+            assert!(sound.samples.iter().all(|sample| sample.abs() <= 1.0));
+        }
+    }
+}
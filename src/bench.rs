@@ -0,0 +1,376 @@
+// Benchmark of the simulation without a window, used to catch performance regressions.
+// Asteroids and cannon balls are kept at the configured numbers on the planet, topping them up every tick with the
+// game's own spawning systems, while gravity, Rapier and the collision handling run at fixed ticks. Every phase of a
+// tick runs in its own schedule so it can be timed, the rest of the app's update isn't included.
+
+use bevy::{
+    ecs::schedule::ScheduleLabel,
+    prelude::{
+        apply_deferred, App, Entity, IntoSystemConfigs, Mut, Resource, Schedule, Startup, Update,
+        With, World,
+    },
+};
+use bevy_rapier3d::prelude::{NoUserData, PhysicsSet, RapierPhysicsPlugin};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    asteroids::{setup_asteroids, spawn_asteroids, Asteroid, AsteroidSpawnTimer},
+    cannon_ball::{shoot_cannon_ball, CannonBall, CANNON_BALL_INITIAL_OFFSET},
+    common::{
+        despawn_destroyed, gravity, handle_collisions, setup_round, AsteroidDestroyedEvent,
+        DestroyEvent, GameState, RequestedSeed, Score,
+    },
+    headless::{add_headless_plugins, insert_fixed_ticks},
+    input::ShootEvent,
+    level::{setup_level, Levels, LEVELS_DIRECTORY},
+    planet::{random_direction, setup_planet, PlanetShape},
+    server::setup_server_assets,
+    settings::Settings,
+};
+
+// CONSTANTS
+
+pub const DEFAULT_BENCH_ASTEROIDS: usize = 100;
+pub const DEFAULT_BENCH_CANNON_BALLS: usize = 100;
+pub const DEFAULT_BENCH_TICKS: u32 = 1000;
+const SPAWN_INTERVAL: Duration = Duration::from_nanos(1); // Asteroid spawn timer while topping up, once per run
+
+// SCHEDULES
+
+// The timed phases of a tick, in the order they run
+#[derive(ScheduleLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BenchPhase {
+    SpawnAsteroids,
+    ShootCannonBalls,
+    Gravity,
+    RapierSync,
+    RapierStep,
+    RapierWriteback,
+    Collisions,
+}
+
+impl BenchPhase {
+    pub const ALL: [BenchPhase; 7] = [
+        BenchPhase::SpawnAsteroids,
+        BenchPhase::ShootCannonBalls,
+        BenchPhase::Gravity,
+        BenchPhase::RapierSync,
+        BenchPhase::RapierStep,
+        BenchPhase::RapierWriteback,
+        BenchPhase::Collisions,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BenchPhase::SpawnAsteroids => "spawn_asteroids",
+            BenchPhase::ShootCannonBalls => "shoot_cannon_ball",
+            BenchPhase::Gravity => "gravity",
+            BenchPhase::RapierSync => "rapier sync",
+            BenchPhase::RapierStep => "rapier step",
+            BenchPhase::RapierWriteback => "rapier writeback",
+            BenchPhase::Collisions => "handle_collisions",
+        }
+    }
+
+    fn schedule(&self) -> Schedule {
+        let mut schedule = Schedule::new(*self);
+        match self {
+            BenchPhase::SpawnAsteroids => {
+                schedule.add_systems((spawn_asteroids, apply_deferred).chain())
+            }
+            BenchPhase::ShootCannonBalls => {
+                schedule.add_systems((shoot_cannon_ball, apply_deferred).chain())
+            }
+            BenchPhase::Gravity => schedule.add_systems(gravity),
+            BenchPhase::RapierSync => schedule.add_systems(
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackend),
+            ),
+            BenchPhase::RapierStep => schedule.add_systems(
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::StepSimulation),
+            ),
+            BenchPhase::RapierWriteback => schedule.add_systems(
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::Writeback),
+            ),
+            BenchPhase::Collisions => {
+                schedule.add_systems((handle_collisions, despawn_destroyed, apply_deferred).chain())
+            }
+        };
+        schedule
+    }
+}
+
+// BENCHMARK
+
+#[derive(Clone, Copy, Debug)]
+pub struct BenchConfig {
+    pub asteroids: usize,
+    pub cannon_balls: usize,
+    pub ticks: u32,
+    pub seed: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            asteroids: DEFAULT_BENCH_ASTEROIDS,
+            cannon_balls: DEFAULT_BENCH_CANNON_BALLS,
+            ticks: DEFAULT_BENCH_TICKS,
+            seed: 0,
+        }
+    }
+}
+
+pub struct BenchReport {
+    pub config: BenchConfig,
+    pub elapsed: Duration, // Including the parts of the update that aren't timed
+    pub phase_times: Vec<(BenchPhase, Duration)>,
+    pub asteroids: usize,
+    pub cannon_balls: usize,
+    pub entities: usize,
+    pub asteroids_spawned: usize,
+    pub cannon_balls_spawned: usize,
+}
+
+impl BenchReport {
+    pub fn ticks_per_second(&self) -> f64 {
+        self.config.ticks as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} ticks with {} asteroids and {} cannon balls in {:.3} s, {:.1} ticks/s",
+            self.config.ticks,
+            self.config.asteroids,
+            self.config.cannon_balls,
+            self.elapsed.as_secs_f64(),
+            self.ticks_per_second()
+        )?;
+
+        writeln!(
+            f,
+            "{:<20} {:>12} {:>14}",
+            "phase", "total ms", "per tick us"
+        )?;
+        for (phase, time) in &self.phase_times {
+            writeln!(
+                f,
+                "{:<20} {:>12.3} {:>14.1}",
+                phase.label(),
+                time.as_secs_f64() * 1e3,
+                time.as_secs_f64() * 1e6 / self.config.ticks.max(1) as f64
+            )?;
+        }
+
+        writeln!(
+            f,
+            "entities at the end: {} asteroids, {} cannon balls, {} in total",
+            self.asteroids, self.cannon_balls, self.entities
+        )?;
+        write!(
+            f,
+            "spawned to keep the numbers up: {} asteroids, {} cannon balls",
+            self.asteroids_spawned, self.cannon_balls_spawned
+        )
+    }
+}
+
+// RESOURCES
+
+// Time spent in every phase and what was spawned to keep the numbers up
+#[derive(Resource)]
+pub struct BenchState {
+    config: BenchConfig,
+    rng: StdRng,
+    phase_times: Vec<Duration>, // By BenchPhase::ALL index
+    asteroids_spawned: usize,
+    cannon_balls_spawned: usize,
+}
+
+impl BenchState {
+    // Forgets the times and spawns so far
+    fn reset(&mut self) {
+        self.phase_times.fill(Duration::ZERO);
+        self.asteroids_spawned = 0;
+        self.cannon_balls_spawned = 0;
+    }
+}
+
+// PLUGINS
+
+// App that runs one benchmark tick per update
+pub fn bench_app(config: BenchConfig) -> App {
+    let mut app = App::new();
+
+    // Rapier's systems are run by the phases instead of in PostUpdate
+    add_headless_plugins(&mut app);
+    app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default().with_default_system_setup(false));
+    insert_fixed_ticks(&mut app);
+
+    // Events
+    app.add_event::<ShootEvent>();
+    app.add_event::<AsteroidDestroyedEvent>();
+    app.add_event::<DestroyEvent>();
+
+    // Resources
+    app.insert_resource(Score(0));
+    app.insert_resource(Settings::default());
+    app.insert_resource(RequestedSeed(config.seed));
+    app.insert_resource(Levels::load(LEVELS_DIRECTORY));
+    app.insert_resource(BenchState {
+        config,
+        rng: StdRng::seed_from_u64(config.seed),
+        phase_times: vec![Duration::ZERO; BenchPhase::ALL.len()],
+        asteroids_spawned: 0,
+        cannon_balls_spawned: 0,
+    });
+
+    // State
+    app.add_state::<GameState>();
+
+    for phase in BenchPhase::ALL {
+        app.add_schedule(phase.schedule());
+    }
+
+    app.add_systems(
+        Startup,
+        (
+            setup_server_assets,
+            setup_level,
+            setup_round,
+            apply_deferred,
+            setup_planet,
+            setup_asteroids,
+        )
+            .chain(),
+    )
+    .add_systems(Update, run_bench_tick);
+
+    app
+}
+
+// SYSTEMS
+
+// Tops the asteroids and cannon balls up, then runs and times every phase
+fn run_bench_tick(world: &mut World) {
+    let (asteroids, cannon_balls) = (count::<Asteroid>(world), count::<CannonBall>(world));
+
+    world.resource_scope(|world, mut bench_state: Mut<BenchState>| {
+        let config = bench_state.config;
+
+        // One asteroid is spawned per run of spawn_asteroids, as soon as its timer is up
+        let missing_asteroids = config.asteroids.saturating_sub(asteroids);
+        world
+            .resource_mut::<AsteroidSpawnTimer>()
+            .0
+            .set_duration(SPAWN_INTERVAL);
+        let start = Instant::now();
+        for _ in 0..missing_asteroids {
+            world.run_schedule(BenchPhase::SpawnAsteroids);
+        }
+        bench_state.phase_times[0] += start.elapsed();
+        bench_state.asteroids_spawned += missing_asteroids;
+
+        // Cannon balls are shot from random points around the planet in random directions
+        let missing_cannon_balls = config.cannon_balls.saturating_sub(cannon_balls);
+        let shots = (0..missing_cannon_balls)
+            .map(|_| {
+                let normal = random_direction(&mut bench_state.rng);
+                let tangent = random_direction(&mut bench_state.rng)
+                    .cross(normal)
+                    .normalize_or_zero();
+                let surface = world.resource::<PlanetShape>().surface_point(normal);
+                ShootEvent {
+                    shooter: Entity::PLACEHOLDER,
+                    position: surface + normal * CANNON_BALL_INITIAL_OFFSET,
+                    direction: tangent,
+                }
+            })
+            .collect::<Vec<_>>();
+        world.send_event_batch(shots);
+        bench_state.cannon_balls_spawned += missing_cannon_balls;
+
+        for (index, phase) in BenchPhase::ALL.into_iter().enumerate().skip(1) {
+            let start = Instant::now();
+            world.run_schedule(phase);
+            bench_state.phase_times[index] += start.elapsed();
+        }
+    });
+}
+
+// HELPER FUNCTIONS
+
+// Runs the benchmark's ticks and reports on them
+pub fn run_bench(config: BenchConfig) -> BenchReport {
+    let mut app = bench_app(config);
+
+    // The first update runs the startup systems and spawns everything, which isn't part of the benchmark
+    app.update();
+    app.world.resource_mut::<BenchState>().reset();
+
+    let start = Instant::now();
+    for _ in 0..config.ticks {
+        app.update();
+    }
+    let elapsed = start.elapsed();
+
+    let bench_state = app.world.resource::<BenchState>();
+    let phase_times = BenchPhase::ALL
+        .into_iter()
+        .zip(bench_state.phase_times.iter().copied())
+        .collect();
+    let (asteroids_spawned, cannon_balls_spawned) = (
+        bench_state.asteroids_spawned,
+        bench_state.cannon_balls_spawned,
+    );
+
+    BenchReport {
+        config,
+        elapsed,
+        phase_times,
+        asteroids: count::<Asteroid>(&mut app.world),
+        cannon_balls: count::<CannonBall>(&mut app.world),
+        entities: app.world.entities().len() as usize,
+        asteroids_spawned,
+        cannon_balls_spawned,
+    }
+}
+
+fn count<T: bevy::prelude::Component>(world: &mut World) -> usize {
+    world.query_filtered::<(), With<T>>().iter(world).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_configured_numbers_up_and_times_every_phase() {
+        let config = BenchConfig {
+            asteroids: 20,
+            cannon_balls: 10,
+            ticks: 60,
+            seed: 1,
+        };
+        let report = run_bench(config);
+
+        assert!(report.ticks_per_second() > 0.0);
+        assert_eq!(report.phase_times.len(), BenchPhase::ALL.len());
+        assert!(report
+            .phase_times
+            .iter()
+            .filter(|(phase, _)| *phase != BenchPhase::SpawnAsteroids)
+            .all(|(_, time)| *time > Duration::ZERO));
+
+        // Topped up at the start of every tick, collisions can only have removed some since
+        assert!(report.asteroids > 0 && report.asteroids <= config.asteroids);
+        assert!(report.cannon_balls > 0 && report.cannon_balls <= config.cannon_balls);
+        assert!(report.entities >= report.asteroids + report.cannon_balls);
+    }
+}
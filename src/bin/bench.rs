@@ -0,0 +1,61 @@
+// Benchmark of the simulation's throughput without a window.
+// Run it with `cargo run --release --bin bench` for numbers worth comparing. Unknown flags and bad values are reported
+// with the usage, like the game binary's flags.
+
+use loose_cannon::{
+    bench::{run_bench, BenchConfig},
+    cli::parse_value,
+};
+
+const USAGE: &str = "\
+Usage: bench [options]
+  --asteroids <count>     Asteroids kept in play
+  --cannon-balls <count>  Cannon balls kept in flight
+  --ticks <ticks>         Ticks to time
+  --seed <seed>           Seed of the asteroids and cannon balls
+  --help                  Show this message";
+
+#[derive(Default)]
+struct BenchArgs {
+    config: BenchConfig,
+    help: bool,
+}
+
+impl BenchArgs {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut bench_args = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+
+            let config = &mut bench_args.config;
+            match arg.as_str() {
+                "--asteroids" => config.asteroids = parse_value("--asteroids", &value()?)?,
+                "--cannon-balls" => config.cannon_balls = parse_value("--cannon-balls", &value()?)?,
+                "--ticks" => config.ticks = parse_value("--ticks", &value()?)?,
+                "--seed" => config.seed = parse_value("--seed", &value()?)?,
+                "--help" | "-h" => bench_args.help = true,
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+
+        Ok(bench_args)
+    }
+}
+
+fn main() {
+    let bench_args = BenchArgs::parse(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, USAGE);
+        std::process::exit(1);
+    });
+    if bench_args.help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    println!("{}", run_bench(bench_args.config));
+}
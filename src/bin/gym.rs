@@ -0,0 +1,91 @@
+// Gym environment for external trainers, speaking line delimited JSON over stdin and stdout.
+// See gym::serve for the protocol. The first round starts with seed 0 until the trainer resets it. Unknown flags and
+// bad values are reported with the usage, like the game binary's flags.
+
+use std::io::{stdin, stdout};
+
+use loose_cannon::{
+    cli::parse_value,
+    gym::{serve, GymConfig, GymEnv},
+    settings::Settings,
+};
+
+const USAGE: &str = "\
+Usage: gym [options]
+  --ticks-per-step <ticks>     Ticks simulated for every action, at least 1
+  --nearest-asteroids <count>  Asteroids in each observation
+  --settings <file>            Play with the settings in this file
+  --help                       Show this message";
+
+#[derive(Default)]
+struct GymArgs {
+    ticks_per_step: Option<u32>,
+    nearest_asteroids: Option<usize>,
+    settings: Option<String>,
+    help: bool,
+}
+
+impl GymArgs {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut gym_args = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+
+            match arg.as_str() {
+                "--ticks-per-step" => {
+                    let ticks = value()?;
+                    match parse_value("--ticks-per-step", &ticks)? {
+                        0 => return Err(format!("Invalid value for --ticks-per-step: {}", ticks)),
+                        ticks => gym_args.ticks_per_step = Some(ticks),
+                    }
+                }
+                "--nearest-asteroids" => {
+                    gym_args.nearest_asteroids =
+                        Some(parse_value("--nearest-asteroids", &value()?)?)
+                }
+                "--settings" => gym_args.settings = Some(value()?),
+                "--help" | "-h" => gym_args.help = true,
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+
+        Ok(gym_args)
+    }
+}
+
+fn main() {
+    let gym_args = GymArgs::parse(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, USAGE);
+        std::process::exit(1);
+    });
+    if gym_args.help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let default_config = GymConfig::default();
+    let config = GymConfig {
+        settings: gym_args
+            .settings
+            .map(|path| Settings::load(&path))
+            .unwrap_or_default(),
+        ticks_per_step: gym_args
+            .ticks_per_step
+            .unwrap_or(default_config.ticks_per_step),
+        nearest_asteroids: gym_args
+            .nearest_asteroids
+            .unwrap_or(default_config.nearest_asteroids),
+        ..default_config
+    };
+
+    let mut env = GymEnv::new(config, 0);
+    if let Err(err) = serve(&mut env, stdin().lock(), stdout().lock()) {
+        eprintln!("Failed to talk to the trainer: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,74 @@
+// Dedicated headless server for networked games.
+// Clients join with `loose-cannon --connect <address>:<port>`. Unknown flags and bad values are reported with the usage,
+// like the game binary's flags.
+
+use bevy::log::LogPlugin;
+
+use loose_cannon::{
+    cli::parse_value,
+    level::LevelConfig,
+    network::{NetworkSocket, DEFAULT_SERVER_PORT},
+    server::server_app,
+};
+
+const USAGE: &str = "\
+Usage: server [options]
+  --port <port>   Listen on this port instead of the default one
+  --level <name>  Play this level instead of the default one
+  --help          Show this message";
+
+#[derive(Default)]
+struct ServerArgs {
+    port: Option<u16>,
+    level: Option<String>,
+    help: bool,
+}
+
+impl ServerArgs {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut server_args = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+
+            match arg.as_str() {
+                "--port" => server_args.port = Some(parse_value("--port", &value()?)?),
+                "--level" => server_args.level = Some(value()?),
+                "--help" | "-h" => server_args.help = true,
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+
+        Ok(server_args)
+    }
+}
+
+fn main() {
+    let server_args = ServerArgs::parse(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, USAGE);
+        std::process::exit(1);
+    });
+    if server_args.help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let port = server_args.port.unwrap_or(DEFAULT_SERVER_PORT);
+    let level = server_args
+        .level
+        .unwrap_or_else(|| LevelConfig::default().name);
+
+    let socket = NetworkSocket::bind(("0.0.0.0", port)).unwrap_or_else(|err| {
+        eprintln!("Failed to listen on port {}: {}", port, err);
+        std::process::exit(1);
+    });
+    println!("Listening on port {}, playing {}", port, level);
+
+    let mut app = server_app(socket, level);
+    app.add_plugins(LogPlugin::default());
+    app.run();
+}
@@ -0,0 +1,405 @@
+// Bots aim and shoot like players do, their BotController is read by handle_player_input in place of an input device.
+// A bot looks for the asteroid that will come closest to it soonest and shoots where the trajectory predictions say its
+// cannon ball will meet that asteroid. It only makes up its mind once per reaction time and its aim is off by a random
+// angle, both depending on its difficulty.
+// Bots join the local players as set in the settings, and play every player in the main menu's attract mode.
+
+use bevy::{
+    input::mouse::MouseMotion,
+    prelude::{
+        default, Color, Commands, Component, Entity, EventReader, Input, KeyCode, MouseButton,
+        Name, NextState, Quat, Query, Res, ResMut, Resource, State, TextBundle, Time, Timer,
+        Transform, Vec3, With, Without,
+    },
+    text::TextStyle,
+    time::TimerMode,
+    ui::{PositionType, Style, Val},
+};
+use bevy_rapier3d::prelude::Velocity;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    asteroids::{Asteroid, ASTEROID_SIZE},
+    cannon_ball::CANNON_BALL_RADIUS,
+    common::{GameSeed, GameState},
+    game_assets::GameAssets,
+    gym::GymAgent,
+    input::{InputDevice, PlayerInput, ShootTimer},
+    planet::PlanetShape,
+    player::{PlayerCollider, PlayerId, FIRE_DELAY, PLAYER_SIZE},
+    replay::ReplayedPlayer,
+    settings::{Difficulty, Settings, SettingsMenuState},
+    trajectory::{intercept_direction, predicted_asteroid},
+};
+
+// CONSTANTS
+
+pub const BOT_FIRE_RANGE: f32 = 30.0; // Bots only shoot at asteroids closer than this
+const BOT_HIT_DISTANCE: f32 = CANNON_BALL_RADIUS + ASTEROID_SIZE; // Bots only shoot if the shot will come this close
+const DANGER_RADIUS: f32 = PLAYER_SIZE * 4.0; // Asteroids passing closer than this are dealt with first
+const THREAT_HORIZON: f32 = 3.0; // Seconds ahead bots look for asteroids coming close
+const ATTRACT_MODE_DELAY: f32 = 20.0; // Seconds of no input on the main menu until the demo starts
+
+// COMPONENTS
+
+// Decisions of a bot, handle_player_input aims its cannon along the aim and takes the fire decision
+#[derive(Component)]
+pub struct BotController {
+    pub difficulty: Difficulty,
+    pub aim: Option<Vec3>, // Tangent the cannon is aimed along, shots go the opposite way
+    pub fire: bool,
+    reaction: Timer,
+    rng: StdRng,
+}
+
+impl BotController {
+    pub fn new(difficulty: Difficulty, seed: u64) -> Self {
+        Self {
+            difficulty,
+            aim: None,
+            fire: false,
+            reaction: Timer::from_seconds(difficulty.bot_reaction_time(), TimerMode::Repeating),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+// RESOURCES
+
+// While it exists every player is a bot in a demo round that any input ends
+#[derive(Resource)]
+pub struct AttractMode;
+
+// Time on the main menu without any input
+#[derive(Resource)]
+pub struct MenuIdleTimer(pub Timer);
+
+// STARTUP SYSTEMS
+
+// Hands the players after the local ones to bots, or every player in attract mode. Each bot's randomness comes from
+// the round's seed so a seeded round plays out the same way
+pub fn setup_bot_players(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    game_seed: Res<GameSeed>,
+    attract_mode: Option<Res<AttractMode>>,
+    player_query: Query<(Entity, &PlayerId), With<PlayerCollider>>,
+) {
+    for (entity, player_id) in player_query.iter() {
+        if attract_mode.is_none() && player_id.0 < settings.players as usize {
+            continue;
+        }
+
+        // The first shot can be fired right away
+        let mut timer = Timer::from_seconds(FIRE_DELAY, TimerMode::Once);
+        timer.tick(timer.duration());
+
+        commands.entity(entity).insert((
+            PlayerInput {
+                device: InputDevice::Bot,
+                last_valid_cursor_pos: None,
+            },
+            ShootTimer(timer),
+            BotController::new(
+                settings.bot_difficulty,
+                game_seed.0.wrapping_add(player_id.0 as u64),
+            ),
+        ));
+    }
+}
+
+pub fn setup_attract_mode_ui(mut commands: Commands, game_assets: Res<GameAssets>) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "Demo - press any key",
+                TextStyle {
+                    font: game_assets.font.clone(),
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(20.0),
+                left: Val::Px(20.0),
+                ..default()
+            }),
+        )
+        .insert(Name::new("Attract_Mode_Text"));
+}
+
+// The main menu starts counting again every time it's opened
+pub fn setup_menu_idle_timer(mut commands: Commands) {
+    commands.remove_resource::<AttractMode>();
+    commands.insert_resource(MenuIdleTimer(Timer::from_seconds(
+        ATTRACT_MODE_DELAY,
+        TimerMode::Once,
+    )));
+}
+
+// SYSTEMS
+
+// Picks a target for every bot once per reaction time, and aims where its shot will meet it. Gym agents and replayed
+// players are told what to do instead
+pub fn update_bots(
+    time: Res<Time>,
+    planet_shape: Res<PlanetShape>,
+    asteroid_query: Query<(&Transform, &Velocity), With<Asteroid>>,
+    mut bot_query: Query<
+        (&Transform, &ShootTimer, &mut BotController),
+        (
+            With<PlayerCollider>,
+            Without<Asteroid>,
+            Without<GymAgent>,
+            Without<ReplayedPlayer>,
+        ),
+    >,
+) {
+    for (player_transform, shoot_timer, mut bot_controller) in bot_query.iter_mut() {
+        if !bot_controller.reaction.tick(time.delta()).just_finished() {
+            continue;
+        }
+
+        let position = player_transform.translation;
+        let asteroids = asteroid_query
+            .iter()
+            .map(|(transform, velocity)| (transform.translation, velocity.linvel));
+        let Some((target, target_velocity)) = most_threatening_asteroid(position, asteroids) else {
+            bot_controller.fire = false;
+            continue;
+        };
+
+        let surface_normal = planet_shape.surface_normal(position);
+        let Some((direction, miss)) = intercept_direction(
+            &planet_shape,
+            position,
+            surface_normal,
+            predicted_asteroid(target, target_velocity),
+        ) else {
+            continue;
+        };
+
+        let aim_error = bot_controller.difficulty.bot_aim_error();
+        let error = bot_controller.rng.gen_range(-aim_error..=aim_error);
+        let direction = Quat::from_axis_angle(surface_normal, error) * direction.normalize();
+
+        bot_controller.aim = Some(-direction);
+        // Shots that would miss are held back, they'd roll around the planet and could hit the bot
+        bot_controller.fire = shoot_timer.0.finished()
+            && target.distance(position) < BOT_FIRE_RANGE
+            && miss < BOT_HIT_DISTANCE;
+    }
+}
+
+// Starts the demo once the main menu has been left alone long enough
+pub fn start_attract_mode(
+    mut commands: Commands,
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    mut ev_mouse_motion: EventReader<MouseMotion>,
+    settings_menu_state: Res<State<SettingsMenuState>>,
+    mut idle_timer: ResMut<MenuIdleTimer>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let moved = ev_mouse_motion.read().count() > 0;
+    if moved
+        || keys.get_pressed().next().is_some()
+        || buttons.get_pressed().next().is_some()
+        || *settings_menu_state.get() == SettingsMenuState::Open
+    {
+        idle_timer.0.reset();
+        return;
+    }
+
+    if idle_timer.0.tick(time.delta()).just_finished() {
+        commands.insert_resource(AttractMode);
+        next_state.set(GameState::Playing);
+    }
+}
+
+// Any key or click ends the demo
+pub fn stop_attract_mode(
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keys.get_just_pressed().next().is_some() || buttons.get_just_pressed().next().is_some() {
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+// The demo goes back to the main menu instead of showing the game over screen
+pub fn end_attract_mode(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::MainMenu);
+}
+
+// HELPER FUNCTIONS
+
+// Asteroid that will come within DANGER_RADIUS of the position soonest, or the closest one if none will, assuming the
+// asteroids keep their velocities
+pub fn most_threatening_asteroid(
+    position: Vec3,
+    asteroids: impl Iterator<Item = (Vec3, Vec3)>,
+) -> Option<(Vec3, Vec3)> {
+    asteroids
+        .map(|(asteroid_position, velocity)| {
+            let offset = asteroid_position - position;
+            let speed_squared = velocity.length_squared();
+            let closest_time = if speed_squared > 0.0 {
+                (-offset.dot(velocity) / speed_squared).clamp(0.0, THREAT_HORIZON)
+            } else {
+                0.0
+            };
+            let closest_distance = (offset + velocity * closest_time).length();
+
+            // Asteroids coming close are ordered by when, the others by how close they are
+            let priority = if closest_distance < DANGER_RADIUS {
+                (0, closest_time)
+            } else {
+                (1, offset.length())
+            };
+            (priority, asteroid_position, velocity)
+        })
+        .min_by(|(a, _, _), (b, _, _)| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))
+        .map(|(_, asteroid_position, velocity)| (asteroid_position, velocity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{
+        ecs::event::{Events, ManualEventReader},
+        prelude::{GlobalTransform, Vec3},
+        utils::HashSet,
+    };
+    use std::time::Duration;
+
+    use crate::{
+        asteroids::{AsteroidSpawnTimer, AsteroidWave},
+        cannon_ball::CannonBall,
+        common::{AsteroidDestroyedEvent, Score},
+        headless::{headless_app, is_game_over},
+    };
+
+    const ROUND_TICKS: usize = 60 * 60 * 5;
+    const ASTEROID_SPAWN_INTERVAL: Duration = Duration::from_millis(500);
+
+    fn bot_settings() -> Settings {
+        Settings {
+            difficulty: Difficulty::Hard,
+            bots: 2,
+            bot_difficulty: Difficulty::Hard,
+            ..Settings::default()
+        }
+    }
+
+    // Headless round with asteroids coming much faster than usual
+    fn soak_app(seed: u64) -> bevy::prelude::App {
+        let mut app = headless_app(bot_settings(), seed);
+        app.update();
+        app.world
+            .resource_mut::<AsteroidSpawnTimer>()
+            .0
+            .set_duration(ASTEROID_SPAWN_INTERVAL);
+        app
+    }
+
+    fn player_positions(app: &mut bevy::prelude::App) -> Vec<Vec3> {
+        let mut players = app
+            .world
+            .query_filtered::<(&PlayerId, &Transform), With<PlayerCollider>>()
+            .iter(&app.world)
+            .map(|(player_id, transform)| (player_id.0, transform.translation))
+            .collect::<Vec<_>>();
+        players.sort_by_key(|(id, _)| *id);
+        players.into_iter().map(|(_, position)| position).collect()
+    }
+
+    // Rounds are played at the default settings, where an asteroid comes every 50 seconds
+    #[test]
+    fn bots_play_headless_rounds() {
+        let mut shots = HashSet::new();
+        let mut spawned = 0;
+        let mut destroyed = 0;
+
+        for seed in 0..3 {
+            let mut app = headless_app(Settings::default(), seed);
+            let mut reader = ManualEventReader::<AsteroidDestroyedEvent>::default();
+            for _ in 0..ROUND_TICKS {
+                if is_game_over(&app) {
+                    break;
+                }
+                app.update();
+
+                // Only asteroids hit by cannon balls score points
+                destroyed += reader
+                    .read(app.world.resource::<Events<AsteroidDestroyedEvent>>())
+                    .filter(|ev| ev.points > 0)
+                    .count();
+
+                shots.extend(
+                    app.world
+                        .query_filtered::<bevy::prelude::Entity, With<CannonBall>>()
+                        .iter(&app.world)
+                        .map(|entity| (seed, entity)),
+                );
+                assert!(app
+                    .world
+                    .query::<&GlobalTransform>()
+                    .iter(&app.world)
+                    .all(|transform| transform.translation().is_finite()));
+            }
+            spawned += app.world.resource::<AsteroidWave>().0;
+        }
+
+        assert!(!shots.is_empty(), "the bots never fired");
+        assert!(
+            destroyed > 0,
+            "the bots destroyed none of the {} asteroids",
+            spawned
+        );
+    }
+
+    #[test]
+    fn seeded_headless_rounds_play_out_the_same() {
+        let mut apps = [soak_app(7), soak_app(7)];
+        for _ in 0..600 {
+            for app in apps.iter_mut() {
+                app.update();
+            }
+        }
+
+        let [first, second] = &mut apps;
+        assert_eq!(player_positions(first), player_positions(second));
+        assert_eq!(
+            first.world.resource::<Score>().0,
+            second.world.resource::<Score>().0
+        );
+    }
+
+    #[test]
+    fn bots_target_the_asteroid_coming_closest_soonest() {
+        let position = Vec3::new(0.0, 21.0, 0.0);
+        let far_and_passing = (Vec3::new(10.0, 21.0, 0.0), Vec3::new(0.0, 0.0, 5.0));
+        let near_and_passing = (Vec3::new(8.0, 21.0, 0.0), Vec3::new(0.0, 0.0, 5.0));
+        let incoming = (Vec3::new(20.0, 21.0, 0.0), Vec3::new(-10.0, 0.0, 0.0));
+
+        assert_eq!(
+            most_threatening_asteroid(position, [far_and_passing, near_and_passing].into_iter()),
+            Some(near_and_passing)
+        );
+        assert_eq!(
+            most_threatening_asteroid(
+                position,
+                [far_and_passing, near_and_passing, incoming].into_iter()
+            ),
+            Some(incoming)
+        );
+        assert_eq!(
+            most_threatening_asteroid(position, std::iter::empty()),
+            None
+        );
+    }
+}
@@ -0,0 +1,394 @@
+// Camera controller with follow, orbit (free look) and cinematic modes, mouse wheel zoom and screen shake.
+// The controller keeps the smoothed camera position separately from the transform so that the shake offset
+// is applied on top of it every frame instead of accumulating. The camera's up direction comes from a
+// spherical frame that is parallel transported as the camera moves, so it doesn't roll or flip over the poles.
+// Every local player has their own camera, side by side in the window when there is more than one.
+
+use bevy::{
+    audio::SpatialListener,
+    core_pipeline::clear_color::ClearColorConfig,
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::{
+        default, Camera, Camera2d, Camera2dBundle, Camera3dBundle, Commands, Component,
+        EventReader, Input, KeyCode, MouseButton, Name, Quat, Query, Res, Time, Transform, UVec2,
+        Vec3, With, Without,
+    },
+    render::camera::Viewport,
+    ui::camera_config::UiCameraConfig,
+    window::{PrimaryWindow, Window},
+};
+use std::f32::consts::PI;
+
+use crate::{
+    audio::LISTENER_EAR_GAP,
+    client::{local_player_count, NetworkClient},
+    common::{AsteroidDestroyedEvent, PrimaryCamera, UiCamera, CAMERA_DISTANCE, PLANET_SIZE},
+    input::ShootEvent,
+    planet::PlanetShape,
+    player::{PlayerCollider, PlayerId},
+    rollback::RollbackSession,
+    settings::Settings,
+    spherical_frame::SphericalFrame,
+};
+
+// CONSTANTS
+
+pub const MIN_CAMERA_DISTANCE: f32 = PLANET_SIZE * 1.75;
+pub const MAX_CAMERA_DISTANCE: f32 = PLANET_SIZE * 5.0;
+const ZOOM_STEP: f32 = 4.0; // Distance per mouse wheel line
+const ZOOM_PIXELS_PER_LINE: f32 = 40.0;
+const ORBIT_SPEED: f32 = 0.005; // Radians per pixel of mouse movement at a mouse sensitivity of 1
+const CINEMATIC_SPEED: f32 = 0.15; // Radians per second
+const CINEMATIC_ELEVATION: f32 = PI / 5.0; // Angle between the player's up direction and the camera
+const SMOOTHING_REFERENCE_FPS: f32 = 60.0;
+const SHOOT_TRAUMA: f32 = 0.25;
+const HIT_TRAUMA: f32 = 0.5;
+const HIT_TRAUMA_RADIUS: f32 = PLANET_SIZE; // Hits further than this from the player don't shake the camera
+const TRAUMA_DECAY: f32 = 1.2; // Trauma lost per second
+const MAX_SHAKE_OFFSET: f32 = 1.5;
+const MAX_SHAKE_ROLL: f32 = 0.05; // Radians
+const SHAKE_FREQUENCY: f32 = 25.0;
+
+// COMPONENTS
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CameraMode {
+    #[default]
+    Follow,
+    Orbit,
+    Cinematic,
+}
+
+#[derive(Component)]
+pub struct CameraController {
+    pub mode: CameraMode,
+    pub distance: f32, // Distance from the centre of the planet
+    pub target_distance: f32,
+    pub orbit_direction: Vec3, // Direction from the centre of the planet in orbit mode
+    pub cinematic_angle: f32,
+    pub translation: Vec3,     // Smoothed translation, without shake
+    pub frame: SphericalFrame, // Frame at the camera, or at the player in cinematic mode
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::Follow,
+            distance: CAMERA_DISTANCE,
+            target_distance: CAMERA_DISTANCE,
+            orbit_direction: Vec3::Z,
+            cinematic_angle: 0.0,
+            translation: Vec3::Z * CAMERA_DISTANCE,
+            frame: SphericalFrame::default(),
+        }
+    }
+}
+
+// Trauma is added by shots and hits, the camera shakes by the square of the trauma
+#[derive(Component, Default)]
+pub struct CameraShake {
+    pub trauma: f32,
+}
+
+// STARTUP SYSTEMS
+
+// One camera per player, the first player's camera is the primary camera and the audio listener
+pub fn setup_camera(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    network_client: Option<Res<NetworkClient>>,
+    rollback_session: Option<Res<RollbackSession>>,
+) {
+    let networked = network_client.is_some() || rollback_session.is_some();
+    let players = local_player_count(&settings, networked);
+    let split_screen = players > 1;
+
+    for index in 0..players {
+        let player_id = PlayerId(index as usize);
+
+        // Scene Camera
+        let mut camera = commands.spawn((Camera3dBundle {
+            camera: Camera {
+                order: 10 + index as isize,
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, CAMERA_DISTANCE)
+                .looking_at(Vec3::ZERO, Vec3::Y),
+            ..default()
+        },));
+        camera
+            .insert(Name::new(format!("Camera_{}", player_id.label())))
+            .insert(player_id)
+            .insert(CameraController::default())
+            .insert(CameraShake::default());
+
+        if player_id == PlayerId::FIRST {
+            camera
+                .insert(PrimaryCamera {})
+                .insert(SpatialListener::new(LISTENER_EAR_GAP));
+        }
+
+        // The UI is drawn by the overlay camera so it isn't squeezed into each viewport
+        if split_screen {
+            camera.insert(UiCameraConfig { show_ui: false });
+        }
+    }
+
+    if split_screen {
+        commands
+            .spawn(Camera2dBundle {
+                camera: Camera {
+                    order: 20,
+                    ..default()
+                },
+                camera_2d: Camera2d {
+                    clear_color: ClearColorConfig::None,
+                },
+                ..default()
+            })
+            .insert(Name::new("UI_Camera"))
+            .insert(UiCamera {});
+    }
+}
+
+// SYSTEMS
+
+// C cycles the camera mode, dragging with the right mouse button orbits in orbit mode and the mouse wheel zooms
+pub fn handle_camera_input(
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    settings: Res<Settings>,
+    mut ev_mouse_motion: EventReader<MouseMotion>,
+    mut ev_mouse_wheel: EventReader<MouseWheel>,
+    mut camera_query: Query<&mut CameraController, With<PrimaryCamera>>,
+) {
+    let Ok(mut controller) = camera_query.get_single_mut() else {
+        ev_mouse_motion.clear();
+        ev_mouse_wheel.clear();
+        return;
+    };
+
+    if keys.just_pressed(KeyCode::C) {
+        controller.mode = match controller.mode {
+            CameraMode::Follow => CameraMode::Orbit,
+            CameraMode::Orbit => CameraMode::Cinematic,
+            CameraMode::Cinematic => CameraMode::Follow,
+        };
+
+        // Start orbiting from wherever the camera currently is
+        controller.orbit_direction = controller.translation.normalize();
+    }
+
+    for ev in ev_mouse_wheel.read() {
+        let lines = match ev.unit {
+            MouseScrollUnit::Line => ev.y,
+            MouseScrollUnit::Pixel => ev.y / ZOOM_PIXELS_PER_LINE,
+        };
+        controller.target_distance = (controller.target_distance - lines * ZOOM_STEP)
+            .clamp(MIN_CAMERA_DISTANCE, MAX_CAMERA_DISTANCE);
+    }
+
+    let dragging = controller.mode == CameraMode::Orbit && buttons.pressed(MouseButton::Right);
+    for ev in ev_mouse_motion.read() {
+        if !dragging {
+            continue;
+        }
+
+        // Rotate around the camera's up and right axes so dragging moves the planet under the cursor,
+        // the frame is transported along when the camera moves
+        let speed = ORBIT_SPEED * settings.mouse_sensitivity;
+        let rotation = Quat::from_axis_angle(controller.frame.forward, -ev.delta.x * speed)
+            * Quat::from_axis_angle(controller.frame.right(), -ev.delta.y * speed);
+
+        controller.orbit_direction = (rotation * controller.orbit_direction).normalize();
+    }
+}
+
+// Splits the window into side by side viewports for the players' cameras when there is more than one
+pub fn update_camera_viewports(
+    primary_window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<(&mut Camera, &PlayerId), With<CameraController>>,
+) {
+    let Ok(window) = primary_window_query.get_single() else {
+        return;
+    };
+
+    let players = camera_query.iter().len() as u32;
+    if players <= 1 {
+        return;
+    }
+
+    let width = window.physical_width() / players;
+    let height = window.physical_height();
+    for (mut camera, player_id) in camera_query.iter_mut() {
+        let viewport = Viewport {
+            physical_position: UVec2::new(width * player_id.0 as u32, 0),
+            physical_size: UVec2::new(width.max(1), height.max(1)),
+            ..default()
+        };
+
+        // Only touch the camera when the window's size has changed
+        let unchanged = camera.viewport.as_ref().is_some_and(|current| {
+            current.physical_position == viewport.physical_position
+                && current.physical_size == viewport.physical_size
+        });
+        if !unchanged {
+            camera.viewport = Some(viewport);
+        }
+    }
+}
+
+// Adds trauma to a player's camera when they shoot a cannon ball or an asteroid is destroyed near them
+pub fn add_camera_trauma(
+    mut ev_shoot: EventReader<ShootEvent>,
+    mut ev_asteroid_destroyed: EventReader<AsteroidDestroyedEvent>,
+    player_query: Query<(&PlayerId, &Transform), With<PlayerCollider>>,
+    mut camera_query: Query<(&PlayerId, &mut CameraShake)>,
+) {
+    let shooters = ev_shoot
+        .read()
+        .filter_map(|ev| player_query.get(ev.shooter).ok())
+        .map(|(player_id, _)| *player_id)
+        .collect::<Vec<_>>();
+    let destroyed_positions = ev_asteroid_destroyed
+        .read()
+        .map(|ev| ev.position)
+        .collect::<Vec<_>>();
+
+    for (player_id, player_transform) in player_query.iter() {
+        let Some((_, mut shake)) = camera_query
+            .iter_mut()
+            .find(|(camera_player_id, _)| *camera_player_id == player_id)
+        else {
+            continue;
+        };
+
+        for _ in shooters.iter().filter(|shooter| *shooter == player_id) {
+            shake.trauma += SHOOT_TRAUMA;
+        }
+
+        for position in destroyed_positions.iter() {
+            let falloff = 1.0 - position.distance(player_transform.translation) / HIT_TRAUMA_RADIUS;
+            shake.trauma += HIT_TRAUMA * falloff.max(0.0);
+        }
+
+        shake.trauma = shake.trauma.min(1.0);
+    }
+}
+
+// Moves every player's camera according to its mode and applies the screen shake
+pub fn move_camera(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    planet_shape: Res<PlanetShape>,
+    mut camera_query: Query<(
+        &PlayerId,
+        &mut Transform,
+        &mut CameraController,
+        &mut CameraShake,
+    )>,
+    player_query: Query<(&PlayerId, &Transform), (With<PlayerCollider>, Without<CameraController>)>,
+) {
+    for (camera_player_id, mut camera_transform, mut controller, mut shake) in
+        camera_query.iter_mut()
+    {
+        let Some((_, player_transform)) = player_query
+            .iter()
+            .find(|(player_id, _)| *player_id == camera_player_id)
+        else {
+            continue;
+        };
+
+        move_player_camera(
+            &time,
+            &settings,
+            &planet_shape,
+            player_transform,
+            &mut camera_transform,
+            &mut controller,
+            &mut shake,
+        );
+    }
+}
+
+// HELPER FUNCTIONS
+
+// Moves a camera towards its target for the player it follows and shakes it by its trauma
+fn move_player_camera(
+    time: &Time,
+    settings: &Settings,
+    planet_shape: &PlanetShape,
+    player_transform: &Transform,
+    camera_transform: &mut Transform,
+    controller: &mut CameraController,
+    shake: &mut CameraShake,
+) {
+    let delta_seconds = time.delta_seconds();
+
+    // Frame rate independent version of lerping by the smoothing factor every frame at 60 fps
+    let smoothing = 1.0
+        - (1.0 - settings.camera_smoothing.min(1.0)).powf(delta_seconds * SMOOTHING_REFERENCE_FPS);
+
+    controller.distance += (controller.target_distance - controller.distance) * smoothing;
+
+    let player_direction = player_transform.translation.normalize();
+    let (target_translation, look_target) = match controller.mode {
+        CameraMode::Follow => (player_direction * controller.distance, Vec3::ZERO),
+        CameraMode::Orbit => (controller.orbit_direction * controller.distance, Vec3::ZERO),
+        CameraMode::Cinematic => {
+            controller.cinematic_angle += CINEMATIC_SPEED * delta_seconds;
+
+            // Circle around the player, looking down at them at an angle, the angle is measured from the
+            // player's frame so it only changes as much as the player moves
+            controller.frame = controller.frame.transported(player_direction);
+            let side = controller.frame.right();
+            let around = Quat::from_axis_angle(player_direction, controller.cinematic_angle);
+            let offset_direction = player_direction * CINEMATIC_ELEVATION.cos()
+                + (around * side) * CINEMATIC_ELEVATION.sin();
+            let player_surface = planet_shape.surface_point(player_direction);
+
+            (
+                player_surface + offset_direction * (controller.distance - player_surface.length()),
+                player_surface,
+            )
+        }
+    };
+
+    if controller.translation.distance(target_translation) > 0.01 {
+        controller.translation = controller.translation.lerp(target_translation, smoothing);
+    }
+
+    let base_transform = match controller.mode {
+        CameraMode::Follow | CameraMode::Orbit => {
+            controller.frame = controller.frame.transported(controller.translation);
+            Transform::from_translation(controller.translation)
+                .with_rotation(controller.frame.camera_rotation())
+        }
+        CameraMode::Cinematic => Transform::from_translation(controller.translation)
+            .looking_at(look_target, player_direction),
+    };
+
+    // Shake by the square of the trauma so small amounts of trauma are subtle
+    shake.trauma = (shake.trauma - TRAUMA_DECAY * delta_seconds).max(0.0);
+    let intensity = shake.trauma * shake.trauma;
+    let t = time.elapsed_seconds() * SHAKE_FREQUENCY;
+    let offset = (base_transform.right() * shake_noise(t, 0.0)
+        + base_transform.up() * shake_noise(t, 10.0))
+        * MAX_SHAKE_OFFSET
+        * intensity;
+    let roll = shake_noise(t, 20.0) * MAX_SHAKE_ROLL * intensity;
+
+    *camera_transform = Transform {
+        translation: base_transform.translation + offset,
+        rotation: base_transform.rotation * Quat::from_rotation_z(roll),
+        ..base_transform
+    };
+}
+
+// Smooth pseudo random value between -1 and 1, different seeds give uncorrelated values
+fn shake_noise(t: f32, seed: f32) -> f32 {
+    ((t + seed).sin() * 0.5
+        + (t * 1.7 + seed * 3.1).sin() * 0.3
+        + (t * 3.3 + seed * 7.3).sin() * 0.2)
+        .clamp(-1.0, 1.0)
+}
@@ -1,5 +1,5 @@
 use bevy::prelude::{
-    default, shape, Assets, Color, Commands, Component, EventReader, Mesh, PbrBundle, ResMut,
+    default, shape, Color, Commands, Component, Entity, EventReader, Mesh, PbrBundle, Res,
     StandardMaterial, Transform, Vec3,
 };
 use bevy_rapier3d::prelude::{
@@ -7,55 +7,53 @@ use bevy_rapier3d::prelude::{
     ExternalImpulse, Friction, GravityScale, Restitution, RigidBody,
 };
 
-use crate::{input::ShootEvent, player::PLAYER_IMPULSE_MAGNITUDE, player::PLAYER_SIZE};
+use crate::{
+    game_assets::GameAssets, input::ShootEvent, player::PLAYER_IMPULSE_MAGNITUDE,
+    player::PLAYER_SIZE,
+};
 
 // CONSTANTS
 
 pub const CANNON_BALL_INITIAL_OFFSET: f32 = 3.0;
+pub const CANNON_BALL_RADIUS: f32 = PLAYER_SIZE;
+pub const CANNON_BALL_DENSITY: f32 = 1.0;
+pub const CANNON_BALL_LINEAR_DAMPING: f32 = 0.1;
+pub const CANNON_BALL_ANGULAR_DAMPING: f32 = 0.2;
 
 // COMPONENTS
 
 #[derive(Component)]
-pub struct CannonBall {}
+pub struct CannonBall {
+    pub shooter: Entity, // Player that shot the cannon ball
+}
 
 // SYSTEMS
 
 // Spawns and shoots a cannon ball when a ShootEvent is triggered
 pub fn shoot_cannon_ball(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    game_assets: Res<GameAssets>,
     mut ev_shoot: EventReader<ShootEvent>,
 ) {
     for ev in ev_shoot.read() {
         commands
             .spawn(PbrBundle {
-                mesh: meshes.add(
-                    shape::Icosphere {
-                        radius: PLAYER_SIZE / 2.0,
-                        subdivisions: 16,
-                    }
-                    .try_into()
-                    .unwrap(),
-                ),
-                material: materials.add(StandardMaterial {
-                    base_color: Color::rgb(0.3, 0.3, 0.3),
-                    perceptual_roughness: 0.3,
-                    metallic: 0.8,
-                    ..default()
-                }),
+                mesh: game_assets.cannon_ball_mesh.clone(),
+                material: game_assets.cannon_ball_material.clone(),
                 transform: Transform::from_translation(ev.position),
                 ..default()
             })
-            .insert(CannonBall {})
-            .insert(Collider::ball(PLAYER_SIZE))
+            .insert(CannonBall {
+                shooter: ev.shooter,
+            })
+            .insert(Collider::ball(CANNON_BALL_RADIUS))
             .insert(ActiveEvents::COLLISION_EVENTS)
             .insert(RigidBody::Dynamic)
             .insert(Damping {
-                linear_damping: 0.1,
-                angular_damping: 0.2,
+                linear_damping: CANNON_BALL_LINEAR_DAMPING,
+                angular_damping: CANNON_BALL_ANGULAR_DAMPING,
             })
-            .insert(ColliderMassProperties::Density(1.0))
+            .insert(ColliderMassProperties::Density(CANNON_BALL_DENSITY))
             .insert(GravityScale(0.0))
             .insert(Friction {
                 coefficient: 2.0,
@@ -75,3 +73,24 @@ pub fn shoot_cannon_ball(
             });
     }
 }
+
+// HELPER FUNCTIONS
+
+// Mesh shared by every cannon ball
+pub fn cannon_ball_mesh() -> Mesh {
+    shape::Icosphere {
+        radius: PLAYER_SIZE / 2.0,
+        subdivisions: 16,
+    }
+    .try_into()
+    .unwrap()
+}
+
+pub fn cannon_ball_material() -> StandardMaterial {
+    StandardMaterial {
+        base_color: Color::rgb(0.3, 0.3, 0.3),
+        perceptual_roughness: 0.3,
+        metallic: 0.8,
+        ..default()
+    }
+}
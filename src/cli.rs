@@ -0,0 +1,182 @@
+// Command line flags of the game binary.
+// Flags that need a value take the next argument, so `--seed 7` and `--window 1920x1080`. Unknown flags and bad values
+// are reported with the usage instead of being ignored, so a typo doesn't silently start a normal game.
+
+use bevy::log::Level;
+
+// CONSTANTS
+
+pub const USAGE: &str = "\
+Usage: loose-cannon [options]
+  --seed <seed>              Play every round with this seed
+  --config <file>            Load and save the settings in this file instead of settings.ron
+  --headless                 Play without a window, with bots for every player, and print a JSON summary
+  --ticks <ticks>            Stop after this many ticks of play
+  --record <file>            Record the first round's inputs to this file
+  --replay <file>            Play back a recorded round
+  --window <width>x<height>  Window resolution
+  --fullscreen               Start in fullscreen
+  --no-editor                Skip the editor in debug builds
+  --log-level <level>        One of error, warn, info, debug or trace
+  --connect <address>        Join a dedicated server
+  --host-duel [port]         Host a rollback duel
+  --join-duel <address>      Join a rollback duel
+  --help                     Show this message";
+
+// HELPER FUNCTIONS
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct CliArgs {
+    pub seed: Option<u64>,
+    pub config: Option<String>,
+    pub headless: bool,
+    pub ticks: Option<u32>,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    pub window: Option<(u32, u32)>,
+    pub fullscreen: bool,
+    pub no_editor: bool,
+    pub log_level: Option<Level>,
+    pub connect: Option<String>,
+    pub host_duel: Option<Option<u16>>, // The port is optional
+    pub join_duel: Option<String>,
+    pub help: bool,
+}
+
+impl CliArgs {
+    // Parses the arguments after the program's name
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut cli_args = Self::default();
+        let mut args = args.into_iter().peekable();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+
+            match arg.as_str() {
+                "--seed" => cli_args.seed = Some(parse_value("--seed", &value()?)?),
+                "--config" => cli_args.config = Some(value()?),
+                "--headless" => cli_args.headless = true,
+                "--ticks" => cli_args.ticks = Some(parse_value("--ticks", &value()?)?),
+                "--record" => cli_args.record = Some(value()?),
+                "--replay" => cli_args.replay = Some(value()?),
+                "--window" => cli_args.window = Some(parse_resolution(&value()?)?),
+                "--fullscreen" => cli_args.fullscreen = true,
+                "--no-editor" => cli_args.no_editor = true,
+                "--log-level" => cli_args.log_level = Some(parse_value("--log-level", &value()?)?),
+                "--connect" => cli_args.connect = Some(value()?),
+                "--host-duel" => {
+                    let port = args.next_if(|port| !port.starts_with("--"));
+                    cli_args.host_duel = Some(
+                        port.map(|port| parse_value("--host-duel", &port))
+                            .transpose()?,
+                    );
+                }
+                "--join-duel" => cli_args.join_duel = Some(value()?),
+                "--help" | "-h" => cli_args.help = true,
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+
+        if cli_args.record.is_some() && cli_args.replay.is_some() {
+            return Err("--record and --replay can't be used together".to_string());
+        }
+        let networked = cli_args.connect.is_some()
+            || cli_args.host_duel.is_some()
+            || cli_args.join_duel.is_some();
+        if networked
+            && (cli_args.headless || cli_args.record.is_some() || cli_args.replay.is_some())
+        {
+            return Err("Networked games can't be headless, recorded or replayed".to_string());
+        }
+
+        Ok(cli_args)
+    }
+}
+
+// Also used by the other binaries' flags
+pub fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", name, value))
+}
+
+// Parses a resolution like 1280x720
+fn parse_resolution(value: &str) -> Result<(u32, u32), String> {
+    value
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .ok_or_else(|| format!("Invalid value for --window: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<CliArgs, String> {
+        CliArgs::parse(args.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn parses_flags_and_values() {
+        assert_eq!(parse(""), Ok(CliArgs::default()));
+        assert_eq!(
+            parse("--headless --ticks 600 --seed 42 --record run.ron --log-level debug"),
+            Ok(CliArgs {
+                headless: true,
+                ticks: Some(600),
+                seed: Some(42),
+                record: Some("run.ron".to_string()),
+                log_level: Some(Level::DEBUG),
+                ..CliArgs::default()
+            })
+        );
+        assert_eq!(
+            parse("--window 1920x1080 --fullscreen --no-editor --config test.ron"),
+            Ok(CliArgs {
+                window: Some((1920, 1080)),
+                fullscreen: true,
+                no_editor: true,
+                config: Some("test.ron".to_string()),
+                ..CliArgs::default()
+            })
+        );
+    }
+
+    #[test]
+    fn the_duel_port_is_optional() {
+        assert_eq!(parse("--host-duel").unwrap().host_duel, Some(None));
+        assert_eq!(
+            parse("--host-duel 7000").unwrap().host_duel,
+            Some(Some(7000))
+        );
+        assert_eq!(
+            parse("--host-duel --no-editor").unwrap(),
+            CliArgs {
+                host_duel: Some(None),
+                no_editor: true,
+                ..CliArgs::default()
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        for args in [
+            "--seed",
+            "--seed abc",
+            "--ticks -1",
+            "--window 1920",
+            "--window 0x720",
+            "--log-level loud",
+            "--frobnicate",
+            "--record a.ron --replay b.ron",
+            "--connect 127.0.0.1:5000 --headless",
+        ] {
+            assert!(parse(args).is_err(), "{} was accepted", args);
+        }
+    }
+}
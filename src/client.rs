@@ -0,0 +1,572 @@
+// Client side of networked games, enabled by connecting to a dedicated server.
+// Instead of simulating the world, the client mirrors the server's replicated entities, drawn INTERPOLATION_DELAY behind
+// the latest snapshot so they move smoothly between snapshots, and sends the local player's aim and shots to the server.
+
+use bevy::{
+    app::AppExit,
+    log::{info, warn},
+    prelude::{
+        in_state, resource_exists, Added, App, Commands, Component, DespawnRecursiveExt, Entity,
+        Event, EventReader, EventWriter, IntoSystemConfigs, Name, Plugin, Query, Res, ResMut,
+        Resource, Transform, Update, Vec3, VisibilityBundle, With, Without,
+    },
+    time::{Time, Timer, TimerMode},
+    transform::TransformBundle,
+};
+use bevy_rapier3d::prelude::Velocity;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+};
+
+use crate::{
+    asteroids::{Asteroid, AsteroidWave},
+    cannon_ball::CannonBall,
+    common::{AsteroidDestroyedEvent, GameState, Score},
+    game_assets::GameAssets,
+    input::{InputDevice, PlayerInput, ShootEvent, ShootTimer},
+    network::{
+        ClientInput, ClientMessage, EntityState, NetworkId, NetworkKind, NetworkSocket,
+        PlayerState, ServerMessage, Snapshot, CONNECT_RETRY_DELAY, DEFAULT_SERVER_PORT,
+        INTERPOLATION_DELAY, MAX_EXTRAPOLATION, PROTOCOL_VERSION, SNAPSHOT_HISTORY, TICK_RATE,
+    },
+    planet::PlanetShape,
+    player::{
+        spawn_player_mesh, PlayerCollider, PlayerId, PlayerMesh, PlayerMeshDesiredTransform,
+        PlayerScore, FIRE_DELAY,
+    },
+    settings::Settings,
+};
+
+// COMPONENTS
+
+// Replicated player, with the player's latest state from the server
+#[derive(Component)]
+pub struct NetworkPlayer(pub PlayerState);
+
+// RESOURCES
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Connection {
+    Connecting,
+    Connected { slot: u8, level: String },
+    Rejected { reason: String },
+}
+
+#[derive(Resource)]
+pub struct NetworkClient {
+    pub socket: NetworkSocket,
+    pub server: SocketAddr,
+    pub connection: Connection,
+    pub input: ClientInput, // Local player's input, sent every frame
+    pub snapshots: VecDeque<Snapshot>, // Received snapshots, oldest first
+    pub render_tick: Option<f64>, // Server tick the replicated entities are drawn at
+    last_connect_attempt: Option<f64>,
+}
+
+// EVENTS
+
+// A replicated entity was removed
+#[derive(Event)]
+pub struct ReplicaDespawnedEvent {
+    pub kind: NetworkKind,
+    pub position: Vec3,
+}
+
+impl NetworkClient {
+    // Binds a socket to talk to the server, the address can leave out the port to use the default one
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let server = address
+            .to_socket_addrs()
+            .or_else(|_| (address, DEFAULT_SERVER_PORT).to_socket_addrs())?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No server address found"))?;
+        let socket = if server.is_ipv4() {
+            NetworkSocket::bind("0.0.0.0:0")?
+        } else {
+            NetworkSocket::bind("[::]:0")?
+        };
+
+        Ok(Self {
+            socket,
+            server,
+            connection: Connection::Connecting,
+            input: ClientInput::default(),
+            snapshots: VecDeque::new(),
+            render_tick: None,
+            last_connect_attempt: None,
+        })
+    }
+
+    // Slot of the local player once connected
+    pub fn slot(&self) -> Option<u8> {
+        match self.connection {
+            Connection::Connected { slot, .. } => Some(slot),
+            _ => None,
+        }
+    }
+
+    // Level the server is playing once connected
+    pub fn level(&self) -> Option<&str> {
+        match &self.connection {
+            Connection::Connected { level, .. } => Some(level),
+            _ => None,
+        }
+    }
+
+    pub fn latest_snapshot(&self) -> Option<&Snapshot> {
+        self.snapshots.back()
+    }
+
+    // Replicated entity states at the render tick
+    pub fn interpolated_states(&self) -> BTreeMap<u32, EntityState> {
+        let Some(render_tick) = self.render_tick else {
+            return BTreeMap::new();
+        };
+
+        let next_index = self
+            .snapshots
+            .iter()
+            .position(|snapshot| snapshot.tick as f64 > render_tick);
+        match next_index {
+            // Between two snapshots
+            Some(next_index) if next_index > 0 => {
+                let previous = &self.snapshots[next_index - 1];
+                let next = &self.snapshots[next_index];
+                let t = (render_tick - previous.tick as f64) / (next.tick - previous.tick) as f64;
+                previous.interpolate(next, t as f32)
+            }
+            // Before the oldest snapshot
+            Some(next_index) => self.snapshots[next_index].entities.clone(),
+            // After the latest snapshot, keep moving for a bit in case the next one is only late
+            None => {
+                let Some(latest) = self.snapshots.back() else {
+                    return BTreeMap::new();
+                };
+                let seconds = ((render_tick - latest.tick as f64) / TICK_RATE)
+                    .clamp(0.0, MAX_EXTRAPOLATION) as f32;
+                latest
+                    .entities
+                    .iter()
+                    .map(|(id, state)| (*id, state.extrapolate(seconds)))
+                    .collect()
+            }
+        }
+    }
+
+    // Keeps the snapshot if it's newer than the ones received so far
+    fn add_snapshot(&mut self, snapshot: Snapshot) -> bool {
+        if self
+            .latest_snapshot()
+            .is_some_and(|latest| latest.tick >= snapshot.tick)
+        {
+            return false;
+        }
+
+        // Jump straight to the new snapshot when the render tick has drifted too far from it
+        let delay = INTERPOLATION_DELAY * TICK_RATE;
+        let target = snapshot.tick as f64 - delay;
+        if self
+            .render_tick
+            .is_none_or(|render_tick| (render_tick - target).abs() > delay)
+        {
+            self.render_tick = Some(target);
+        }
+
+        self.snapshots.push_back(snapshot);
+        if self.snapshots.len() > SNAPSHOT_HISTORY {
+            self.snapshots.pop_front();
+        }
+        true
+    }
+}
+
+// PLUGINS
+
+// Connects to the server and replicates its entities while playing when there is a NetworkClient resource
+pub struct NetworkClientPlugin;
+
+impl Plugin for NetworkClientPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ReplicaDespawnedEvent>().add_systems(
+            Update,
+            (
+                connect_to_server,
+                receive_server_messages,
+                advance_render_tick,
+                replicate_snapshots.run_if(in_state(GameState::Playing)),
+                send_client_input,
+                disconnect_on_exit,
+            )
+                .chain()
+                .run_if(resource_exists::<NetworkClient>()),
+        );
+    }
+}
+
+// SYSTEMS
+
+// Asks to join until the server answers
+pub fn connect_to_server(mut client: ResMut<NetworkClient>, time: Res<Time>) {
+    if client.connection != Connection::Connecting {
+        return;
+    }
+
+    let now = time.elapsed_seconds_f64();
+    if client
+        .last_connect_attempt
+        .is_some_and(|last_attempt| now - last_attempt < CONNECT_RETRY_DELAY)
+    {
+        return;
+    }
+
+    client.last_connect_attempt = Some(now);
+    client.socket.send(
+        &ClientMessage::Connect {
+            version: PROTOCOL_VERSION,
+        },
+        client.server,
+    );
+}
+
+// Rebuilds the snapshots from the server's deltas and acknowledges the latest one
+pub fn receive_server_messages(mut client: ResMut<NetworkClient>) {
+    let server = client.server;
+    let mut acked_tick = None;
+
+    for (address, message) in client.socket.receive::<ServerMessage>() {
+        // Anyone can send packets to the socket
+        if address != server {
+            continue;
+        }
+
+        match message {
+            ServerMessage::Welcome { slot, level } => {
+                if client.connection == Connection::Connecting {
+                    info!(
+                        "Joined {} as {} playing {}",
+                        server,
+                        PlayerId(slot as usize).label(),
+                        level
+                    );
+                    client.connection = Connection::Connected { slot, level };
+                }
+            }
+            ServerMessage::Rejected { reason } => {
+                if client.connection == Connection::Connecting {
+                    warn!("{} refused to let us join: {}", server, reason);
+                    client.connection = Connection::Rejected { reason };
+                }
+            }
+            ServerMessage::Snapshot(delta) => {
+                // Deltas against a snapshot that's no longer kept are dropped, the server falls back to a full
+                // snapshot once it notices the acknowledgements don't move on
+                let baseline = delta.baseline.and_then(|baseline_tick| {
+                    client
+                        .snapshots
+                        .iter()
+                        .find(|snapshot| snapshot.tick == baseline_tick)
+                });
+                let Some(snapshot) = delta.apply(baseline) else {
+                    continue;
+                };
+
+                let tick = snapshot.tick;
+                if client.add_snapshot(snapshot) {
+                    acked_tick = Some(tick);
+                }
+            }
+        }
+    }
+
+    if let Some(tick) = acked_tick {
+        client.socket.send(&ClientMessage::Ack { tick }, server);
+    }
+}
+
+// Moves the render tick along at the server's tick rate
+pub fn advance_render_tick(mut client: ResMut<NetworkClient>, time: Res<Time>) {
+    if let Some(render_tick) = client.render_tick.as_mut() {
+        *render_tick += time.delta_seconds_f64() * TICK_RATE;
+    }
+}
+
+// Spawns, moves and despawns the replicated entities to match the snapshots at the render tick
+pub fn replicate_snapshots(
+    mut commands: Commands,
+    client: Res<NetworkClient>,
+    mut ev_replica_despawned: EventWriter<ReplicaDespawnedEvent>,
+    mut replica_query: Query<(
+        Entity,
+        &NetworkId,
+        &NetworkKind,
+        &mut Transform,
+        &mut Velocity,
+        Option<&mut NetworkPlayer>,
+    )>,
+) {
+    let mut states = client.interpolated_states();
+
+    for (entity, network_id, kind, mut transform, mut velocity, network_player) in
+        replica_query.iter_mut()
+    {
+        let Some(state) = states.remove(&network_id.0) else {
+            ev_replica_despawned.send(ReplicaDespawnedEvent {
+                kind: *kind,
+                position: transform.translation,
+            });
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        transform.translation = state.translation();
+        transform.rotation = state.rotation();
+        velocity.linvel = state.linvel();
+        velocity.angvel = Vec3::from_array(state.angvel);
+        if let (Some(mut network_player), Some(player_state)) = (network_player, state.player) {
+            network_player.0 = player_state;
+        }
+    }
+
+    // Whatever is left is new
+    for (id, state) in states {
+        let mut replica = commands.spawn(TransformBundle::from(
+            Transform::from_translation(state.translation()).with_rotation(state.rotation()),
+        ));
+        replica
+            .insert(Name::new(format!("Replica_{}", id)))
+            .insert(NetworkId(id))
+            .insert(state.kind)
+            .insert(Velocity {
+                linvel: state.linvel(),
+                angvel: Vec3::from_array(state.angvel),
+            });
+        if let Some(player_state) = state.player {
+            replica.insert(NetworkPlayer(player_state));
+        }
+    }
+}
+
+pub fn send_client_input(client: Res<NetworkClient>) {
+    if client.slot().is_some() {
+        client
+            .socket
+            .send(&ClientMessage::Input(client.input), client.server);
+    }
+}
+
+// Lets the server know right away instead of waiting for the client to time out
+pub fn disconnect_on_exit(client: Res<NetworkClient>, mut ev_app_exit: EventReader<AppExit>) {
+    if ev_app_exit.read().next().is_some() && client.slot().is_some() {
+        client
+            .socket
+            .send(&ClientMessage::Disconnect, client.server);
+    }
+}
+
+// Adds meshes to new replicated entities and turns replicated players into players, the local player gets the mouse
+pub fn attach_replica_visuals(
+    mut commands: Commands,
+    client: Res<NetworkClient>,
+    game_assets: Res<GameAssets>,
+    planet_shape: Res<PlanetShape>,
+    replica_query: Query<(Entity, &NetworkKind, Option<&NetworkPlayer>), Added<NetworkKind>>,
+) {
+    for (entity, kind, network_player) in replica_query.iter() {
+        match kind {
+            NetworkKind::Asteroid { variant } => {
+                let Some((mesh, _)) = game_assets.asteroid_variants.get(*variant as usize) else {
+                    continue;
+                };
+                let material = game_assets.asteroid_materials
+                    [*variant as usize % game_assets.asteroid_materials.len()]
+                .clone();
+                commands
+                    .entity(entity)
+                    .insert((mesh.clone(), material, VisibilityBundle::default()))
+                    .insert(Asteroid {});
+            }
+            NetworkKind::CannonBall => {
+                commands
+                    .entity(entity)
+                    .insert((
+                        game_assets.cannon_ball_mesh.clone(),
+                        game_assets.cannon_ball_material.clone(),
+                        VisibilityBundle::default(),
+                    ))
+                    .insert(CannonBall {
+                        shooter: Entity::PLACEHOLDER,
+                    });
+            }
+            NetworkKind::Player => {
+                let Some(NetworkPlayer(player_state)) = network_player else {
+                    continue;
+                };
+                let local = client.slot() == Some(player_state.slot);
+                let player_id = local_player_id(player_state.slot, client.slot());
+
+                spawn_player_mesh(&mut commands, &game_assets, player_id);
+
+                let mut player = commands.entity(entity);
+                player
+                    .insert(PlayerCollider {})
+                    .insert(player_id)
+                    .insert(PlayerScore(player_state.score))
+                    .insert(network_player_desired_transform(
+                        &planet_shape,
+                        player_state,
+                        Vec3::ZERO,
+                    ));
+
+                if local {
+                    // The server runs the same timer, this one only keeps the client from asking for shots it won't fire
+                    let mut timer = Timer::from_seconds(FIRE_DELAY, TimerMode::Once);
+                    timer.tick(timer.duration());
+                    player
+                        .insert(PlayerInput {
+                            device: InputDevice::Mouse,
+                            last_valid_cursor_pos: None,
+                        })
+                        .insert(ShootTimer(timer));
+                }
+            }
+        }
+    }
+}
+
+// Keeps the replicated players' scores and aim up to date, the local player aims with its own input instead
+pub fn sync_network_players(
+    planet_shape: Res<PlanetShape>,
+    mut player_query: Query<
+        (
+            &Transform,
+            &NetworkPlayer,
+            &mut PlayerMeshDesiredTransform,
+            &mut PlayerScore,
+        ),
+        Without<PlayerInput>,
+    >,
+    mut local_player_query: Query<(&NetworkPlayer, &mut PlayerScore), With<PlayerInput>>,
+) {
+    for (transform, network_player, mut desired_transform, mut player_score) in
+        player_query.iter_mut()
+    {
+        *desired_transform = network_player_desired_transform(
+            &planet_shape,
+            &network_player.0,
+            transform.translation,
+        );
+        if player_score.0 != network_player.0.score {
+            player_score.0 = network_player.0.score;
+        }
+    }
+
+    for (network_player, mut player_score) in local_player_query.iter_mut() {
+        if player_score.0 != network_player.0.score {
+            player_score.0 = network_player.0.score;
+        }
+    }
+}
+
+// Removes the meshes of players that have left
+pub fn despawn_orphaned_player_meshes(
+    mut commands: Commands,
+    player_mesh_query: Query<(Entity, &PlayerId), With<PlayerMesh>>,
+    player_query: Query<&PlayerId, With<PlayerCollider>>,
+) {
+    for (entity, mesh_player_id) in player_mesh_query.iter() {
+        if !player_query
+            .iter()
+            .any(|player_id| player_id == mesh_player_id)
+        {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+// Takes the round's score and wave from the latest snapshot
+pub fn sync_network_round(
+    client: Res<NetworkClient>,
+    mut score: ResMut<Score>,
+    wave: Option<ResMut<AsteroidWave>>,
+) {
+    let Some(latest) = client.latest_snapshot() else {
+        return;
+    };
+
+    if score.0 != latest.score {
+        score.0 = latest.score;
+    }
+    if let Some(mut wave) = wave {
+        if wave.0 != latest.wave {
+            wave.0 = latest.wave;
+        }
+    }
+}
+
+// Aim and shots of the local player, sent to the server
+pub fn collect_client_input(
+    mut client: ResMut<NetworkClient>,
+    mut ev_shoot: EventReader<ShootEvent>,
+    local_player_query: Query<(Entity, &PlayerMeshDesiredTransform), With<PlayerInput>>,
+) {
+    let Ok((local_player, desired_transform)) = local_player_query.get_single() else {
+        return;
+    };
+
+    client.input.aim = desired_transform.tangent.to_array();
+    client.input.shots += ev_shoot
+        .read()
+        .filter(|ev| ev.shooter == local_player)
+        .count() as u32;
+}
+
+// Asteroids that disappear explode like they do on the server, the points were already counted there
+pub fn explode_replica_asteroids(
+    mut ev_replica_despawned: EventReader<ReplicaDespawnedEvent>,
+    mut ev_asteroid_destroyed: EventWriter<AsteroidDestroyedEvent>,
+) {
+    for ev in ev_replica_despawned.read() {
+        if matches!(ev.kind, NetworkKind::Asteroid { .. }) {
+            ev_asteroid_destroyed.send(AsteroidDestroyedEvent {
+                position: ev.position,
+                points: 0,
+            });
+        }
+    }
+}
+
+// HELPER FUNCTIONS
+
+// Only one local player is controlled when connected to a server or playing a duel
+pub fn local_player_count(settings: &Settings, networked: bool) -> u32 {
+    if networked {
+        1
+    } else {
+        settings.players
+    }
+}
+
+// The local player is the first player so it gets the mouse and the primary camera, the others follow it
+pub fn local_player_id(slot: u8, local_slot: Option<u8>) -> PlayerId {
+    match local_slot {
+        Some(local_slot) if slot == local_slot => PlayerId::FIRST,
+        _ => PlayerId(slot as usize + 1),
+    }
+}
+
+fn network_player_desired_transform(
+    planet_shape: &PlanetShape,
+    player_state: &PlayerState,
+    position: Vec3,
+) -> PlayerMeshDesiredTransform {
+    let tangent = Vec3::from_array(player_state.aim);
+    let local_up = planet_shape.surface_normal(position);
+    PlayerMeshDesiredTransform {
+        position,
+        tangent,
+        local_up,
+        local_forward: local_up.any_orthonormal_vector(),
+    }
+}
@@ -0,0 +1,121 @@
+// Animated cloud shell around the planet.
+// The shell is displaced by three Gerstner waves in `cloud_vertex.wgsl`, whose crests are cloudy and troughs clear.
+// The waves are tuned per level, the material's time follows the virtual clock so the clouds stop when paused.
+
+use bevy::{
+    pbr::{MaterialMeshBundle, NotShadowCaster},
+    prelude::{
+        default, shape, AlphaMode, Asset, Assets, Color, Commands, Component, Handle, Material,
+        Mesh, Name, Query, Res, ResMut, Time, Vec2, With,
+    },
+    reflect::TypePath,
+    render::render_resource::{AsBindGroup, ShaderRef},
+};
+
+use crate::{common::PLANET_SIZE, level::LevelConfig};
+
+// CONSTANTS
+
+const CLOUD_SHELL_SUBDIVISIONS: usize = 48;
+
+// COMPONENTS
+
+#[derive(Component)]
+pub struct CloudShell {}
+
+// MATERIALS
+
+#[derive(Asset, TypePath, AsBindGroup, Clone, Debug)]
+pub struct CloudMaterial {
+    #[uniform(0)]
+    pub time: f32,
+    #[uniform(1)]
+    pub steepness: f32,
+    #[uniform(2)]
+    pub wavelength: f32,
+    #[uniform(3)]
+    pub speed: f32,
+    #[uniform(4)]
+    pub wave_1_dir: Vec2,
+    #[uniform(5)]
+    pub wave_2_dir: Vec2,
+    #[uniform(6)]
+    pub wave_3_dir: Vec2,
+    #[uniform(7)]
+    pub color: Color,
+    #[uniform(8)]
+    pub coverage: f32,
+}
+
+impl Material for CloudMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/cloud_vertex.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/cloud_vertex.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+}
+
+// STARTUP SYSTEMS
+
+pub fn setup_clouds(
+    mut commands: Commands,
+    level: Res<LevelConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<CloudMaterial>>,
+) {
+    let clouds = &level.clouds;
+    if !clouds.enabled {
+        return;
+    }
+
+    let [wave_1_dir, wave_2_dir, wave_3_dir] = clouds.wave_directions.map(Vec2::from);
+    let [red, green, blue, alpha] = clouds.color;
+
+    // A UV sphere's vertices bunch up at the poles, so an icosphere keeps the waves even all around
+    let Ok(mesh) = Mesh::try_from(shape::Icosphere {
+        radius: PLANET_SIZE + clouds.altitude,
+        subdivisions: CLOUD_SHELL_SUBDIVISIONS,
+    }) else {
+        return;
+    };
+
+    commands
+        .spawn(MaterialMeshBundle {
+            mesh: meshes.add(mesh),
+            material: materials.add(CloudMaterial {
+                time: 0.0,
+                steepness: clouds.steepness,
+                wavelength: clouds.wavelength,
+                speed: clouds.speed,
+                wave_1_dir,
+                wave_2_dir,
+                wave_3_dir,
+                color: Color::rgba(red, green, blue, alpha),
+                coverage: clouds.coverage.clamp(0.0, 1.0),
+            }),
+            ..default()
+        })
+        .insert(Name::new("Clouds"))
+        .insert(CloudShell {})
+        .insert(NotShadowCaster);
+}
+
+// SYSTEMS
+
+pub fn animate_clouds(
+    time: Res<Time>,
+    mut materials: ResMut<Assets<CloudMaterial>>,
+    cloud_query: Query<&Handle<CloudMaterial>, With<CloudShell>>,
+) {
+    for handle in cloud_query.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            material.time = time.elapsed_seconds();
+        }
+    }
+}
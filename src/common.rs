@@ -1,22 +1,33 @@
 use bevy::{
+    app::AppExit,
+    log::warn,
     prelude::{
-        default, shape, AmbientLight, AssetServer, Assets, Camera, Camera3dBundle, Color, Commands,
-        Component, DespawnRecursiveExt, DirectionalLight, DirectionalLightBundle, Entity,
-        EventReader, Mesh, Name, NextState, NonSend, PbrBundle, Quat, Query, Res, ResMut, Resource,
-        StandardMaterial, States, Transform, Vec3, With, Without,
+        default, AmbientLight, Camera, Color, Commands, Component, DespawnRecursiveExt,
+        DetectChangesMut, DirectionalLight, DirectionalLightBundle, Entity, Event, EventReader,
+        EventWriter, Name, NextState, NonSend, Or, Quat, Query, Res, ResMut, Resource, States,
+        Transform, Vec3, With, Without,
     },
+    time::{Stopwatch, Time, Timer, TimerMode, Virtual},
     window::{PrimaryWindow, Window},
     winit::WinitWindows,
 };
 use bevy_rapier3d::prelude::{
-    CoefficientCombineRule, Collider, CollisionEvent, ExternalForce, Friction,
-    RapierColliderHandle, RapierContext, RapierRigidBodyHandle, Restitution,
+    CollisionEvent, ExternalForce, ExternalImpulse, RapierColliderHandle, RapierConfiguration,
+    RapierContext, RapierRigidBodyHandle,
 };
 use image;
-use std::f32::consts::PI;
+use rand::{rngs::StdRng, SeedableRng};
+use std::{f32::consts::PI, path::Path};
 use winit::window::Icon;
 
-use crate::{asteroids::Asteroid, cannon_ball::CannonBall, player::PlayerCollider};
+use crate::{
+    asset_manifest::{AssetManifest, ICON_PATH},
+    asteroids::Asteroid,
+    cannon_ball::CannonBall,
+    player::{PlayerCollider, PlayerId, PlayerScore, FRIENDLY_FIRE_IMPULSE},
+    settings::Settings,
+    versus::VersusScoreboard,
+};
 
 // CONSTANTS
 pub const PLANET_SIZE: f32 = 20.0;
@@ -24,6 +35,9 @@ pub const CAMERA_DISTANCE: f32 = 60.0;
 pub const CAMERA_DELAY: f32 = 0.9;
 pub const GRAVITY_MAGNITUDE: f32 = 3.0;
 pub const SCORE_INCREMENT: i32 = 1;
+pub const COMBO_WINDOW: f32 = 3.0; // Seconds until the combo resets if no asteroid is destroyed
+pub const COMBO_HITS_PER_MULTIPLIER: u32 = 3;
+pub const MAX_COMBO_MULTIPLIER: i32 = 5;
 pub const SHOW_DEBUG_LINES: bool = false;
 
 // COMPONENTS
@@ -31,75 +45,102 @@ pub const SHOW_DEBUG_LINES: bool = false;
 #[derive(Component)]
 pub struct PrimaryCamera {}
 
+// Draws the UI over the whole window when the players' cameras are split into viewports
+#[derive(Component)]
+pub struct UiCamera {}
+
+#[derive(Component)]
+pub struct Sun {}
+
 // RESOURCES
 
 #[derive(Resource)]
 pub struct Score(pub i32);
 
+// Seed of the current round, used to seed GameRng
+#[derive(Resource)]
+pub struct GameSeed(pub u64);
+
+// Ticks of play until the app exits
+#[derive(Resource)]
+pub struct TickLimit {
+    pub ticks: u32,
+    pub played: u32,
+}
+
+// Seed every round is played with instead of a random one, so a run can be reproduced
+#[derive(Resource)]
+pub struct RequestedSeed(pub u64);
+
+// Random number generator for gameplay randomness, seeded from GameSeed
+#[derive(Resource)]
+pub struct GameRng(pub StdRng);
+
+// Time survived in the current round
+#[derive(Resource, Default)]
+pub struct SurvivalTime(pub Stopwatch);
+
+// Whole seconds of SurvivalTime, only changes once per second
+#[derive(Resource, Default, PartialEq)]
+pub struct SurvivalSeconds(pub u32);
+
+// Consecutive asteroid hits, each hit within COMBO_WINDOW of the last one keeps the combo going
+#[derive(Resource, Clone)]
+pub struct Combo {
+    pub hits: u32,
+    pub timer: Timer,
+}
+
+impl Combo {
+    pub fn multiplier(&self) -> i32 {
+        (1 + (self.hits / COMBO_HITS_PER_MULTIPLIER) as i32).min(MAX_COMBO_MULTIPLIER)
+    }
+}
+
+impl Default for Combo {
+    fn default() -> Self {
+        Self {
+            hits: 0,
+            timer: Timer::from_seconds(COMBO_WINDOW, TimerMode::Once),
+        }
+    }
+}
+
+// EVENTS
+
+// An entity destroyed in a collision, despawned by despawn_destroyed or buried by rollbacks so they can bring it back
+#[derive(Event)]
+pub struct DestroyEvent {
+    pub entity: Entity,
+}
+
+#[derive(Event)]
+pub struct AsteroidDestroyedEvent {
+    pub position: Vec3,
+    pub points: i32, // Zero if it wasn't destroyed by a cannon ball
+}
+
 // STATES
 #[derive(States, Clone, PartialEq, Eq, Debug, Hash, Default)]
 pub enum GameState {
     #[default]
+    Loading,
+    MainMenu,
     Playing,
     GameOver,
+    HighScores,
 }
-// STARTUP SYSTEMS
 
-pub fn setup_scene(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    // Scene Camera
-    commands
-        .spawn((Camera3dBundle {
-            camera: Camera {
-                order: 10,
-                ..default()
-            },
-            transform: Transform::from_xyz(0.0, 0.0, CAMERA_DISTANCE)
-                .looking_at(Vec3::ZERO, Vec3::Y),
-            ..default()
-        },))
-        .insert(PrimaryCamera {});
-
-    // Planet
-    commands
-        .spawn(PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::UVSphere {
-                radius: PLANET_SIZE,
-                sectors: 32,
-                stacks: 32,
-            })),
-            material: materials.add(StandardMaterial {
-                // base_color: Color::rgb(0.3, 0.5, 0.3),
-                base_color_texture: asset_server
-                    .load("textures/planet/DirtRug_diffuse.png")
-                    .into(),
-                normal_map_texture: asset_server
-                    .load("textures/planet/DirtRug_normal.png")
-                    .into(),
-                metallic_roughness_texture: asset_server
-                    .load("textures/planet/DirtRug_metallic_roughness.png")
-                    .into(),
-                perceptual_roughness: 0.8,
-                metallic: 0.4,
-                ..default()
-            }),
-            ..default()
-        })
-        .insert(Name::new("Planet"))
-        .insert(Collider::ball(20.0))
-        .insert(Friction {
-            coefficient: 2.0,
-            combine_rule: CoefficientCombineRule::Max,
-        })
-        .insert(Restitution {
-            coefficient: 0.0,
-            combine_rule: CoefficientCombineRule::Max,
-        });
+// Only relevant while the game state is Playing
+#[derive(States, Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub enum PauseState {
+    #[default]
+    Running,
+    Paused,
+}
+// STARTUP SYSTEMS
 
+pub fn setup_scene(mut commands: Commands, settings: Res<Settings>) {
     // Directional light - sun
     commands
         .spawn(DirectionalLightBundle {
@@ -111,7 +152,7 @@ pub fn setup_scene(
                     alpha: 1.0,
                 },
                 illuminance: 100_000.0,
-                shadows_enabled: true,
+                shadows_enabled: settings.shadows,
                 ..default()
             },
             transform: Transform {
@@ -121,7 +162,8 @@ pub fn setup_scene(
             },
             ..default()
         })
-        .insert(Name::new("Sun"));
+        .insert(Name::new("Sun"))
+        .insert(Sun {});
 
     // Ambient light
     commands.insert_resource(AmbientLight {
@@ -130,6 +172,17 @@ pub fn setup_scene(
     });
 }
 
+// Picks a new seed for the round, unless one was requested, and resets the per round resources
+pub fn setup_round(mut commands: Commands, requested_seed: Option<Res<RequestedSeed>>) {
+    let seed = requested_seed.map_or_else(rand::random::<u64>, |seed| seed.0);
+
+    commands.insert_resource(GameSeed(seed));
+    commands.insert_resource(GameRng(StdRng::seed_from_u64(seed)));
+    commands.insert_resource(SurvivalTime::default());
+    commands.insert_resource(SurvivalSeconds::default());
+    commands.insert_resource(Combo::default());
+}
+
 pub fn reset_rapier(
     mut commands: Commands,
     mut rapier: ResMut<RapierContext>,
@@ -161,108 +214,260 @@ pub fn reset_rapier(
 
 pub fn setup_window(
     windows: NonSend<WinitWindows>,
+    asset_manifest: Res<AssetManifest>,
     primary_window_query: Query<Entity, With<PrimaryWindow>>,
 ) {
-    let primary_window_entity = primary_window_query.single();
-    let primary_window = windows.get_window(primary_window_entity).unwrap();
+    let Ok(primary_window_entity) = primary_window_query.get_single() else {
+        return;
+    };
+    let Some(primary_window) = windows.get_window(primary_window_entity) else {
+        return;
+    };
+
+    // The manifest has already reported a missing icon, the window keeps the default icon
+    if !asset_manifest.is_available(ICON_PATH) {
+        return;
+    }
 
     // here we use the `image` crate to load our icon data from a png file
     // this is not a very bevy-native solution, but it will do
-    let (icon_rgba, icon_width, icon_height) = {
-        let image = image::open("assets/icons/main_icon.png")
-            .expect("Failed to open icon path")
-            .into_rgba8();
-        let (width, height) = image.dimensions();
-        let rgba = image.into_raw();
-        (rgba, width, height)
+    let image = match image::open(Path::new(&asset_manifest.directory).join(ICON_PATH)) {
+        Ok(image) => image.into_rgba8(),
+        Err(err) => {
+            warn!("Failed to open the window icon {}: {}", ICON_PATH, err);
+            return;
+        }
     };
+    let (icon_width, icon_height) = image.dimensions();
 
-    let icon = Icon::from_rgba(icon_rgba, icon_width, icon_height).unwrap();
-
-    primary_window.set_window_icon(Some(icon));
+    match Icon::from_rgba(image.into_raw(), icon_width, icon_height) {
+        Ok(icon) => primary_window.set_window_icon(Some(icon)),
+        Err(err) => warn!("Failed to use {} as the window icon: {}", ICON_PATH, err),
+    }
 }
 
 // SYSTEMS
 
 // System to handle collision events
 pub fn handle_collisions(
-    mut commands: Commands,
     mut next_state: ResMut<NextState<GameState>>,
     mut score: ResMut<Score>,
+    mut combo: ResMut<Combo>,
     mut ev_collision: EventReader<CollisionEvent>,
-    player_collider_query: Query<Entity, With<PlayerCollider>>,
-    cannon_ball_query: Query<Entity, With<CannonBall>>,
-    asteroid_query: Query<Entity, With<Asteroid>>,
+    mut ev_asteroid_destroyed: EventWriter<AsteroidDestroyedEvent>,
+    mut ev_destroy: EventWriter<DestroyEvent>,
+    mut player_collider_query: Query<
+        (&Transform, &mut ExternalImpulse, &mut PlayerScore),
+        With<PlayerCollider>,
+    >,
+    player_id_query: Query<&PlayerId, With<PlayerCollider>>,
+    cannon_ball_query: Query<(&CannonBall, &Transform)>,
+    asteroid_query: Query<&Transform, With<Asteroid>>,
+    mut versus_scoreboard: Option<ResMut<VersusScoreboard>>,
 ) {
     for collsion_event in ev_collision.read() {
         // Check only when collision has started
         if let CollisionEvent::Started(collider, other_collider, _) = collsion_event {
-            if player_collider_query.get(*collider).is_ok()
-                || player_collider_query.get(*other_collider).is_ok()
+            // Order the pair so that a player always comes first
+            let (collider, other_collider) = if player_collider_query.contains(*other_collider) {
+                (*other_collider, *collider)
+            } else {
+                (*collider, *other_collider)
+            };
+
+            if let Ok((player_transform, mut player_impulse, _)) =
+                player_collider_query.get_mut(collider)
             {
-                next_state.set(GameState::GameOver);
-            } else if cannon_ball_query.get(*collider).is_ok() {
-                if asteroid_query.get(*other_collider).is_ok() {
-                    score.0 += SCORE_INCREMENT;
-                    commands.entity(*collider).despawn();
-                    commands.entity(*other_collider).despawn();
-                } else if cannon_ball_query.get(*other_collider).is_ok() {
-                    commands.entity(*collider).despawn();
-                    commands.entity(*other_collider).despawn();
+                // Friendly fire knocks the player away from another player's cannon ball,
+                // outside of versus rounds anything else the player collides with ends the game
+                match cannon_ball_query.get(other_collider) {
+                    Ok((cannon_ball, cannon_ball_transform)) if cannon_ball.shooter != collider => {
+                        let away = (player_transform.translation
+                            - cannon_ball_transform.translation)
+                            .normalize_or_zero();
+                        player_impulse.impulse += away * FRIENDLY_FIRE_IMPULSE;
+                        ev_destroy.send(DestroyEvent {
+                            entity: other_collider,
+                        });
+
+                        if let (Some(scoreboard), Ok(shooter_id), Ok(player_id)) = (
+                            versus_scoreboard.as_deref_mut(),
+                            player_id_query.get(cannon_ball.shooter),
+                            player_id_query.get(collider),
+                        ) {
+                            scoreboard.knock(*shooter_id, *player_id);
+                        }
+                    }
+                    // In a versus round an asteroid hit scores for the player's opponents instead,
+                    // and the asteroid explodes
+                    _ if versus_scoreboard.is_some() => {
+                        let Ok(asteroid_transform) = asteroid_query.get(other_collider) else {
+                            continue;
+                        };
+                        if let (Some(scoreboard), Ok(player_id)) = (
+                            versus_scoreboard.as_deref_mut(),
+                            player_id_query.get(collider),
+                        ) {
+                            scoreboard.asteroid_hit(*player_id);
+                        }
+
+                        ev_asteroid_destroyed.send(AsteroidDestroyedEvent {
+                            position: asteroid_transform.translation,
+                            points: 0,
+                        });
+                        ev_destroy.send(DestroyEvent {
+                            entity: other_collider,
+                        });
+                    }
+                    _ => next_state.set(GameState::GameOver),
                 }
-            } else if asteroid_query.get(*collider).is_ok() {
-                if cannon_ball_query.get(*other_collider).is_ok() {
-                    score.0 += SCORE_INCREMENT;
-                    commands.entity(*collider).despawn();
-                    commands.entity(*other_collider).despawn();
-                } else if asteroid_query.get(*other_collider).is_ok() {
-                    commands.entity(*collider).despawn();
-                    commands.entity(*other_collider).despawn();
+                continue;
+            }
+
+            // Order the pair so that a cannon ball always comes first
+            let (collider, other_collider) = if cannon_ball_query.contains(other_collider) {
+                (other_collider, collider)
+            } else {
+                (collider, other_collider)
+            };
+
+            if let Ok((cannon_ball, _)) = cannon_ball_query.get(collider) {
+                if let Ok(asteroid_transform) = asteroid_query.get(other_collider) {
+                    combo.hits += 1;
+                    combo.timer.reset();
+
+                    // Points go to the round's score and the score of the player that shot the cannon ball
+                    let points = SCORE_INCREMENT * combo.multiplier();
+                    score.0 += points;
+                    if let Ok((_, _, mut player_score)) =
+                        player_collider_query.get_mut(cannon_ball.shooter)
+                    {
+                        player_score.0 += points;
+                    }
+
+                    ev_asteroid_destroyed.send(AsteroidDestroyedEvent {
+                        position: asteroid_transform.translation,
+                        points,
+                    });
+                    ev_destroy.send(DestroyEvent { entity: collider });
+                    ev_destroy.send(DestroyEvent {
+                        entity: other_collider,
+                    });
+                } else if cannon_ball_query.contains(other_collider) {
+                    ev_destroy.send(DestroyEvent { entity: collider });
+                    ev_destroy.send(DestroyEvent {
+                        entity: other_collider,
+                    });
+                }
+            } else if let (Ok(asteroid_transform), Ok(other_asteroid_transform)) = (
+                asteroid_query.get(collider),
+                asteroid_query.get(other_collider),
+            ) {
+                for transform in [asteroid_transform, other_asteroid_transform] {
+                    ev_asteroid_destroyed.send(AsteroidDestroyedEvent {
+                        position: transform.translation,
+                        points: 0,
+                    });
                 }
+                ev_destroy.send(DestroyEvent { entity: collider });
+                ev_destroy.send(DestroyEvent {
+                    entity: other_collider,
+                });
             }
         }
     }
 }
 
-// Move primary camera to follow the player
-pub fn move_camera(
-    mut camera_transforms: Query<&mut Transform, With<PrimaryCamera>>,
-    player_query: Query<&Transform, (With<PlayerCollider>, Without<PrimaryCamera>)>,
+// Despawns the entities destroyed in collisions, duels bury them with bury_destroyed instead
+pub fn despawn_destroyed(mut commands: Commands, mut ev_destroy: EventReader<DestroyEvent>) {
+    for ev in ev_destroy.read() {
+        commands.entity(ev.entity).despawn();
+    }
+}
+
+// Resets the combo when no asteroid was destroyed within the combo window
+pub fn update_combo(mut combo: ResMut<Combo>, time: Res<Time>) {
+    if combo.hits == 0 {
+        return;
+    }
+
+    if combo.timer.tick(time.delta()).finished() {
+        combo.hits = 0;
+    }
+}
+
+// Exits the app once the tick limit has been played
+pub fn exit_after_tick_limit(
+    mut tick_limit: ResMut<TickLimit>,
+    mut ev_app_exit: EventWriter<AppExit>,
 ) {
-    let mut camera_transform = camera_transforms.iter_mut().next().unwrap();
-
-    let player_transform = player_query.single();
-    let player_translation_scaled = player_transform.translation.normalize() * CAMERA_DISTANCE;
-
-    if camera_transform
-        .translation
-        .distance(player_translation_scaled)
-        > 0.1
-    {
-        let new_camera_translation = camera_transform
-            .translation
-            .lerp(player_translation_scaled, CAMERA_DELAY);
-
-        *camera_transform = Transform::from_translation(new_camera_translation)
-            .looking_at(Vec3::ZERO, camera_transform.up());
+    tick_limit.played += 1;
+    if tick_limit.played >= tick_limit.ticks {
+        ev_app_exit.send(AppExit);
     }
 }
 
+// Keeps track of how long the player has survived in the current round
+pub fn update_survival_time(
+    mut survival_time: ResMut<SurvivalTime>,
+    mut survival_seconds: ResMut<SurvivalSeconds>,
+    time: Res<Time>,
+) {
+    survival_time.0.tick(time.delta());
+    survival_seconds.set_if_neq(SurvivalSeconds(survival_time.0.elapsed_secs() as u32));
+}
+
 // Custom gravity which acts towards the center of the planet (which is at the origin)
 pub fn gravity(mut query: Query<(&Transform, &mut ExternalForce)>) {
     for (transform, mut force) in query.iter_mut() {
-        let grav_force_magnitude = transform.translation.length().powi(2) * GRAVITY_MAGNITUDE;
-        force.force = grav_force_magnitude * -transform.translation.normalize();
+        force.force = gravity_force(transform.translation);
     }
 }
 
+// Freezes time and physics while the game is paused
+pub fn pause_game(
+    mut time: ResMut<Time<Virtual>>,
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+) {
+    time.pause();
+    rapier_configuration.physics_pipeline_active = false;
+}
+
+pub fn resume_game(
+    mut time: ResMut<Time<Virtual>>,
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+) {
+    time.unpause();
+    rapier_configuration.physics_pipeline_active = true;
+}
+
+// HELPER FUNCTIONS
+
+// Gravitational force on a body at the given position, shared by the gravity system and trajectory predictions
+pub fn gravity_force(translation: Vec3) -> Vec3 {
+    let grav_force_magnitude = translation.length().powi(2) * GRAVITY_MAGNITUDE;
+    grav_force_magnitude * -translation.normalize()
+}
+
+// Formats a duration in seconds as M:SS
+pub fn format_duration(seconds: f32) -> String {
+    let seconds = seconds as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
 // CLEANUP SYSTEMS
 
-// Remove all entities except non primary cameras
+// Remove all entities except non primary cameras, the players' cameras and the UI overlay camera are removed too
 pub fn teardown(
     mut commands: Commands,
     entities: Query<Entity, (Without<Camera>, Without<Window>)>,
-    primary_camera_query: Query<Entity, With<PrimaryCamera>>,
+    primary_camera_query: Query<
+        Entity,
+        (
+            With<Camera>,
+            Or<(With<PrimaryCamera>, With<PlayerId>, With<UiCamera>)>,
+        ),
+    >,
 ) {
     for entity in entities.iter() {
         commands.entity(entity).despawn_recursive();
@@ -277,3 +482,8 @@ pub fn teardown(
 pub fn reset_score(mut score: ResMut<Score>) {
     score.0 = 0;
 }
+
+// Make sure the game isn't left paused when leaving the Playing state from the pause menu
+pub fn reset_pause(mut next_pause_state: ResMut<NextState<PauseState>>) {
+    next_pause_state.set(PauseState::Running);
+}
@@ -1,17 +1,28 @@
-use bevy::prelude::{Mat3, Quat, Transform, Vec3};
+use bevy::prelude::{Transform, Vec3};
+
+use crate::spherical_frame::{project_on_tangent_plane, SphericalFrame};
 
 pub trait TransformExt {
     /// Rotates this [`Transform`] so that its local negative `Y` direction is toward
     /// `target` and its local negative `Z` direction is toward `forward`.
+    ///
+    /// If `forward` is parallel to the up direction, the current facing is parallel transported
+    /// to the new up direction instead so the rotation doesn't flip.
     fn set_down(&mut self, target: Vec3, forward: Vec3);
 }
 
 impl TransformExt for Transform {
     fn set_down(&mut self, target: Vec3, forward: Vec3) {
         let up = Vec3::normalize(self.translation - target);
-        let right = up.cross(forward).normalize();
-        let forward = right.cross(up).normalize();
 
-        self.rotation = Quat::from_mat3(&Mat3::from_cols(right, up, forward));
+        let frame = match project_on_tangent_plane(forward, up) {
+            Some(forward) => SphericalFrame {
+                normal: up,
+                forward,
+            },
+            None => SphericalFrame::new(self.up(), self.forward()).transported(up),
+        };
+
+        self.rotation = frame.surface_rotation();
     }
 }
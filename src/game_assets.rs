@@ -0,0 +1,185 @@
+// Handles to everything the game loads or generates up front, built while the game is in the Loading state.
+// Systems use these typed handles instead of loading assets by path, so each file is only requested once.
+// The game moves on to the main menu once every file has finished loading or failed to load.
+
+use bevy::{
+    asset::{RecursiveDependencyLoadState, UntypedHandle},
+    log::info,
+    prelude::{
+        AssetServer, Assets, Commands, Font, Handle, Image, Mesh, NextState, Res, ResMut, Resource,
+        Scene, StandardMaterial,
+    },
+};
+use bevy_rapier3d::prelude::Collider;
+
+use crate::{
+    asset_manifest::{AssetManifest, CANNON_MODEL_PATH, FONT_PATH},
+    asteroids::{asteroid_materials, asteroid_variants},
+    cannon_ball::{cannon_ball_material, cannon_ball_mesh},
+    common::GameState,
+    planet::planet_material,
+};
+
+// RESOURCES
+
+#[derive(Resource)]
+pub struct GameAssets {
+    pub font: Handle<Font>,
+    pub cannon: Handle<Scene>,
+    pub textures: Vec<Handle<Image>>, // Textures used by the materials, loaded from files or generated
+    pub planet_material: Handle<StandardMaterial>,
+    pub asteroid_variants: Vec<(Handle<Mesh>, Collider)>,
+    pub asteroid_materials: Vec<Handle<StandardMaterial>>,
+    pub cannon_ball_mesh: Handle<Mesh>,
+    pub cannon_ball_material: Handle<StandardMaterial>,
+}
+
+// Number of the game's assets that have finished loading
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LoadingProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
+impl GameAssets {
+    // Only the meshes and colliders used by the simulation, for the dedicated server which doesn't draw anything
+    pub fn headless(meshes: &mut Assets<Mesh>) -> Self {
+        Self {
+            font: Handle::default(),
+            cannon: Handle::default(),
+            textures: Vec::new(),
+            planet_material: Handle::default(),
+            asteroid_variants: asteroid_variants(meshes),
+            asteroid_materials: vec![Handle::default()],
+            cannon_ball_mesh: meshes.add(cannon_ball_mesh()),
+            cannon_ball_material: Handle::default(),
+        }
+    }
+
+    // Handles of every asset loaded by the asset server, generated assets are already in their Assets collection
+    pub fn loading_handles(&self) -> Vec<UntypedHandle> {
+        [self.font.clone().untyped(), self.cannon.clone().untyped()]
+            .into_iter()
+            .chain(
+                self.textures
+                    .iter()
+                    .map(|texture| texture.clone().untyped()),
+            )
+            .collect()
+    }
+}
+
+impl LoadingProgress {
+    pub fn is_finished(&self) -> bool {
+        self.done >= self.total
+    }
+
+    // Between 0 and 1
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.done as f32 / self.total as f32
+        }
+    }
+}
+
+// STARTUP SYSTEMS
+
+// Requests every file and generates the shared meshes and materials, missing files are replaced by defaults
+pub fn setup_game_assets(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    asset_manifest: Res<AssetManifest>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    // The default font handle is Bevy's built in font
+    let font = if asset_manifest.is_available(FONT_PATH) {
+        asset_server.load(FONT_PATH)
+    } else {
+        Handle::default()
+    };
+    let cannon = if asset_manifest.is_available(CANNON_MODEL_PATH) {
+        asset_server.load(format!("{}#Scene0", CANNON_MODEL_PATH))
+    } else {
+        Handle::default()
+    };
+
+    let planet_material = planet_material(&asset_server, &asset_manifest, &mut images);
+    let asteroid_materials = asteroid_materials(&asset_server, &asset_manifest, &mut images);
+
+    let textures = std::iter::once(&planet_material)
+        .chain(asteroid_materials.iter())
+        .flat_map(|material| {
+            [
+                &material.base_color_texture,
+                &material.normal_map_texture,
+                &material.metallic_roughness_texture,
+            ]
+        })
+        .flatten()
+        .fold(Vec::new(), |mut textures, texture| {
+            if !textures.contains(texture) {
+                textures.push(texture.clone());
+            }
+            textures
+        });
+
+    let game_assets = GameAssets {
+        font,
+        cannon,
+        textures,
+        planet_material: materials.add(planet_material),
+        asteroid_variants: asteroid_variants(&mut meshes),
+        asteroid_materials: asteroid_materials
+            .into_iter()
+            .map(|material| materials.add(material))
+            .collect(),
+        cannon_ball_mesh: meshes.add(cannon_ball_mesh()),
+        cannon_ball_material: materials.add(cannon_ball_material()),
+    };
+
+    commands.insert_resource(LoadingProgress {
+        done: 0,
+        total: game_assets.loading_handles().len(),
+    });
+    commands.insert_resource(game_assets);
+}
+
+// SYSTEMS
+
+// Counts the assets that have finished loading, failed ones count as finished since they were already reported
+pub fn check_loading_progress(
+    asset_server: Res<AssetServer>,
+    game_assets: Res<GameAssets>,
+    mut loading_progress: ResMut<LoadingProgress>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let handles = game_assets.loading_handles();
+    let done = handles
+        .iter()
+        .filter(|handle| {
+            !matches!(
+                asset_server.get_recursive_dependency_load_state(handle.id()),
+                Some(RecursiveDependencyLoadState::NotLoaded)
+                    | Some(RecursiveDependencyLoadState::Loading)
+            )
+        })
+        .count();
+
+    // Only change the resource when the progress changes so the loading screen only updates then
+    let progress = LoadingProgress {
+        done,
+        total: handles.len(),
+    };
+    if *loading_progress != progress {
+        *loading_progress = progress;
+    }
+
+    if progress.is_finished() {
+        info!("Loaded {} assets", progress.total);
+        next_state.set(GameState::MainMenu);
+    }
+}
@@ -0,0 +1,460 @@
+// Step based environment over the headless simulation for training agents to play.
+// The agent plays the first player in place of a bot, every step it picks where to aim and whether to fire, then the
+// game runs a fixed number of ticks. Observations are given in the player's tangent frame so they don't depend on where
+// on the planet the player is, and the reward is the score gained plus a bonus for every second survived.
+// External trainers drive the environment with line delimited JSON over stdin and stdout, see serve().
+
+use bevy::prelude::{App, Component, Entity, Transform, Vec2, Vec3, With};
+use bevy_rapier3d::prelude::Velocity;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+
+use crate::{
+    asteroids::Asteroid,
+    bot::BotController,
+    common::Score,
+    headless::{headless_app, is_game_over, HEADLESS_TICK_RATE},
+    input::ShootTimer,
+    planet::PlanetShape,
+    player::{PlayerCollider, PlayerId, PlayerMeshDesiredTransform},
+    settings::Settings,
+    spherical_frame::{project_on_tangent_plane, SphericalFrame},
+};
+
+// CONSTANTS
+
+pub const DEFAULT_TICKS_PER_STEP: u32 = 4;
+pub const DEFAULT_NEAREST_ASTEROIDS: usize = 8;
+pub const DEFAULT_SURVIVAL_REWARD: f32 = 1.0; // Reward per second survived
+pub const ASTEROID_FEATURES: usize = 6;
+pub const PLAYER_FEATURES: usize = 5;
+
+// COMPONENTS
+
+// The player the agent plays, bots leave its BotController to the environment
+#[derive(Component)]
+pub struct GymAgent;
+
+// ENVIRONMENT
+
+#[derive(Clone, Debug)]
+pub struct GymConfig {
+    pub settings: Settings, // Local players after the first one are played by bots
+    pub ticks_per_step: u32,
+    pub nearest_asteroids: usize, // Asteroids in each observation
+    pub survival_reward: f32,
+}
+
+impl Default for GymConfig {
+    fn default() -> Self {
+        Self {
+            settings: Settings::default(),
+            ticks_per_step: DEFAULT_TICKS_PER_STEP,
+            nearest_asteroids: DEFAULT_NEAREST_ASTEROIDS,
+            survival_reward: DEFAULT_SURVIVAL_REWARD,
+        }
+    }
+}
+
+// What the agent does in a step, directions are [right, forward] in the observation's tangent frame
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct GymAction {
+    pub aim: Option<[f32; 2]>, // Direction to shoot in, the cannon keeps its aim if None
+    #[serde(default)]
+    pub fire: bool, // Fires on the step's first tick if the cannon is loaded
+}
+
+// The player and the asteroids closest to it, vectors are [right, forward, up] in the player's tangent frame, where
+// up is the normal of the surface under the player and forward is the player's forward moved along with it
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct GymObservation {
+    pub player: PlayerObservation,
+    pub asteroids: Vec<AsteroidObservation>, // Closest first, at most nearest_asteroids of them
+    pub score: i32,
+    pub survival_ticks: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct PlayerObservation {
+    pub altitude: f32, // Height above the surface
+    pub velocity: [f32; 3],
+    pub reload: f32, // Fraction of the reload done, 1 when the cannon can fire
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct AsteroidObservation {
+    pub position: [f32; 3], // Relative to the player
+    pub velocity: [f32; 3], // Relative to the player
+}
+
+impl GymObservation {
+    // Flat features for the player and nearest_asteroids asteroids, missing asteroids are zeros
+    pub fn features(&self, nearest_asteroids: usize) -> Vec<f32> {
+        let mut features =
+            Vec::with_capacity(PLAYER_FEATURES + nearest_asteroids * ASTEROID_FEATURES);
+        features.push(self.player.altitude);
+        features.extend(self.player.velocity);
+        features.push(self.player.reload);
+
+        for index in 0..nearest_asteroids {
+            match self.asteroids.get(index) {
+                Some(asteroid) => {
+                    features.extend(asteroid.position);
+                    features.extend(asteroid.velocity);
+                }
+                None => features.extend([0.0; ASTEROID_FEATURES]),
+            }
+        }
+        features
+    }
+}
+
+pub struct GymEnv {
+    config: GymConfig,
+    app: App,
+    score: i32,
+    survival_ticks: u32,
+}
+
+impl GymEnv {
+    pub fn new(config: GymConfig, seed: u64) -> Self {
+        let app = gym_app(&config.settings, seed);
+        let mut env = Self {
+            config,
+            app,
+            score: 0,
+            survival_ticks: 0,
+        };
+        env.score = env.app.world.resource::<Score>().0;
+        env
+    }
+
+    pub fn config(&self) -> &GymConfig {
+        &self.config
+    }
+
+    // Starts a new round played with the seed
+    pub fn reset(&mut self, seed: u64) -> GymObservation {
+        self.app = gym_app(&self.config.settings, seed);
+        self.score = self.app.world.resource::<Score>().0;
+        self.survival_ticks = 0;
+        self.observe()
+    }
+
+    // Runs ticks_per_step ticks with the action, stepping a round that's over does nothing
+    pub fn step(&mut self, action: GymAction) -> (GymObservation, f32, bool) {
+        if is_game_over(&self.app) {
+            return (self.observe(), 0.0, true);
+        }
+
+        self.apply_action(action);
+
+        let mut survived = 0;
+        for _ in 0..self.config.ticks_per_step {
+            self.app.update();
+            if is_game_over(&self.app) {
+                break;
+            }
+            survived += 1;
+        }
+        self.survival_ticks += survived;
+
+        let score = self.app.world.resource::<Score>().0;
+        let reward = (score - self.score) as f32
+            + self.config.survival_reward * survived as f32 / HEADLESS_TICK_RATE as f32;
+        self.score = score;
+
+        (self.observe(), reward, is_game_over(&self.app))
+    }
+
+    fn apply_action(&mut self, action: GymAction) {
+        let Some(agent) = self.agent() else {
+            return;
+        };
+        let frame = self.agent_frame(agent);
+        let mut bot_controller = self
+            .app
+            .world
+            .get_mut::<BotController>(agent)
+            .expect("the agent is a bot player");
+
+        // The cannon faces away from where its shots go
+        if let Some(aim) = action.aim.map(Vec2::from).filter(|aim| *aim != Vec2::ZERO) {
+            bot_controller.aim = Some(-(frame.right() * aim.x + frame.forward * aim.y));
+        }
+        bot_controller.fire = action.fire;
+    }
+
+    fn observe(&mut self) -> GymObservation {
+        let score = self.app.world.resource::<Score>().0;
+        let Some(agent) = self.agent() else {
+            return GymObservation {
+                player: PlayerObservation {
+                    altitude: 0.0,
+                    velocity: [0.0; 3],
+                    reload: 0.0,
+                },
+                asteroids: Vec::new(),
+                score,
+                survival_ticks: self.survival_ticks,
+            };
+        };
+
+        let frame = self.agent_frame(agent);
+        let world = &self.app.world;
+        let position = world.get::<Transform>(agent).unwrap().translation;
+        let velocity = world.get::<Velocity>(agent).unwrap().linvel;
+        let reload = world.get::<ShootTimer>(agent).unwrap().0.percent();
+        let surface = world.resource::<PlanetShape>().surface_point(position);
+        let to_frame = |vector: Vec3| {
+            [
+                vector.dot(frame.right()),
+                vector.dot(frame.forward),
+                vector.dot(frame.normal),
+            ]
+        };
+
+        let mut asteroids = self
+            .app
+            .world
+            .query_filtered::<(&Transform, &Velocity), With<Asteroid>>()
+            .iter(&self.app.world)
+            .map(|(transform, asteroid_velocity)| {
+                (
+                    transform.translation - position,
+                    asteroid_velocity.linvel - velocity,
+                )
+            })
+            .collect::<Vec<_>>();
+        asteroids.sort_by(|(a, _), (b, _)| a.length_squared().total_cmp(&b.length_squared()));
+        asteroids.truncate(self.config.nearest_asteroids);
+
+        GymObservation {
+            player: PlayerObservation {
+                altitude: position.length() - surface.length(),
+                velocity: to_frame(velocity),
+                reload,
+            },
+            asteroids: asteroids
+                .into_iter()
+                .map(|(offset, relative_velocity)| AsteroidObservation {
+                    position: to_frame(offset),
+                    velocity: to_frame(relative_velocity),
+                })
+                .collect(),
+            score,
+            survival_ticks: self.survival_ticks,
+        }
+    }
+
+    fn agent(&mut self) -> Option<Entity> {
+        self.app
+            .world
+            .query_filtered::<Entity, With<GymAgent>>()
+            .iter(&self.app.world)
+            .next()
+    }
+
+    // The agent's tangent frame, the forward direction is the one handle_player_input keeps moving with the player
+    fn agent_frame(&self, agent: Entity) -> SphericalFrame {
+        let world = &self.app.world;
+        let position = world.get::<Transform>(agent).unwrap().translation;
+        let forward = world
+            .get::<PlayerMeshDesiredTransform>(agent)
+            .unwrap()
+            .local_forward;
+        let normal = world.resource::<PlanetShape>().surface_normal(position);
+
+        SphericalFrame::new(
+            normal,
+            project_on_tangent_plane(forward, normal).unwrap_or(forward),
+        )
+    }
+}
+
+// PROTOCOL
+
+// Line delimited JSON protocol
+// Requests:  {"reset": {"seed": 1}}  or  {"step": {"aim": [0.0, 1.0], "fire": true}}
+// Responses: {"observation": {...}}  or  {"observation": {...}, "reward": 0.07, "done": false}  or  {"error": "..."}
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum GymRequest {
+    Reset { seed: u64 },
+    Step(GymAction),
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(untagged)]
+pub enum GymResponse {
+    Step {
+        observation: GymObservation,
+        reward: f32,
+        done: bool,
+    },
+    Reset {
+        observation: GymObservation,
+    },
+    Error {
+        error: String,
+    },
+}
+
+impl GymEnv {
+    pub fn handle(&mut self, request: GymRequest) -> GymResponse {
+        match request {
+            GymRequest::Reset { seed } => GymResponse::Reset {
+                observation: self.reset(seed),
+            },
+            GymRequest::Step(action) => {
+                let (observation, reward, done) = self.step(action);
+                GymResponse::Step {
+                    observation,
+                    reward,
+                    done,
+                }
+            }
+        }
+    }
+}
+
+// Answers every request line with a response line until the input ends, requests that can't be parsed are answered
+// with an error and otherwise ignored
+pub fn serve(env: &mut GymEnv, input: impl BufRead, mut output: impl Write) -> std::io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<GymRequest>(&line) {
+            Ok(request) => env.handle(request),
+            Err(err) => GymResponse::Error {
+                error: format!("Invalid request: {}", err),
+            },
+        };
+        serde_json::to_writer(&mut output, &response)?;
+        writeln!(output)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+// HELPER FUNCTIONS
+
+// Headless round that has started, with the first player handed to the agent
+fn gym_app(settings: &Settings, seed: u64) -> App {
+    let mut app = headless_app(settings.clone(), seed);
+    app.update();
+
+    let agent = app
+        .world
+        .query_filtered::<(Entity, &PlayerId), With<PlayerCollider>>()
+        .iter(&app.world)
+        .find(|(_, player_id)| **player_id == PlayerId::FIRST)
+        .map(|(entity, _)| entity);
+    if let Some(agent) = agent {
+        app.world.entity_mut(agent).insert(GymAgent);
+        let mut bot_controller = app.world.get_mut::<BotController>(agent).unwrap();
+        bot_controller.aim = None;
+        bot_controller.fire = false;
+    }
+
+    app
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn aim_at_nearest(observation: &GymObservation) -> GymAction {
+        GymAction {
+            aim: observation
+                .asteroids
+                .first()
+                .map(|asteroid| [asteroid.position[0], asteroid.position[1]]),
+            fire: true,
+        }
+    }
+
+    #[test]
+    fn seeded_episodes_play_out_the_same() {
+        let mut env = GymEnv::new(GymConfig::default(), 3);
+        let play = |env: &mut GymEnv| {
+            let mut observation = env.reset(5);
+            let mut rewards = Vec::new();
+            for _ in 0..150 {
+                let (next_observation, reward, done) = env.step(aim_at_nearest(&observation));
+                observation = next_observation;
+                rewards.push(reward);
+                if done {
+                    break;
+                }
+            }
+            (observation, rewards)
+        };
+
+        assert_eq!(play(&mut env), play(&mut env));
+    }
+
+    #[test]
+    fn steps_reward_survival_and_score() {
+        let config = GymConfig::default();
+        let mut env = GymEnv::new(config.clone(), 1);
+        let observation = env.reset(1);
+        assert_eq!(observation.survival_ticks, 0);
+        assert_eq!(
+            observation.features(config.nearest_asteroids).len(),
+            PLAYER_FEATURES + config.nearest_asteroids * ASTEROID_FEATURES
+        );
+
+        let (observation, reward, done) = env.step(GymAction::default());
+        assert!(!done);
+        assert_eq!(observation.survival_ticks, config.ticks_per_step);
+        let survival =
+            config.survival_reward * config.ticks_per_step as f32 / HEADLESS_TICK_RATE as f32;
+        assert!((reward - survival).abs() < 1e-6);
+
+        // Asteroids are seen in the player's tangent frame, so their distances are kept
+        assert!(observation.asteroids.windows(2).all(|pair| {
+            Vec3::from(pair[0].position).length() <= Vec3::from(pair[1].position).length()
+        }));
+    }
+
+    #[test]
+    fn firing_recoils_the_agent_away_from_its_aim() {
+        let mut env = GymEnv::new(GymConfig::default(), 2);
+        env.step(GymAction::default());
+        let (observation, _, _) = env.step(GymAction {
+            aim: Some([0.0, 1.0]),
+            fire: true,
+        });
+
+        let [right, forward, _] = observation.player.velocity;
+        assert!(forward < 0.0 && forward.abs() > right.abs());
+        assert!(observation.player.reload < 1.0);
+    }
+
+    #[test]
+    fn protocol_answers_every_request_line() {
+        let mut env = GymEnv::new(GymConfig::default(), 0);
+        let input = "{\"reset\": {\"seed\": 4}}\n\n{\"step\": {\"aim\": [1.0, 0.0], \"fire\": true}}\nnonsense\n";
+        let mut output = Vec::new();
+        serve(&mut env, Cursor::new(input), &mut output).unwrap();
+
+        let responses = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<GymResponse>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            responses.as_slice(),
+            [
+                GymResponse::Reset { .. },
+                GymResponse::Step { done: false, .. },
+                GymResponse::Error { .. }
+            ]
+        ));
+    }
+}
@@ -0,0 +1,185 @@
+// Local game without a window, rendering, audio or input devices, for soak tests and other runs that don't need to be
+// watched. There are no local players, every player is a bot, and every update advances the game by one fixed tick so
+// a seed always plays out the same way.
+
+use bevy::{
+    asset::{AssetApp, AssetPlugin},
+    hierarchy::HierarchyPlugin,
+    input::InputPlugin,
+    prelude::{
+        apply_deferred, in_state, resource_exists, App, IntoSystemConfigs, Mesh, MinimalPlugins,
+        NextState, OnEnter, StandardMaterial, State, TransformPlugin, Update,
+    },
+    scene::ScenePlugin,
+    time::TimeUpdateStrategy,
+};
+use bevy_rapier3d::prelude::{NoUserData, RapierConfiguration, RapierPhysicsPlugin, TimestepMode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::{
+    asteroids::{setup_asteroids, spawn_asteroids},
+    bot::{setup_bot_players, update_bots},
+    cannon_ball::shoot_cannon_ball,
+    common::{
+        despawn_destroyed, gravity, handle_collisions, setup_round, update_combo,
+        update_survival_time, AsteroidDestroyedEvent, DestroyEvent, GameSeed, GameState,
+        RequestedSeed, Score,
+    },
+    input::{handle_player_input, ShootEvent},
+    level::{setup_level, Levels, LEVELS_DIRECTORY},
+    planet::setup_planet,
+    player::{apply_player_collider_impulse, setup_player},
+    replay::{
+        play_replay_inputs, record_replay_inputs, setup_replay_players, start_replay_recording,
+        ReplayPlayback, ReplayRecorder,
+    },
+    server::setup_server_assets,
+    settings::Settings,
+    versus::{setup_versus_round, update_versus_round, VersusScoreboard},
+};
+
+// CONSTANTS
+
+pub const HEADLESS_TICK_RATE: f64 = 60.0;
+
+// What a headless run prints when it's done
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct HeadlessSummary {
+    pub seed: u64,
+    pub score: i32,
+    pub survival_ticks: u32,
+    pub game_over: bool, // False if the tick limit was reached first
+}
+
+// PLUGINS
+
+// App playing a round with the given settings and seed, the settings' local players are played by bots too. The round
+// starts with the first update and the app stays in GameState::GameOver once it's over
+pub fn headless_app(settings: Settings, seed: u64) -> App {
+    let mut app = App::new();
+
+    add_headless_plugins(&mut app);
+    app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default());
+
+    insert_fixed_ticks(&mut app);
+
+    // Events
+    app.add_event::<ShootEvent>();
+    app.add_event::<AsteroidDestroyedEvent>();
+    app.add_event::<DestroyEvent>();
+
+    // Resources
+    app.insert_resource(Score(0));
+    app.insert_resource(Settings {
+        bots: settings.player_count(),
+        players: 0,
+        ..settings
+    });
+    app.insert_resource(RequestedSeed(seed));
+    app.insert_resource(Levels::load(LEVELS_DIRECTORY));
+
+    // State
+    app.add_state::<GameState>();
+    app.insert_resource(NextState(Some(GameState::Playing)));
+
+    // GameState::Playing systems
+    app.add_systems(
+        OnEnter(GameState::Playing),
+        (
+            setup_server_assets,
+            setup_level,
+            setup_round,
+            setup_versus_round,
+            apply_deferred,
+            start_replay_recording.run_if(resource_exists::<ReplayRecorder>()),
+            setup_planet,
+            apply_deferred,
+            setup_player,
+            apply_deferred,
+            setup_bot_players,
+            apply_deferred,
+            setup_replay_players.run_if(resource_exists::<ReplayPlayback>()),
+            setup_asteroids,
+        )
+            .chain(),
+    )
+    .add_systems(
+        Update,
+        (
+            gravity,
+            update_bots,
+            play_replay_inputs.run_if(resource_exists::<ReplayPlayback>()),
+            handle_player_input,
+            record_replay_inputs.run_if(resource_exists::<ReplayRecorder>()),
+            apply_player_collider_impulse,
+            shoot_cannon_ball,
+            handle_collisions,
+            despawn_destroyed,
+            spawn_asteroids,
+            update_survival_time,
+            update_combo,
+            update_versus_round.run_if(resource_exists::<VersusScoreboard>()),
+        )
+            .chain()
+            .run_if(in_state(GameState::Playing)),
+    );
+
+    app
+}
+
+// HELPER FUNCTIONS
+
+// Rapier's systems need the mesh and scene assets even though nothing is drawn, and reading players' input needs the
+// input resources even though there are no input devices
+pub fn add_headless_plugins(app: &mut App) {
+    app.add_plugins((
+        MinimalPlugins,
+        TransformPlugin,
+        HierarchyPlugin,
+        InputPlugin,
+        AssetPlugin::default(),
+        ScenePlugin,
+    ))
+    .init_asset::<Mesh>()
+    .init_asset::<StandardMaterial>();
+}
+
+// Time and physics advance by exactly one tick per update, which makes a round reproducible
+pub fn insert_fixed_ticks(app: &mut App) {
+    let tick = Duration::from_secs_f64(1.0 / HEADLESS_TICK_RATE);
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(tick));
+    app.insert_resource(RapierConfiguration {
+        timestep_mode: TimestepMode::Fixed {
+            dt: tick.as_secs_f32(),
+            substeps: 1,
+        },
+        ..RapierConfiguration::default()
+    });
+}
+
+// Plays the round until it's over or the tick limit is reached
+pub fn run_headless(app: &mut App, max_ticks: Option<u32>) -> HeadlessSummary {
+    let mut survival_ticks = 0;
+    let mut ticks = 0;
+    while !is_game_over(app) && max_ticks.is_none_or(|max_ticks| ticks < max_ticks) {
+        app.update();
+        ticks += 1;
+        if !is_game_over(app) {
+            survival_ticks += 1;
+        }
+    }
+
+    HeadlessSummary {
+        seed: app.world.resource::<GameSeed>().0,
+        score: app.world.resource::<Score>().0,
+        survival_ticks,
+        game_over: is_game_over(app),
+    }
+}
+
+// The round is over as soon as the tick that ended it, not only once the state has changed on the next update
+pub fn is_game_over(app: &App) -> bool {
+    *app.world.resource::<State<GameState>>().get() == GameState::GameOver
+        || app.world.resource::<NextState<GameState>>().0 == Some(GameState::GameOver)
+}
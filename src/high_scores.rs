@@ -0,0 +1,425 @@
+// High scores are kept in a local file with one entry per line, so a corrupt line only loses that entry.
+// When a round ends with a qualifying score, the player enters a name on the game over screen.
+
+use bevy::{
+    log::warn,
+    prelude::{
+        Commands, DetectChanges, EventReader, Input, KeyCode, Query, Res, ResMut, Resource, With,
+    },
+    text::Text,
+    window::ReceivedCharacter,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    asteroids::AsteroidWave,
+    common::{format_duration, GameSeed, Score, SurvivalTime},
+    ui::NameInputUI,
+};
+
+// CONSTANTS
+
+pub const HIGH_SCORES_PATH: &str = "high_scores.ron";
+pub const MAX_HIGH_SCORES: usize = 10;
+pub const MAX_NAME_LENGTH: usize = 12;
+pub const DEFAULT_PLAYER_NAME: &str = "Player";
+
+// RESOURCES
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HighScoreEntry {
+    pub name: String,
+    pub score: i32,
+    pub date: u64, // Seconds since the unix epoch
+    pub seed: u64,
+    pub wave: u32,
+    pub duration: f32, // Seconds survived
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct HighScores {
+    pub entries: Vec<HighScoreEntry>,
+}
+
+// Entry waiting for the player to finish typing their name
+#[derive(Resource)]
+pub struct PendingHighScore {
+    pub entry: HighScoreEntry,
+}
+
+impl HighScores {
+    // Loads the high score table, skipping lines that can't be parsed
+    pub fn load(path: &str) -> Self {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return Self::default(),
+        };
+
+        let mut entries = Vec::new();
+        for (line_number, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match ron::from_str::<HighScoreEntry>(line) {
+                Ok(entry) if entry.is_valid() => entries.push(entry),
+                _ => warn!(
+                    "Ignoring corrupt high score entry on line {} of {}",
+                    line_number + 1,
+                    path
+                ),
+            }
+        }
+
+        let mut high_scores = Self { entries };
+        high_scores.sort_and_truncate();
+        high_scores
+    }
+
+    // Writes the table to a temporary file first and moves it over the old one, so a crash while saving can't leave
+    // a half written table behind
+    pub fn save(&self, path: &str) {
+        let mut contents = String::new();
+        for entry in &self.entries {
+            match ron::to_string(entry) {
+                Ok(line) => {
+                    contents.push_str(&line);
+                    contents.push('\n');
+                }
+                Err(err) => warn!("Failed to serialize high score entry: {}", err),
+            }
+        }
+
+        let temporary_path = format!("{}.tmp", path);
+        if let Err(err) =
+            fs::write(&temporary_path, contents).and_then(|_| fs::rename(&temporary_path, path))
+        {
+            warn!("Failed to save high scores to {}: {}", path, err);
+        }
+    }
+
+    // Checks if a score would make it into the table
+    pub fn qualifies(&self, score: i32) -> bool {
+        score > 0
+            && (self.entries.len() < MAX_HIGH_SCORES
+                || self.entries.iter().any(|entry| score > entry.score))
+    }
+
+    // Inserts an entry and returns its rank (starting from 1) if it stayed in the table
+    pub fn insert(&mut self, entry: HighScoreEntry) -> Option<usize> {
+        // Entries with the same score are ranked by who got there first
+        let index = self
+            .entries
+            .iter()
+            .position(|existing| entry.score > existing.score)
+            .unwrap_or(self.entries.len());
+
+        if index >= MAX_HIGH_SCORES {
+            return None;
+        }
+
+        self.entries.insert(index, entry);
+        self.entries.truncate(MAX_HIGH_SCORES);
+
+        Some(index + 1)
+    }
+
+    fn sort_and_truncate(&mut self) {
+        // Stable sort keeps the file order for equal scores
+        self.entries.sort_by_key(|entry| Reverse(entry.score));
+        self.entries.truncate(MAX_HIGH_SCORES);
+    }
+}
+
+impl HighScoreEntry {
+    fn is_valid(&self) -> bool {
+        self.score >= 0 && self.duration.is_finite() && self.duration >= 0.0
+    }
+
+    // Formats the date as YYYY-MM-DD (UTC)
+    pub fn formatted_date(&self) -> String {
+        let (year, month, day) = civil_from_days((self.date / 86_400) as i64);
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+
+    // Formats the duration as M:SS
+    pub fn formatted_duration(&self) -> String {
+        format_duration(self.duration)
+    }
+}
+
+// STARTUP SYSTEMS
+
+pub fn setup_high_scores(mut commands: Commands) {
+    commands.insert_resource(HighScores::load(HIGH_SCORES_PATH));
+}
+
+// Creates a pending entry if the final score qualifies for the high score table
+pub fn check_high_score(
+    mut commands: Commands,
+    high_scores: Res<HighScores>,
+    score: Res<Score>,
+    seed: Res<GameSeed>,
+    wave: Res<AsteroidWave>,
+    survival_time: Res<SurvivalTime>,
+) {
+    if !high_scores.qualifies(score.0) {
+        return;
+    }
+
+    commands.insert_resource(PendingHighScore {
+        entry: HighScoreEntry {
+            name: String::new(),
+            score: score.0,
+            date: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+            seed: seed.0,
+            wave: wave.0,
+            duration: survival_time.0.elapsed_secs(),
+        },
+    });
+}
+
+// SYSTEMS
+
+// Handles typing the player's name for a pending high score, and submits it on enter
+pub fn handle_name_input(
+    mut commands: Commands,
+    pending: Option<ResMut<PendingHighScore>>,
+    mut high_scores: ResMut<HighScores>,
+    keys: Res<Input<KeyCode>>,
+    mut ev_character: EventReader<ReceivedCharacter>,
+    mut name_input_query: Query<&mut Text, With<NameInputUI>>,
+) {
+    let Some(mut pending) = pending else {
+        ev_character.clear();
+        return;
+    };
+
+    for ev in ev_character.read() {
+        if !ev.char.is_control() && pending.entry.name.chars().count() < MAX_NAME_LENGTH {
+            pending.entry.name.push(ev.char);
+        }
+    }
+
+    if keys.just_pressed(KeyCode::Back) {
+        pending.entry.name.pop();
+    }
+
+    if keys.just_pressed(KeyCode::Return) {
+        let rank = submit_high_score(&mut high_scores, &pending);
+        commands.remove_resource::<PendingHighScore>();
+
+        for mut text in name_input_query.iter_mut() {
+            text.sections[0].value = match rank {
+                Some(rank) => format!("Saved as #{}", rank),
+                None => "Not saved".to_string(),
+            };
+        }
+
+        return;
+    }
+
+    if pending.is_changed() {
+        for mut text in name_input_query.iter_mut() {
+            text.sections[0].value = format!("{}_", pending.entry.name);
+        }
+    }
+}
+
+// CLEANUP SYSTEMS
+
+// Saves a pending entry with the default name if the player left the game over screen without submitting it
+pub fn commit_pending_high_score(
+    mut commands: Commands,
+    pending: Option<Res<PendingHighScore>>,
+    mut high_scores: ResMut<HighScores>,
+) {
+    if let Some(pending) = pending {
+        submit_high_score(&mut high_scores, &pending);
+        commands.remove_resource::<PendingHighScore>();
+    }
+}
+
+// HELPER FUNCTIONS
+
+fn submit_high_score(high_scores: &mut HighScores, pending: &PendingHighScore) -> Option<usize> {
+    let mut entry = pending.entry.clone();
+    entry.name = entry.name.trim().to_string();
+    if entry.name.is_empty() {
+        entry.name = DEFAULT_PLAYER_NAME.to_string();
+    }
+
+    let rank = high_scores.insert(entry);
+    high_scores.save(HIGH_SCORES_PATH);
+
+    rank
+}
+
+// Converts days since the unix epoch to a (year, month, day) date in the proleptic Gregorian calendar
+// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn entry(name: &str, score: i32) -> HighScoreEntry {
+        HighScoreEntry {
+            name: name.to_string(),
+            score,
+            date: 0,
+            seed: 0,
+            wave: 1,
+            duration: 60.0,
+        }
+    }
+
+    fn full_table() -> HighScores {
+        HighScores {
+            entries: (0..MAX_HIGH_SCORES)
+                .map(|index| entry("Full", 1000 - index as i32 * 100))
+                .collect(),
+        }
+    }
+
+    // Path in the system's temporary directory that's unique to the test
+    fn temporary_path(test: &str) -> String {
+        env::temp_dir()
+            .join(format!("loose_cannon_{}_{}.ron", test, std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn load_skips_corrupt_and_invalid_lines() {
+        let path = temporary_path("load");
+        let lines = [
+            ron::to_string(&entry("Low", 10)).unwrap(),
+            "not a high score".to_string(),
+            String::new(),
+            ron::to_string(&entry("Negative", -5)).unwrap(),
+            ron::to_string(&HighScoreEntry {
+                duration: f32::NAN,
+                ..entry("Forever", 50)
+            })
+            .unwrap(),
+            ron::to_string(&entry("High", 20)).unwrap(),
+        ];
+        fs::write(&path, lines.join("\n")).unwrap();
+
+        let high_scores = HighScores::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        let names = high_scores
+            .entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["High", "Low"]);
+    }
+
+    #[test]
+    fn saved_tables_load_the_same() {
+        let path = temporary_path("save");
+        let high_scores = full_table();
+        high_scores.save(&path);
+        high_scores.save(&path);
+
+        let loaded = HighScores::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.entries, high_scores.entries);
+        assert!(fs::metadata(format!("{}.tmp", path)).is_err());
+    }
+
+    #[test]
+    fn missing_files_load_an_empty_table() {
+        assert!(HighScores::load(&temporary_path("missing"))
+            .entries
+            .is_empty());
+    }
+
+    #[test]
+    fn equal_scores_rank_below_earlier_entries() {
+        let mut high_scores = HighScores::default();
+        assert_eq!(high_scores.insert(entry("First", 100)), Some(1));
+        assert_eq!(high_scores.insert(entry("Second", 100)), Some(2));
+        assert_eq!(high_scores.insert(entry("Best", 200)), Some(1));
+
+        let names = high_scores
+            .entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Best", "First", "Second"]);
+    }
+
+    #[test]
+    fn full_tables_only_take_better_scores() {
+        let mut high_scores = full_table();
+        let lowest = high_scores.entries.last().unwrap().score;
+
+        assert!(!high_scores.qualifies(lowest));
+        assert_eq!(high_scores.insert(entry("Tied", lowest)), None);
+        assert!(high_scores.qualifies(lowest + 1));
+        assert_eq!(
+            high_scores.insert(entry("Better", lowest + 1)),
+            Some(MAX_HIGH_SCORES)
+        );
+
+        assert_eq!(high_scores.entries.len(), MAX_HIGH_SCORES);
+        assert_eq!(high_scores.entries.last().unwrap().name, "Better");
+    }
+
+    #[test]
+    fn only_positive_scores_qualify() {
+        assert!(!HighScores::default().qualifies(0));
+        assert!(HighScores::default().qualifies(1));
+    }
+
+    #[test]
+    fn days_convert_to_known_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(59), (1970, 3, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(11_017), (2000, 3, 1));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        assert_eq!(civil_from_days(20_454), (2026, 1, 1));
+    }
+
+    #[test]
+    fn dates_are_formatted_from_seconds() {
+        let entry = HighScoreEntry {
+            date: 951_782_400 + 86_399,
+            ..entry("Leap", 1)
+        };
+        assert_eq!(entry.formatted_date(), "2000-02-29");
+    }
+}
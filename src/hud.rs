@@ -0,0 +1,509 @@
+// In game HUD showing the score, combo, wave, survival time and reload progress.
+// Each indicator is updated by its own system that only runs when the resource or component it displays has changed.
+// With more than one local player every player has their own score and reload bar, and in versus rounds a scoreboard
+// ranking the players replaces the score.
+
+use bevy::{
+    prelude::{
+        BuildChildren, Camera, Changed, Color, Commands, Component, DespawnRecursiveExt, Entity,
+        EventReader, GlobalTransform, Name, NodeBundle, Query, Res, TextBundle, Time, Timer, Vec3,
+        Visibility, With, Without,
+    },
+    text::{Text, TextStyle},
+    time::TimerMode,
+    ui::{
+        AlignItems, BackgroundColor, FlexDirection, JustifyContent, PositionType, Style, UiRect,
+        Val,
+    },
+    utils::default,
+};
+
+use crate::{
+    asteroids::AsteroidWave,
+    client::{local_player_count, NetworkClient},
+    common::{
+        format_duration, AsteroidDestroyedEvent, Combo, PrimaryCamera, Score, SurvivalSeconds,
+    },
+    game_assets::GameAssets,
+    input::ShootTimer,
+    player::{PlayerId, PlayerScore},
+    rollback::RollbackSession,
+    settings::{Settings, VersusRules},
+    versus::VersusScoreboard,
+};
+
+// CONSTANTS
+
+const SCORE_POPUP_DURATION: f32 = 1.0;
+const SCORE_POPUP_RISE: f32 = 40.0; // Pixels the pop-up floats up over its lifetime
+const RELOAD_BAR_WIDTH: f32 = 150.0;
+const RELOAD_BAR_HEIGHT: f32 = 14.0;
+const RELOADING_COLOR: Color = Color::rgb(1.0, 0.6, 0.2);
+const RELOADED_COLOR: Color = Color::rgb(0.4, 1.0, 0.4);
+
+// COMPONENTS
+
+#[derive(Component)]
+pub struct ScoreUI {}
+
+#[derive(Component)]
+pub struct PlayerScoreUI {
+    pub player: PlayerId,
+}
+
+// Row of the versus scoreboard showing whichever player is currently in that place
+#[derive(Component)]
+pub struct ScoreboardUI {
+    pub rank: usize,
+}
+
+// Time left or points needed to win the versus round
+#[derive(Component)]
+pub struct RoundLimitUI {}
+
+#[derive(Component)]
+pub struct ComboUI {}
+
+#[derive(Component)]
+pub struct WaveUI {}
+
+#[derive(Component)]
+pub struct SurvivalTimeUI {}
+
+// Background of the reload bar
+#[derive(Component)]
+pub struct ReloadUI {}
+
+// Fill of a player's reload bar, its width is the reload progress
+#[derive(Component)]
+pub struct ReloadBarUI {
+    pub player: PlayerId,
+}
+
+#[derive(Component)]
+pub struct ScorePopup {
+    pub world_position: Vec3,
+    pub timer: Timer,
+}
+
+// STARTUP SYSTEMS
+
+pub fn setup_game_ui(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    settings: Res<Settings>,
+    network_client: Option<Res<NetworkClient>>,
+    rollback_session: Option<Res<RollbackSession>>,
+    versus_scoreboard: Option<Res<VersusScoreboard>>,
+) {
+    let networked = network_client.is_some() || rollback_session.is_some();
+    let player_ids = (0..local_player_count(&settings, networked) as usize)
+        .map(PlayerId)
+        .collect::<Vec<_>>();
+    let multiplayer = player_ids.len() > 1;
+
+    let text_style = |font_size: f32| TextStyle {
+        font: game_assets.font.clone(),
+        font_size,
+        color: Color::WHITE,
+    };
+
+    // In Game UI
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                justify_content: JustifyContent::SpaceBetween,
+                align_items: AlignItems::FlexStart,
+                width: Val::Percent(100.0),
+                padding: UiRect::all(Val::Px(15.0)),
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Name::new("In_Game_UI"))
+        .with_children(|parent| {
+            // Score and combo
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::FlexStart,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert(Name::new("Score_Panel"))
+                .with_children(|parent| {
+                    if let Some(scoreboard) = versus_scoreboard.as_deref() {
+                        parent
+                            .spawn(
+                                TextBundle::from_section(
+                                    round_limit_text(scoreboard),
+                                    text_style(30.0),
+                                )
+                                .with_style(Style {
+                                    margin: UiRect::all(Val::Px(5.0)),
+                                    ..default()
+                                }),
+                            )
+                            .insert(Name::new("Round_Limit_Indicator"))
+                            .insert(RoundLimitUI {});
+
+                        for rank in 0..scoreboard.standings.len() {
+                            parent
+                                .spawn(TextBundle::from_section("", text_style(24.0)).with_style(
+                                    Style {
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        ..default()
+                                    },
+                                ))
+                                .insert(Name::new(format!("Scoreboard_Row_{}", rank + 1)))
+                                .insert(ScoreboardUI { rank });
+                        }
+                    } else {
+                        parent
+                            .spawn(
+                                TextBundle::from_section("Score: 0", text_style(30.0)).with_style(
+                                    Style {
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        ..default()
+                                    },
+                                ),
+                            )
+                            .insert(Name::new("Score_Indicator"))
+                            .insert(ScoreUI {});
+                    }
+
+                    if multiplayer && versus_scoreboard.is_none() {
+                        for player_id in player_ids.iter() {
+                            parent
+                                .spawn(
+                                    TextBundle::from_section(
+                                        format!("{}: 0", player_id.label()),
+                                        text_style(24.0),
+                                    )
+                                    .with_style(Style {
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        ..default()
+                                    }),
+                                )
+                                .insert(Name::new(format!("Score_Indicator_{}", player_id.label())))
+                                .insert(PlayerScoreUI { player: *player_id });
+                        }
+                    }
+
+                    parent
+                        .spawn(
+                            TextBundle::from_section("", text_style(24.0)).with_style(Style {
+                                margin: UiRect::all(Val::Px(5.0)),
+                                ..default()
+                            }),
+                        )
+                        .insert(Name::new("Combo_Indicator"))
+                        .insert(ComboUI {})
+                        .insert(Visibility::Hidden);
+                });
+
+            // Wave and survival time
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert(Name::new("Round_Panel"))
+                .with_children(|parent| {
+                    parent
+                        .spawn(
+                            TextBundle::from_section("Wave 0", text_style(30.0)).with_style(
+                                Style {
+                                    margin: UiRect::all(Val::Px(5.0)),
+                                    ..default()
+                                },
+                            ),
+                        )
+                        .insert(Name::new("Wave_Indicator"))
+                        .insert(WaveUI {});
+
+                    parent
+                        .spawn(
+                            TextBundle::from_section("0:00", text_style(24.0)).with_style(Style {
+                                margin: UiRect::all(Val::Px(5.0)),
+                                ..default()
+                            }),
+                        )
+                        .insert(Name::new("Survival_Time_Indicator"))
+                        .insert(SurvivalTimeUI {});
+                });
+
+            // Reload indicator
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::FlexEnd,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert(Name::new("Reload_Panel"))
+                .with_children(|parent| {
+                    for player_id in player_ids.iter() {
+                        let label = if multiplayer {
+                            format!("{} Cannon", player_id.label())
+                        } else {
+                            "Cannon".to_string()
+                        };
+                        parent.spawn(
+                            TextBundle::from_section(label, text_style(24.0)).with_style(Style {
+                                margin: UiRect::all(Val::Px(5.0)),
+                                ..default()
+                            }),
+                        );
+
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    width: Val::Px(RELOAD_BAR_WIDTH),
+                                    height: Val::Px(RELOAD_BAR_HEIGHT),
+                                    margin: UiRect::all(Val::Px(5.0)),
+                                    ..default()
+                                },
+                                background_color: Color::rgba(0.0, 0.0, 0.0, 0.5).into(),
+                                ..default()
+                            })
+                            .insert(Name::new("Reload_Indicator"))
+                            .insert(ReloadUI {})
+                            .with_children(|parent| {
+                                parent
+                                    .spawn(NodeBundle {
+                                        style: Style {
+                                            width: Val::Percent(100.0),
+                                            height: Val::Percent(100.0),
+                                            ..default()
+                                        },
+                                        background_color: RELOADED_COLOR.into(),
+                                        ..default()
+                                    })
+                                    .insert(Name::new("Reload_Bar"))
+                                    .insert(ReloadBarUI { player: *player_id });
+                            });
+                    }
+                });
+        });
+}
+
+// SYSTEMS
+
+// Runs only when the score has changed, the players' scores change along with it
+pub fn update_score_ui(
+    score: Res<Score>,
+    mut score_ui_query: Query<&mut Text, With<ScoreUI>>,
+    mut player_score_ui_query: Query<(&mut Text, &PlayerScoreUI), Without<ScoreUI>>,
+    player_query: Query<(&PlayerId, &PlayerScore)>,
+) {
+    for mut score_ui in score_ui_query.iter_mut() {
+        score_ui.sections[0].value = format!("Score: {}", score.0);
+    }
+
+    for (mut text, player_score_ui) in player_score_ui_query.iter_mut() {
+        if let Some((_, player_score)) = player_query
+            .iter()
+            .find(|(player_id, _)| **player_id == player_score_ui.player)
+        {
+            text.sections[0].value =
+                format!("{}: {}", player_score_ui.player.label(), player_score.0);
+        }
+    }
+}
+
+// Runs only when the versus scoreboard has changed, which is every frame since its clock is running
+pub fn update_scoreboard_ui(
+    scoreboard: Res<VersusScoreboard>,
+    mut scoreboard_ui_query: Query<(&mut Text, &ScoreboardUI)>,
+    mut round_limit_ui_query: Query<&mut Text, (With<RoundLimitUI>, Without<ScoreboardUI>)>,
+) {
+    let ranking = scoreboard.ranking();
+    for (mut text, scoreboard_ui) in scoreboard_ui_query.iter_mut() {
+        let Some(standing) = ranking.get(scoreboard_ui.rank) else {
+            continue;
+        };
+
+        let value = format!(
+            "{}. {}: {}",
+            scoreboard_ui.rank + 1,
+            standing.player.label(),
+            standing.points
+        );
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+
+    let value = round_limit_text(&scoreboard);
+    for mut text in round_limit_ui_query.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+// Runs only when the combo has changed
+pub fn update_combo_ui(
+    combo: Res<Combo>,
+    mut combo_ui_query: Query<(&mut Text, &mut Visibility), With<ComboUI>>,
+) {
+    for (mut text, mut visibility) in combo_ui_query.iter_mut() {
+        if combo.hits == 0 {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+
+        *visibility = Visibility::Visible;
+
+        let value = format!("Combo {} - x{}", combo.hits, combo.multiplier());
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+
+        // Fade out as the combo window runs out
+        let alpha = 0.4 + 0.6 * combo.timer.percent_left();
+        text.sections[0].style.color = Color::rgba(1.0, 0.85, 0.2, alpha);
+    }
+}
+
+// Runs only when the wave has changed
+pub fn update_wave_ui(wave: Res<AsteroidWave>, mut wave_ui_query: Query<&mut Text, With<WaveUI>>) {
+    for mut wave_ui in wave_ui_query.iter_mut() {
+        wave_ui.sections[0].value = format!("Wave {}", wave.0);
+    }
+}
+
+// Runs only when the whole seconds survived have changed, so once per second
+pub fn update_survival_time_ui(
+    survival_seconds: Res<SurvivalSeconds>,
+    mut survival_time_ui_query: Query<&mut Text, With<SurvivalTimeUI>>,
+) {
+    let value = format_duration(survival_seconds.0 as f32);
+
+    for mut survival_time_ui in survival_time_ui_query.iter_mut() {
+        if survival_time_ui.sections[0].value != value {
+            survival_time_ui.sections[0].value = value.clone();
+        }
+    }
+}
+
+// Only updates the bars of players whose shoot timer has changed, which is only while reloading
+pub fn update_reload_ui(
+    shoot_timer_query: Query<(&PlayerId, &ShootTimer), Changed<ShootTimer>>,
+    mut reload_bar_ui_query: Query<(&ReloadBarUI, &mut Style, &mut BackgroundColor)>,
+) {
+    for (player_id, shoot_timer) in shoot_timer_query.iter() {
+        for (_, mut style, mut color) in reload_bar_ui_query
+            .iter_mut()
+            .filter(|(reload_bar_ui, _, _)| reload_bar_ui.player == *player_id)
+        {
+            style.width = Val::Percent(shoot_timer.0.percent() * 100.0);
+            *color = if shoot_timer.0.finished() {
+                RELOADED_COLOR.into()
+            } else {
+                RELOADING_COLOR.into()
+            };
+        }
+    }
+}
+
+// Spawns a pop-up with the points gained where an asteroid was destroyed
+pub fn spawn_score_popups(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    mut ev_asteroid_destroyed: EventReader<AsteroidDestroyedEvent>,
+) {
+    for ev in ev_asteroid_destroyed.read() {
+        if ev.points <= 0 {
+            continue;
+        }
+
+        commands
+            .spawn(
+                TextBundle::from_section(
+                    format!("+{}", ev.points),
+                    TextStyle {
+                        font: game_assets.font.clone(),
+                        font_size: 28.0,
+                        color: Color::YELLOW,
+                    },
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    ..default()
+                }),
+            )
+            .insert(Name::new("Score_Popup"))
+            // Hidden until it's been positioned on screen
+            .insert(Visibility::Hidden)
+            .insert(ScorePopup {
+                world_position: ev.position,
+                timer: Timer::from_seconds(SCORE_POPUP_DURATION, TimerMode::Once),
+            });
+    }
+}
+
+// Keeps the pop-ups over the point where the asteroid was destroyed while they float up and fade out
+pub fn update_score_popups(
+    mut commands: Commands,
+    time: Res<Time>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<PrimaryCamera>>,
+    mut popup_query: Query<
+        (
+            Entity,
+            &mut ScorePopup,
+            &mut Style,
+            &mut Text,
+            &mut Visibility,
+        ),
+        Without<PrimaryCamera>,
+    >,
+) {
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+
+    for (entity, mut popup, mut style, mut text, mut visibility) in popup_query.iter_mut() {
+        if popup.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        match camera.world_to_viewport(camera_transform, popup.world_position) {
+            Some(viewport_position) => {
+                let rise = SCORE_POPUP_RISE * popup.timer.percent();
+                style.left = Val::Px(viewport_position.x);
+                style.top = Val::Px(viewport_position.y - rise);
+                *visibility = Visibility::Visible;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+
+        text.sections[0]
+            .style
+            .color
+            .set_a(popup.timer.percent_left());
+    }
+}
+
+// HELPER FUNCTIONS
+
+fn round_limit_text(scoreboard: &VersusScoreboard) -> String {
+    match scoreboard.rules {
+        VersusRules::FirstTo(points) => format!("First to {}", points),
+        _ => format!(
+            "{} left",
+            format_duration(scoreboard.remaining_seconds().unwrap_or(0.0).ceil())
+        ),
+    }
+}
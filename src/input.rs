@@ -1,7 +1,9 @@
 use bevy::{
     prelude::{
-        Camera, Commands, Event, EventWriter, GlobalTransform, Input, MouseButton, Query, Res,
-        ResMut, Resource, Time, Timer, Transform, Vec2, Vec3, Visibility, Window, With,
+        Axis, Camera, Commands, Component, Entity, Event, EventWriter, Gamepad, GamepadAxis,
+        GamepadAxisType, GamepadButton, GamepadButtonType, Gamepads, GlobalTransform, Input,
+        KeyCode, MouseButton, NextState, Query, Res, ResMut, State, Time, Timer, Transform, Vec2,
+        Vec3, Window, With, Without,
     },
     time::TimerMode,
     window::PrimaryWindow,
@@ -10,167 +12,336 @@ use bevy::{
 use bevy_rapier3d::prelude::{QueryFilter, RapierContext};
 
 use crate::{
+    bot::BotController,
+    camera::CameraController,
     cannon_ball::CANNON_BALL_INITIAL_OFFSET,
-    common::{PrimaryCamera, SHOW_DEBUG_LINES},
-    player::{PlayerCollider, PlayerMeshDesiredTransform, FIRE_DELAY},
-    ui::ReloadUI,
+    common::{GameState, PauseState},
+    planet::PlanetShape,
+    player::{PlayerCollider, PlayerId, PlayerMeshDesiredTransform, FIRE_DELAY},
+    settings::SettingsMenuState,
+    spherical_frame::{project_on_tangent_plane, SphericalFrame},
 };
 
-// RESOURCES
+// CONSTANTS
 
-#[derive(Resource)]
+const STICK_DEAD_ZONE: f32 = 0.3;
+
+// COMPONENTS
+
+// Device a player aims and shoots with
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InputDevice {
+    Mouse,            // Aims at the cursor, shoots with the left mouse button
+    Keyboard,         // Aims with the arrow keys, shoots with space
+    Gamepad(Gamepad), // Aims with the left stick, shoots with the south button or right trigger
+    Bot,              // Aims and shoots as its BotController decides
+}
+
+#[derive(Component)]
 pub struct PlayerInput {
-    pub last_valid_cursor_pos: Option<Vec2>,
+    pub device: InputDevice,
+    pub last_valid_cursor_pos: Option<Vec2>, // Relative to the player's viewport
 }
 
-#[derive(Resource)]
+// Time until the player's next cannon ball can be fired
+#[derive(Component)]
 pub struct ShootTimer(pub Timer);
 
 // EVENTS
 
 #[derive(Event)]
 pub struct ShootEvent {
+    pub shooter: Entity, // Player that shot
     pub position: Vec3,
     pub direction: Vec3,
 }
 
 // STARTUP SYSTEMS
 
-pub fn setup_player_input(mut commands: Commands) {
-    // Insert resource to keep track of player cursor position
-    commands.insert_resource(PlayerInput {
-        last_valid_cursor_pos: Option::None,
-    });
+// The first player uses the mouse, the others use the connected gamepads in order or the keyboard.
+// Players that are already controlled by a bot are left alone
+pub fn setup_player_input(
+    mut commands: Commands,
+    gamepads: Res<Gamepads>,
+    player_query: Query<(Entity, &PlayerId), (With<PlayerCollider>, Without<PlayerInput>)>,
+) {
+    let mut players = player_query.iter().collect::<Vec<_>>();
+    players.sort_by_key(|(_, player_id)| player_id.0);
+
+    let mut unused_gamepads = gamepads.iter();
+    for (entity, player_id) in players {
+        let device = if *player_id == PlayerId::FIRST {
+            InputDevice::Mouse
+        } else {
+            unused_gamepads
+                .next()
+                .map_or(InputDevice::Keyboard, InputDevice::Gamepad)
+        };
+
+        // Keep track of time until the next cannon ball can be fired, the first one can be fired right away
+        let mut timer = Timer::from_seconds(FIRE_DELAY, TimerMode::Once);
+        timer.tick(timer.duration());
 
-    // Insert resouce to keep track of time until the next cannon ball can be fired
-    let mut timer = Timer::from_seconds(FIRE_DELAY, TimerMode::Once);
-    timer.tick(timer.duration());
-    commands.insert_resource(ShootTimer(timer));
+        commands.entity(entity).insert(PlayerInput {
+            device,
+            last_valid_cursor_pos: Option::None,
+        });
+        commands.entity(entity).insert(ShootTimer(timer));
+    }
 }
 
 // SYSTEMS
 
-// Handles change in cursor position, updates PlayerMeshDesiredTransform resource
-// And sends ShootEvent on LMB click based on the ShootTimer resource
+// Aims every player's cannon with their input device, updating their PlayerMeshDesiredTransform
+// And sends a ShootEvent when they shoot based on their ShootTimer
+// Bots don't need a window or a camera, so they also play in headless apps
 pub fn handle_player_input(
-    mut player_input: ResMut<PlayerInput>,
-    mut player_mesh_desired_transform: ResMut<PlayerMeshDesiredTransform>,
-    mut shoot_timer: ResMut<ShootTimer>,
     time: Res<Time>,
     rapier_context: Res<RapierContext>,
+    planet_shape: Res<PlanetShape>,
     // mut lines: ResMut<DebugLines>,
+    keys: Res<Input<KeyCode>>,
     buttons: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
     mut ev_shoot: EventWriter<ShootEvent>,
     primary_window_query: Query<&Window, With<PrimaryWindow>>,
-    camera_query: Query<(&GlobalTransform, &Camera), With<PrimaryCamera>>,
-    player_collider_query: Query<&Transform, With<PlayerCollider>>,
-    mut reload_ui_query: Query<&mut Visibility, With<ReloadUI>>,
+    camera_query: Query<(&GlobalTransform, &Camera, &CameraController, &PlayerId)>,
+    mut player_query: Query<
+        (
+            Entity,
+            &PlayerId,
+            &Transform,
+            &mut PlayerInput,
+            &mut PlayerMeshDesiredTransform,
+            &mut ShootTimer,
+            Option<&mut BotController>,
+        ),
+        With<PlayerCollider>,
+    >,
 ) {
-    // If there is no primary window, do nothing
-    if primary_window_query.is_empty() {
-        return;
-    }
+    let window = primary_window_query.get_single().ok();
 
-    let window: &Window = primary_window_query.single();
-    let (camera_transform, camera) = camera_query.single();
-    let player_collider_transform = player_collider_query.single();
-    let mut reload_ui_visibility = reload_ui_query.single_mut();
+    for (
+        entity,
+        player_id,
+        player_collider_transform,
+        mut player_input,
+        mut player_mesh_desired_transform,
+        mut shoot_timer,
+        bot_controller,
+    ) in player_query.iter_mut()
+    {
+        let camera = camera_query
+            .iter()
+            .find(|(_, _, _, camera_player_id)| *camera_player_id == player_id);
 
-    player_mesh_desired_transform.position = player_collider_transform.translation;
-    player_mesh_desired_transform.local_up = camera_transform.back();
-    player_mesh_desired_transform.local_forward = camera_transform.up();
+        // Players are controlled from their own viewport, so only bots play without a window and a camera
+        if (window.is_none() || camera.is_none()) && player_input.device != InputDevice::Bot {
+            continue;
+        }
 
-    // Flag to check if the cursor is inside the window and over the planet collider
-    let mut invalid_cursor_pos = false;
+        // Only tick the timer while reloading so the component is only changed when the reload progress changes
+        if !shoot_timer.0.finished() {
+            shoot_timer.0.tick(time.delta());
+        }
 
-    // Raycast parameters
-    let max_toi = 600.0;
-    let solid = true;
-    let filter = QueryFilter::new();
+        // The player's frame is the camera's frame moved to the player, so the player's forward is up on the screen,
+        // without a camera the player's last forward is kept.
+        // The player stands on the terrain, so its up is the surface normal rather than the radial direction
+        let player_frame = match camera {
+            Some((_, _, camera_controller, _)) => camera_controller
+                .frame
+                .transported(player_collider_transform.translation),
+            None => SphericalFrame::new(
+                player_collider_transform.translation,
+                player_mesh_desired_transform.local_forward,
+            ),
+        };
+        let surface_normal = planet_shape.surface_normal(player_collider_transform.translation);
+        player_mesh_desired_transform.position = player_collider_transform.translation;
+        player_mesh_desired_transform.local_up = surface_normal;
+        player_mesh_desired_transform.local_forward =
+            project_on_tangent_plane(player_frame.forward, surface_normal)
+                .unwrap_or(player_frame.forward);
+
+        let shoot = match (player_input.device, window, camera) {
+            (InputDevice::Bot, _, _) => {
+                let Some(mut bot_controller) = bot_controller else {
+                    continue;
+                };
 
-    // If cursor is inside the window
-    if let Some(cursor_pos) = window.cursor_position() {
-        // Make a raycast from cursor world position parallel to camera direction
-        // Could fail when interacting with UI while state gets overwritten
-        if let Some(ray) = camera.viewport_to_world(camera_transform, cursor_pos) {
-            // If the raycast hits the planet collider
-            if let Some((_entity, toi)) =
-                rapier_context.cast_ray(ray.origin, ray.direction, max_toi, solid, filter)
-            {
-                // Set player_input resource
-                player_input.last_valid_cursor_pos = Option::Some(cursor_pos);
-
-                // Get the point on the planet where the raycast hit
-                let hit_point = ray.origin + (ray.direction * toi);
-
-                let tangent = get_tangent_helper(hit_point, player_collider_transform);
-
-                player_mesh_desired_transform.tangent = tangent;
-
-                // the player can shoot only after the timer is up
-                if !shoot_timer.0.tick(time.delta()).finished() {
-                    // Show reload UI
-                    *reload_ui_visibility = Visibility::Visible;
-                    return;
+                if let Some(tangent) = bot_controller
+                    .aim
+                    .and_then(|aim| project_on_tangent_plane(aim, surface_normal))
+                {
+                    player_mesh_desired_transform.tangent = tangent.normalize();
                 }
 
-                // Hide the reload UI
-                *reload_ui_visibility = Visibility::Hidden;
+                std::mem::take(&mut bot_controller.fire)
+            }
+            (InputDevice::Mouse, Some(window), Some((camera_transform, camera, _, _))) => {
+                // Cursor position relative to the player's viewport, only if it's inside of it
+                let viewport = camera.logical_viewport_rect();
+                let cursor_pos = window
+                    .cursor_position()
+                    .and_then(|cursor_pos| match viewport {
+                        Some(viewport) if viewport.contains(cursor_pos) => {
+                            Some(cursor_pos - viewport.min)
+                        }
+                        Some(_) => None,
+                        None => Some(cursor_pos),
+                    });
+
+                // Make a raycast from the cursor, the tangent is only updated if it hits something
+                let cursor_tangent = |cursor_pos: Vec2| {
+                    cursor_hit_point(camera, camera_transform, &rapier_context, cursor_pos).map(
+                        |hit_point| {
+                            get_tangent_helper(hit_point, player_collider_transform, surface_normal)
+                        },
+                    )
+                };
 
-                // If the left mouse button is pressed, apply an impulse in the direction of the tangent
-                if buttons.just_pressed(MouseButton::Left) {
-                    if SHOW_DEBUG_LINES {
-                        // lines.line(ray.origin, hit_point, 20.0);
+                if let Some(tangent) = cursor_pos.and_then(cursor_tangent) {
+                    player_input.last_valid_cursor_pos = cursor_pos;
+                    player_mesh_desired_transform.tangent = tangent;
+                    buttons.just_pressed(MouseButton::Left)
+                } else {
+                    // Cursor position and raycast still needs to be checked because last_valid_cursor_pos won't be
+                    // valid if the game window's position or size has changed
+                    if let Some(tangent) =
+                        player_input.last_valid_cursor_pos.and_then(cursor_tangent)
+                    {
+                        player_mesh_desired_transform.tangent = tangent;
                     }
+                    false
+                }
+            }
+            (InputDevice::Mouse, _, _) => false,
+            (InputDevice::Keyboard | InputDevice::Gamepad(_), _, _) => {
+                let (stick, shoot) = match player_input.device {
+                    InputDevice::Gamepad(gamepad) => (
+                        gamepad_stick(&gamepad_axes, gamepad),
+                        gamepad_buttons
+                            .just_pressed(GamepadButton::new(gamepad, GamepadButtonType::South))
+                            || gamepad_buttons.just_pressed(GamepadButton::new(
+                                gamepad,
+                                GamepadButtonType::RightTrigger2,
+                            )),
+                    ),
+                    _ => (keyboard_stick(&keys), keys.just_pressed(KeyCode::Space)),
+                };
 
-                    shoot_timer.0.reset();
-
-                    ev_shoot.send(ShootEvent {
-                        position: player_collider_transform.translation
-                            - (tangent * CANNON_BALL_INITIAL_OFFSET),
-                        direction: -tangent,
-                    });
+                // The stick points where the cannon ball should go on the screen, the cannon faces the other way
+                if stick.length() > STICK_DEAD_ZONE {
+                    let aim = player_frame.right() * stick.x + player_frame.forward * stick.y;
+                    if let Some(direction) = project_on_tangent_plane(aim, surface_normal) {
+                        player_mesh_desired_transform.tangent = -direction;
+                    }
                 }
-            } else {
-                invalid_cursor_pos = true;
+
+                shoot
             }
-        } else {
-            invalid_cursor_pos = true;
+        };
+
+        // the player can shoot only after the timer is up
+        if shoot && shoot_timer.0.finished() {
+            shoot_timer.0.reset();
+
+            let tangent = player_mesh_desired_transform.tangent;
+            ev_shoot.send(ShootEvent {
+                shooter: entity,
+                position: player_collider_transform.translation
+                    - (tangent * CANNON_BALL_INITIAL_OFFSET),
+                direction: -tangent,
+            });
         }
-    } else {
-        invalid_cursor_pos = true;
     }
+}
 
-    if invalid_cursor_pos {
-        if let Some(cursor_pos) = player_input.last_valid_cursor_pos {
-            // Cursor position and raycast still needs to be checked because last_valid_cursor_pos won't be valid
-            // if the game window's position or size has changed
-            if let Some(ray) = camera.viewport_to_world(camera_transform, cursor_pos) {
-                if let Some((_entity, toi)) =
-                    rapier_context.cast_ray(ray.origin, ray.direction, max_toi, solid, filter)
-                {
-                    let hit_point = ray.origin + (ray.direction * toi);
-
-                    let tangent = get_tangent_helper(hit_point, player_collider_transform);
+// Escape closes the settings menu if it's open, otherwise it toggles the pause menu while playing
+pub fn handle_escape_input(
+    keys: Res<Input<KeyCode>>,
+    game_state: Res<State<GameState>>,
+    pause_state: Res<State<PauseState>>,
+    settings_menu_state: Res<State<SettingsMenuState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+    mut next_settings_menu_state: ResMut<NextState<SettingsMenuState>>,
+) {
+    if !keys.just_pressed(KeyCode::Escape) {
+        return;
+    }
 
-                    player_mesh_desired_transform.tangent = tangent;
-                }
-            }
-        }
+    if *settings_menu_state.get() == SettingsMenuState::Open {
+        next_settings_menu_state.set(SettingsMenuState::Closed);
+    } else if *game_state.get() == GameState::Playing {
+        next_pause_state.set(match pause_state.get() {
+            PauseState::Running => PauseState::Paused,
+            PauseState::Paused => PauseState::Running,
+        });
     }
 }
 
 // HELPER FUNCTIONS
 
-// Calculates the tangent in the direction of the vector from the player collider to the hit point on the planet
-fn get_tangent_helper(hit_point: Vec3, player_collider_transform: &Transform) -> Vec3 {
+// Point where a ray from the cursor, parallel to the camera's direction, hits the planet or anything on it
+// Could fail when interacting with UI while state gets overwritten
+fn cursor_hit_point(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    rapier_context: &RapierContext,
+    cursor_pos: Vec2,
+) -> Option<Vec3> {
+    // Raycast parameters
+    let max_toi = 600.0;
+    let solid = true;
+    let filter = QueryFilter::new();
+
+    let ray = camera.viewport_to_world(camera_transform, cursor_pos)?;
+    let (_entity, toi) =
+        rapier_context.cast_ray(ray.origin, ray.direction, max_toi, solid, filter)?;
+
+    Some(ray.origin + (ray.direction * toi))
+}
+
+// Direction held on the arrow keys, with up being up on the screen
+fn keyboard_stick(keys: &Input<KeyCode>) -> Vec2 {
+    let axis = |negative: KeyCode, positive: KeyCode| {
+        keys.pressed(positive) as i32 as f32 - keys.pressed(negative) as i32 as f32
+    };
+
+    Vec2::new(
+        axis(KeyCode::Left, KeyCode::Right),
+        axis(KeyCode::Down, KeyCode::Up),
+    )
+}
+
+fn gamepad_stick(gamepad_axes: &Axis<GamepadAxis>, gamepad: Gamepad) -> Vec2 {
+    let axis = |axis_type: GamepadAxisType| {
+        gamepad_axes
+            .get(GamepadAxis::new(gamepad, axis_type))
+            .unwrap_or(0.0)
+    };
+
+    Vec2::new(
+        axis(GamepadAxisType::LeftStickX),
+        axis(GamepadAxisType::LeftStickY),
+    )
+}
+
+// Calculates the tangent in the direction of the vector from the player collider to the hit point on the planet,
+// along the surface with the given normal under the player
+fn get_tangent_helper(
+    hit_point: Vec3,
+    player_collider_transform: &Transform,
+    surface_normal: Vec3,
+) -> Vec3 {
     // Get the unit vector in the direction of the vector from the hit point to the player
     let hit_to_player_collider = (hit_point - player_collider_transform.translation).normalize();
 
-    // Cross hit_to_player and player collider's normalized translation vector to get the tangent perpendicular to the desired direction
-    let tangent = (hit_to_player_collider.cross(player_collider_transform.translation.normalize()))
-        .normalize();
+    // Cross hit_to_player and the surface normal to get the tangent perpendicular to the desired direction
+    let tangent = (hit_to_player_collider.cross(surface_normal)).normalize();
 
     // Cross again to get the desired direction
-    tangent.cross(player_collider_transform.translation.normalize())
+    tangent.cross(surface_normal)
 }
@@ -0,0 +1,229 @@
+// Levels describe the look of a round, like its background.
+// They are loaded from RON files in the levels directory on startup, and the level to play is picked in the settings.
+
+use bevy::{
+    log::warn,
+    prelude::{Commands, Res, Resource},
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::{client::NetworkClient, settings::Settings};
+
+// CONSTANTS
+
+pub const LEVELS_DIRECTORY: &str = "assets/levels";
+pub const DEFAULT_STARS: usize = 4000;
+pub const DEFAULT_STARS_SEED: u64 = 0;
+
+// RESOURCES
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum Background {
+    // Six faces named <faces>_rt.png, <faces>_lf.png, <faces>_up.png, <faces>_dn.png, <faces>_ft.png and <faces>_bk.png
+    Skybox { faces: String },
+    // Randomly placed stars, used when a skybox can't be loaded
+    Starfield { stars: usize, seed: u64 },
+}
+
+// Tunables of the procedural planet terrain, distances are in world units
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct PlanetConfig {
+    pub seed: u64,
+    pub resolution: u32, // Quads along the edge of each face of the cube sphere
+    pub amplitude: f32,  // Height of the hills
+    pub frequency: f32,  // Number of hills across the planet
+    pub octaves: u32,
+    pub craters: usize,
+    pub min_crater_radius: f32,
+    pub max_crater_radius: f32,
+    pub crater_depth: f32, // Depth of the largest craters
+}
+
+// Tunables of the animated cloud shell around the planet
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct CloudConfig {
+    pub enabled: bool,
+    pub altitude: f32, // Height of the shell above the surface of the planet
+    pub steepness: f32,
+    pub wavelength: f32,
+    pub speed: f32,
+    pub wave_directions: [[f32; 2]; 3],
+    pub color: [f32; 4],
+    pub coverage: f32, // Fraction of the shell covered by clouds, between 0 and 1
+}
+
+// Tunables of the glowing atmosphere shell around the planet
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct AtmosphereConfig {
+    pub enabled: bool,
+    pub altitude: f32,   // Height of the shell above the surface of the planet
+    pub color: [f32; 4], // The alpha is the strength of the glow
+    pub falloff: f32,    // Higher values keep the glow closer to the rim
+}
+
+// Level of the current round
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct LevelConfig {
+    pub name: String,
+    pub background: Background,
+    pub planet: PlanetConfig,
+    pub clouds: CloudConfig,
+    pub atmosphere: AtmosphereConfig,
+    pub day_length: f32, // Seconds for the sun to orbit the planet, 0 keeps it still
+}
+
+// All the levels that can be picked
+#[derive(Resource)]
+pub struct Levels(pub Vec<LevelConfig>);
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Starfield {
+            stars: DEFAULT_STARS,
+            seed: DEFAULT_STARS_SEED,
+        }
+    }
+}
+
+impl Default for PlanetConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            resolution: 48,
+            amplitude: 0.6,
+            frequency: 2.5,
+            octaves: 4,
+            craters: 10,
+            min_crater_radius: 1.5,
+            max_crater_radius: 4.0,
+            crater_depth: 0.8,
+        }
+    }
+}
+
+impl Default for CloudConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            altitude: 4.0,
+            steepness: 0.25,
+            wavelength: 9.0,
+            speed: 0.1,
+            wave_directions: [[1.0, 0.0], [0.3, 1.0], [-0.7, 0.4]],
+            color: [1.0, 1.0, 1.0, 0.6],
+            coverage: 0.4,
+        }
+    }
+}
+
+impl Default for AtmosphereConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            altitude: 6.0,
+            color: [0.35, 0.6, 1.0, 1.5],
+            falloff: 3.0,
+        }
+    }
+}
+
+impl Default for LevelConfig {
+    fn default() -> Self {
+        Self {
+            name: "Corona".to_string(),
+            background: Background::Skybox {
+                faces: "textures/skybox/corona".to_string(),
+            },
+            planet: PlanetConfig::default(),
+            clouds: CloudConfig::default(),
+            atmosphere: AtmosphereConfig::default(),
+            day_length: 120.0,
+        }
+    }
+}
+
+impl Levels {
+    // Loads every level in the directory, sorted by file name, falling back to the default level if there are none
+    pub fn load(directory: &str) -> Self {
+        let mut paths = match fs::read_dir(directory) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|extension| extension == "ron"))
+                .collect::<Vec<_>>(),
+            Err(err) => {
+                warn!("Failed to read levels from {}: {}", directory, err);
+                Vec::new()
+            }
+        };
+        paths.sort();
+
+        let levels = paths
+            .iter()
+            .filter_map(|path| {
+                let contents = fs::read_to_string(path).ok()?;
+                match ron::from_str::<LevelConfig>(&contents) {
+                    Ok(level) => Some(level),
+                    Err(err) => {
+                        warn!("Failed to parse level {}: {}", path.display(), err);
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if levels.is_empty() {
+            Self(vec![LevelConfig::default()])
+        } else {
+            Self(levels)
+        }
+    }
+
+    // Level with the given name, or the first level if there isn't one
+    pub fn get(&self, name: &str) -> &LevelConfig {
+        self.0
+            .iter()
+            .find(|level| level.name == name)
+            .unwrap_or(&self.0[0])
+    }
+
+    // Name of the level next to the given one in the given direction (-1 or 1)
+    pub fn cycle(&self, name: &str, direction: i32) -> String {
+        let index = self
+            .0
+            .iter()
+            .position(|level| level.name == name)
+            .unwrap_or(0) as i32;
+
+        self.0[(index + direction).rem_euclid(self.0.len() as i32) as usize]
+            .name
+            .clone()
+    }
+}
+
+// STARTUP SYSTEMS
+
+// Picks the level for the round from the settings, or the server's level when connected to one
+pub fn setup_level(
+    mut commands: Commands,
+    levels: Res<Levels>,
+    settings: Res<Settings>,
+    network_client: Option<Res<NetworkClient>>,
+) {
+    let name = match network_client.as_ref().and_then(|client| client.level()) {
+        Some(level) => level,
+        None => &settings.level,
+    };
+    if levels.get(name).name != name {
+        warn!(
+            "Level {} not found, playing {} instead",
+            name, levels.0[0].name
+        );
+    }
+
+    commands.insert_resource(levels.get(name).clone());
+}
@@ -1,7 +1,40 @@
+// Bevy systems commonly take many parameters and complex queries
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
+pub mod asset_manifest;
 pub mod asteroids;
+pub mod atmosphere;
+pub mod audio;
+pub mod bench;
+pub mod bot;
+pub mod camera;
 pub mod cannon_ball;
+pub mod cli;
+pub mod client;
+pub mod clouds;
 pub mod common;
 pub mod extensions;
+pub mod game_assets;
+pub mod gym;
+pub mod headless;
+pub mod high_scores;
+pub mod hud;
 pub mod input;
+pub mod level;
+pub mod music;
+pub mod network;
+pub mod noise;
+pub mod particles;
+pub mod planet;
 pub mod player;
+pub mod radar;
+pub mod replay;
+pub mod rollback;
+pub mod server;
+pub mod settings;
+pub mod skybox;
+pub mod spherical_frame;
+pub mod synth;
+pub mod trajectory;
 pub mod ui;
+pub mod versus;
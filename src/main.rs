@@ -1,13 +1,15 @@
 use bevy::{
+    log::LogPlugin,
     prelude::{
-        default, in_state, App, IntoSystemConfigs, OnEnter, OnExit, PluginGroup, Startup, Update,
+        apply_deferred, default, in_state, not, resource_changed, resource_exists,
+        resource_exists_and_changed, App, Condition, IntoSystemConfigs, Last, MaterialPlugin,
+        OnEnter, OnExit, PluginGroup, Res, Startup, Update,
     },
     window::{Window, WindowPlugin},
     DefaultPlugins,
 };
 // use bevy_prototype_debug_lines::DebugLinesPlugin;
 use bevy_rapier3d::prelude::{NoUserData, RapierPhysicsPlugin};
-// use bevy_starfield::{GameUnitsToCelestial, StarfieldPlugin};
 
 #[cfg(debug_assertions)]
 use bevy_editor_pls::prelude::EditorPlugin;
@@ -15,105 +17,531 @@ use bevy_editor_pls::prelude::EditorPlugin;
 use bevy_rapier3d::render::RapierDebugRenderPlugin;
 
 use loose_cannon::{
-    asteroids::{setup_asteroids, spawn_asteroids},
+    asset_manifest::{report_asset_problems, AssetManifest, ASSETS_DIRECTORY},
+    asteroids::{setup_asteroids, spawn_asteroids, AsteroidWave},
+    atmosphere::{setup_atmosphere, update_day_night, AtmosphereMaterial},
+    audio::GameAudioPlugin,
+    bot::{
+        end_attract_mode, setup_attract_mode_ui, setup_bot_players, setup_menu_idle_timer,
+        start_attract_mode, stop_attract_mode, update_bots, AttractMode,
+    },
+    camera::{
+        add_camera_trauma, handle_camera_input, move_camera, setup_camera, update_camera_viewports,
+    },
     cannon_ball::shoot_cannon_ball,
+    cli::{CliArgs, USAGE},
+    client::{
+        attach_replica_visuals, collect_client_input, despawn_orphaned_player_meshes,
+        explode_replica_asteroids, replicate_snapshots, send_client_input, sync_network_players,
+        sync_network_round, NetworkClient, NetworkClientPlugin,
+    },
+    clouds::{animate_clouds, setup_clouds, CloudMaterial},
     common::{
-        gravity, handle_collisions, move_camera, reset_score, setup_scene, setup_window, teardown,
-        GameState, Score,
+        despawn_destroyed, exit_after_tick_limit, gravity, handle_collisions, pause_game,
+        reset_pause, reset_rapier, reset_score, resume_game, setup_round, setup_scene,
+        setup_window, teardown, update_combo, update_survival_time, AsteroidDestroyedEvent, Combo,
+        DestroyEvent, GameState, PauseState, RequestedSeed, Score, SurvivalSeconds, TickLimit,
+    },
+    game_assets::{check_loading_progress, setup_game_assets, LoadingProgress},
+    headless::{headless_app, insert_fixed_ticks, run_headless},
+    high_scores::{
+        check_high_score, commit_pending_high_score, handle_name_input, setup_high_scores,
+    },
+    hud::{
+        setup_game_ui, spawn_score_popups, update_combo_ui, update_reload_ui, update_score_popups,
+        update_score_ui, update_scoreboard_ui, update_survival_time_ui, update_wave_ui,
+    },
+    input::{handle_escape_input, handle_player_input, setup_player_input, ShootEvent},
+    level::{setup_level, Levels, LEVELS_DIRECTORY},
+    particles::{
+        emit_particles, setup_particle_assets, setup_particle_pool, spawn_particle_bursts,
+        update_dust_emitters, update_particles,
     },
-    input::{handle_player_input, setup_player_input, ShootEvent},
+    planet::setup_planet,
     player::{apply_player_collider_impulse, set_player_mesh_transform, setup_player},
-    ui::{restart_button_system, setup_game_over_ui, setup_game_ui, update_score_ui},
+    radar::{
+        apply_minimap_settings, setup_minimap_ui, setup_radar_images, spawn_radar_markers,
+        update_minimap, update_offscreen_indicators,
+    },
+    replay::{
+        end_replay, play_replay_inputs, record_replay_inputs, save_replay, save_replay_on_exit,
+        setup_replay_players, start_replay, start_replay_recording, ReplayPlayback, ReplayRecorder,
+    },
+    rollback::{
+        end_rollback_session, setup_duel_players, RollbackPlugin, RollbackSession, UdpPeer,
+        DEFAULT_DUEL_PORT, HOST_SLOT,
+    },
+    settings::{
+        apply_shadow_settings, apply_window_settings, save_settings, Settings, SettingsMenuState,
+        SettingsPath, WindowModeSetting, SETTINGS_PATH,
+    },
+    skybox::{assemble_skybox, setup_skybox, PendingSkybox, SkyboxCache},
+    trajectory::draw_trajectory_preview,
+    ui::{
+        menu_button_system, settings_button_system, setup_game_over_ui, setup_high_scores_ui,
+        setup_loading_ui, setup_main_menu_ui, setup_pause_ui, setup_settings_ui, teardown_pause_ui,
+        teardown_settings_ui, update_loading_ui, update_settings_ui,
+    },
+    versus::{end_versus_round, setup_versus_round, update_versus_round, VersusScoreboard},
 };
 
 // TODO: add grass to planet
-// TODO: cannon ball shooting sfx
-// TODO: cannon ball shooting vfx
-// TODO: cannon ball explosion sfx
-// TODO: cannon ball explosion vfx
 
 fn main() {
+    let cli_args = CliArgs::parse(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, USAGE);
+        std::process::exit(1);
+    });
+    if cli_args.help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    // Settings are loaded before the window is created so they apply on startup
+    let settings_path = cli_args
+        .config
+        .clone()
+        .unwrap_or_else(|| SETTINGS_PATH.to_string());
+    let mut settings = Settings::load(&settings_path);
+    if let Some(resolution) = cli_args.window {
+        settings.resolution = resolution;
+    }
+    if cli_args.fullscreen {
+        settings.window_mode = WindowModeSetting::Fullscreen;
+    }
+
+    // A recording is played with the seed and settings it was recorded with, only the display settings are kept
+    let replay_playback = cli_args.replay.as_deref().map(|path| {
+        ReplayPlayback::load(path).unwrap_or_else(|err| {
+            eprintln!("Failed to load the recording {}: {}", path, err);
+            std::process::exit(1);
+        })
+    });
+    let seed = match &replay_playback {
+        Some(replay_playback) => {
+            settings = Settings {
+                window_mode: settings.window_mode,
+                resolution: settings.resolution,
+                vsync: settings.vsync,
+                ..replay_playback.replay.settings.clone()
+            };
+            Some(replay_playback.replay.seed)
+        }
+        None => cli_args.seed,
+    };
+
+    if cli_args.headless {
+        run_headless_game(&cli_args, settings, seed, replay_playback);
+        return;
+    }
+
     let mut app = App::new();
 
+    // Joins a dedicated server instead of simulating the game locally when started with --connect <address>
+    let network_client = cli_args.connect.as_deref().map(|address| {
+        NetworkClient::connect(address).unwrap_or_else(|err| {
+            eprintln!("Failed to connect to {}: {}", address, err);
+            std::process::exit(1);
+        })
+    });
+
+    // Plays a rollback duel against another peer when started with --host-duel [port] or --join-duel <address>
+    let rollback_session = if let Some(port) = cli_args.host_duel {
+        let port = port.unwrap_or(DEFAULT_DUEL_PORT);
+        let peer = UdpPeer::host(port).unwrap_or_else(|err| {
+            eprintln!("Failed to host a duel on port {}: {}", port, err);
+            std::process::exit(1);
+        });
+        Some(RollbackSession::new(peer, HOST_SLOT, rand::random()))
+    } else if let Some(address) = cli_args.join_duel.as_deref() {
+        let peer = UdpPeer::join(address).unwrap_or_else(|err| {
+            eprintln!("Failed to join the duel at {}: {}", address, err);
+            std::process::exit(1);
+        });
+        Some(RollbackSession::new(peer, 1 - HOST_SLOT, 0))
+    } else {
+        None
+    };
+
+    // Recordings of headless rounds have no local players, one of their bots gets the camera instead
+    if replay_playback.is_some() && settings.players == 0 && settings.bots > 0 {
+        settings.players = 1;
+        settings.bots -= 1;
+    }
+
+    let mut primary_window = Window {
+        title: "Loose Cannon".to_string(),
+        ..default()
+    };
+    settings.apply_to_window(&mut primary_window);
+
     // Default plugins
-    app.add_plugins(DefaultPlugins.set(WindowPlugin {
-        primary_window: Some(Window {
-            title: "Loose Cannon".to_string(),
-            ..default()
-        }),
+    let mut default_plugins = DefaultPlugins.set(WindowPlugin {
+        primary_window: Some(primary_window),
         ..default()
-    }));
+    });
+    if let Some(level) = cli_args.log_level {
+        default_plugins = default_plugins.set(LogPlugin { level, ..default() });
+    }
+    app.add_plugins(default_plugins);
 
     // Third-party plugins
     app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
-        // .add_plugin(AtmospherePlugin)
-        // .add_plugin(StarfieldPlugin)
         // .add_plugin(DebugLinesPlugin::with_depth_test(true))
         ;
 
     // Third-party debug plugins
     #[cfg(debug_assertions)]
+    {
+        app.add_plugins(RapierDebugRenderPlugin::default());
+        if !cli_args.no_editor {
+            app.add_plugins(EditorPlugin::new().in_new_window(Window::default()));
+        }
+    }
+
+    // Game plugins
+    app.add_plugins((GameAudioPlugin, NetworkClientPlugin, RollbackPlugin));
+
+    // Custom materials
     app.add_plugins((
-        RapierDebugRenderPlugin::default(),
-        EditorPlugin::new().in_new_window(Window::default()),
+        MaterialPlugin::<CloudMaterial>::default(),
+        MaterialPlugin::<AtmosphereMaterial>::default(),
     ));
 
-    // // Custom materials
-    // app.add_plugin(MaterialPlugin::<CloudMaterial>::default());
-
     // Events
     app.add_event::<ShootEvent>();
+    app.add_event::<AsteroidDestroyedEvent>();
+    app.add_event::<DestroyEvent>();
 
     // Resources
     app.insert_resource(Score(0));
+    app.insert_resource(settings);
+    app.insert_resource(SettingsPath(settings_path));
+    app.insert_resource(AssetManifest::validate(ASSETS_DIRECTORY));
+    app.insert_resource(Levels::load(LEVELS_DIRECTORY));
+    app.init_resource::<SkyboxCache>();
+    if let Some(network_client) = network_client {
+        app.insert_resource(network_client);
+    }
+    if let Some(rollback_session) = rollback_session {
+        app.insert_resource(rollback_session);
+    }
+    if let Some(seed) = seed {
+        app.insert_resource(RequestedSeed(seed));
+    }
+    if let Some(ticks) = cli_args.ticks {
+        app.insert_resource(TickLimit { ticks, played: 0 });
+    }
+
+    // Recording and playing back need every tick to be the same length
+    if let Some(path) = cli_args.record.clone() {
+        app.insert_resource(ReplayRecorder::new(path));
+    }
+    if let Some(replay_playback) = replay_playback {
+        app.insert_resource(replay_playback);
+    }
+    if cli_args.record.is_some() || cli_args.replay.is_some() {
+        insert_fixed_ticks(&mut app);
+    }
 
     // State
     app.add_state::<GameState>();
+    app.add_state::<PauseState>();
+    app.add_state::<SettingsMenuState>();
 
     // Startup systems
-    app.add_systems(Startup, setup_window);
+    app.add_systems(
+        Startup,
+        (
+            report_asset_problems,
+            setup_window,
+            setup_high_scores,
+            setup_radar_images,
+            setup_particle_assets,
+        ),
+    );
+
+    // GameState::Loading systems
+    app.add_systems(
+        OnEnter(GameState::Loading),
+        (setup_game_assets, setup_loading_ui),
+    )
+    .add_systems(
+        Update,
+        (
+            check_loading_progress,
+            update_loading_ui.run_if(resource_changed::<LoadingProgress>()),
+        )
+            .chain()
+            .run_if(in_state(GameState::Loading)),
+    )
+    .add_systems(OnExit(GameState::Loading), teardown);
+
+    // GameState::MainMenu systems
+    app.add_systems(
+        OnEnter(GameState::MainMenu),
+        (
+            setup_main_menu_ui,
+            setup_menu_idle_timer,
+            start_replay.run_if(resource_exists::<ReplayPlayback>()),
+        ),
+    )
+    .add_systems(
+        Update,
+        start_attract_mode.run_if(in_state(GameState::MainMenu)),
+    )
+    .add_systems(OnExit(GameState::MainMenu), teardown);
 
     // GameState::Playing systems
     app.add_systems(
         OnEnter(GameState::Playing),
+        // Later systems need the resources and entities spawned by earlier ones, so the commands are applied in between
         (
+            setup_level,
+            setup_round,
+            setup_versus_round,
+            apply_deferred,
+            start_replay_recording.run_if(resource_exists::<ReplayRecorder>()),
             setup_scene,
-            setup_player,
-            setup_player_input,
+            setup_planet,
+            apply_deferred,
+            setup_camera,
+            setup_skybox,
+            setup_clouds,
+            setup_atmosphere,
+            (
+                setup_player.run_if(simulates_locally),
+                setup_duel_players.run_if(resource_exists::<RollbackSession>()),
+                apply_deferred,
+                setup_bot_players.run_if(simulates_locally),
+                apply_deferred,
+                setup_player_input.run_if(not(resource_exists::<RollbackSession>())),
+                apply_deferred,
+                setup_replay_players.run_if(resource_exists::<ReplayPlayback>()),
+            )
+                .chain(),
             setup_asteroids,
             setup_game_ui,
+            setup_minimap_ui,
+            setup_particle_pool,
+            setup_attract_mode_ui.run_if(resource_exists::<AttractMode>()),
         )
             .chain(),
     )
     .add_systems(
         Update,
         (
-            gravity,
+            gravity.run_if(simulates_locally),
+            update_bots.run_if(simulates_locally),
+            play_replay_inputs.run_if(resource_exists::<ReplayPlayback>()),
             handle_player_input,
+            record_replay_inputs.run_if(resource_exists::<ReplayRecorder>()),
             set_player_mesh_transform,
-            apply_player_collider_impulse,
-            shoot_cannon_ball,
+            apply_player_collider_impulse.run_if(simulates_locally),
+            shoot_cannon_ball.run_if(simulates_locally),
+            handle_camera_input,
             move_camera,
-            handle_collisions,
-            spawn_asteroids,
-            update_score_ui,
+            handle_collisions.run_if(simulates_locally),
+            despawn_destroyed.run_if(simulates_locally),
+            add_camera_trauma,
+            spawn_asteroids.run_if(simulates_locally),
+            update_survival_time,
+            update_combo.run_if(simulates_locally),
+            update_versus_round.run_if(resource_exists::<VersusScoreboard>()),
+            exit_after_tick_limit.run_if(resource_exists::<TickLimit>()),
+        )
+            .chain()
+            .run_if(in_state(GameState::Playing).and_then(in_state(PauseState::Running))),
+    )
+    .add_systems(
+        Update,
+        (
+            (
+                attach_replica_visuals,
+                sync_network_players,
+                despawn_orphaned_player_meshes,
+                sync_network_round,
+                explode_replica_asteroids,
+            )
+                .chain()
+                .after(replicate_snapshots)
+                .before(handle_player_input),
+            collect_client_input
+                .after(handle_player_input)
+                .before(send_client_input),
+        )
+            .run_if(resource_exists::<NetworkClient>().and_then(in_state(GameState::Playing))),
+    )
+    .add_systems(
+        Update,
+        (
+            update_day_night,
+            spawn_particle_bursts,
+            update_dust_emitters,
+            emit_particles,
+            update_particles,
         )
             .chain()
+            .after(update_combo)
+            .run_if(in_state(GameState::Playing).and_then(in_state(PauseState::Running))),
+    )
+    .add_systems(
+        Update,
+        (
+            update_score_ui.run_if(resource_changed::<Score>()),
+            update_scoreboard_ui.run_if(resource_exists_and_changed::<VersusScoreboard>()),
+            update_combo_ui.run_if(resource_exists_and_changed::<Combo>()),
+            update_wave_ui.run_if(resource_exists_and_changed::<AsteroidWave>()),
+            update_survival_time_ui.run_if(resource_exists_and_changed::<SurvivalSeconds>()),
+            update_reload_ui,
+            spawn_score_popups,
+            update_score_popups,
+            draw_trajectory_preview,
+            update_camera_viewports,
+            assemble_skybox.run_if(resource_exists::<PendingSkybox>()),
+            animate_clouds,
+            (
+                spawn_radar_markers,
+                update_offscreen_indicators,
+                update_minimap,
+                apply_minimap_settings.run_if(resource_changed::<Settings>()),
+            )
+                .chain(),
+        )
+            .after(update_combo)
             .run_if(in_state(GameState::Playing)),
     )
-    .add_systems(OnExit(GameState::Playing), teardown);
+    .add_systems(
+        Update,
+        stop_attract_mode
+            .run_if(resource_exists::<AttractMode>().and_then(in_state(GameState::Playing))),
+    )
+    .add_systems(OnExit(GameState::Playing), (teardown, reset_pause));
+
+    // PauseState::Paused systems
+    app.add_systems(
+        OnEnter(PauseState::Paused),
+        (pause_game, setup_pause_ui).run_if(in_state(GameState::Playing)),
+    )
+    .add_systems(OnExit(PauseState::Paused), (resume_game, teardown_pause_ui));
 
     // GameState::GameOver systems
-    app.add_systems(OnEnter(GameState::GameOver), setup_game_over_ui)
+    app.add_systems(
+        OnEnter(GameState::GameOver),
+        // Versus rounds and replays aren't entered into the high scores, and the attract mode's demo goes back to the
+        // main menu
+        (
+            check_high_score.run_if(not(resource_exists::<VersusScoreboard>()
+                .or_else(resource_exists::<AttractMode>())
+                .or_else(resource_exists::<ReplayPlayback>()))),
+            save_replay.run_if(resource_exists::<ReplayRecorder>()),
+            apply_deferred,
+            setup_game_over_ui.run_if(not(resource_exists::<AttractMode>())),
+            end_attract_mode.run_if(resource_exists::<AttractMode>()),
+        )
+            .chain(),
+    )
+    .add_systems(
+        Update,
+        (handle_name_input).run_if(in_state(GameState::GameOver)),
+    )
+    .add_systems(
+        OnExit(GameState::GameOver),
+        (
+            commit_pending_high_score,
+            teardown,
+            reset_score,
+            end_versus_round,
+            end_replay,
+            // The duel's bodies were left for its ticks to remove, which won't run anymore
+            (end_rollback_session, reset_rapier).run_if(resource_exists::<RollbackSession>()),
+        )
+            .chain(),
+    );
+
+    // GameState::HighScores systems
+    app.add_systems(OnEnter(GameState::HighScores), setup_high_scores_ui)
+        .add_systems(OnExit(GameState::HighScores), teardown);
+
+    // SettingsMenuState::Open systems
+    app.add_systems(OnEnter(SettingsMenuState::Open), setup_settings_ui)
         .add_systems(
             Update,
-            (restart_button_system).run_if(in_state(GameState::GameOver)),
+            (
+                settings_button_system,
+                update_settings_ui.run_if(resource_changed::<Settings>()),
+            )
+                .chain()
+                .run_if(in_state(SettingsMenuState::Open)),
         )
-        .add_systems(OnExit(GameState::GameOver), (teardown, reset_score).chain());
+        .add_systems(
+            OnExit(SettingsMenuState::Open),
+            (teardown_settings_ui, save_settings),
+        );
 
     // Misc systems
-    app.add_systems(Update, bevy::window::close_on_esc);
+    app.add_systems(
+        Update,
+        (
+            handle_escape_input,
+            menu_button_system,
+            (apply_window_settings, apply_shadow_settings).run_if(resource_changed::<Settings>()),
+        ),
+    );
+
+    // Recordings are also saved when the app is closed during the round
+    app.add_systems(
+        Last,
+        save_replay_on_exit.run_if(resource_exists::<ReplayRecorder>()),
+    );
 
     // Run app
     app.run();
 }
+
+// Plays a round without a window and prints its summary as JSON
+fn run_headless_game(
+    cli_args: &CliArgs,
+    settings: Settings,
+    seed: Option<u64>,
+    replay_playback: Option<ReplayPlayback>,
+) {
+    let mut app = headless_app(settings, seed.unwrap_or_else(rand::random));
+
+    // Logs are off by default so only the summary is printed
+    if let Some(level) = cli_args.log_level {
+        app.add_plugins(LogPlugin { level, ..default() });
+    }
+    if let Some(path) = cli_args.record.clone() {
+        app.insert_resource(ReplayRecorder::new(path));
+    }
+
+    // A replay stops where its recording did
+    let max_ticks = cli_args.ticks.or(replay_playback
+        .as_ref()
+        .map(|replay_playback| replay_playback.replay.ticks));
+    if let Some(replay_playback) = replay_playback {
+        app.insert_resource(replay_playback);
+    }
+
+    let summary = run_headless(&mut app, max_ticks);
+    if let Some(recorder) = app.world.get_resource::<ReplayRecorder>() {
+        recorder.save();
+    }
+
+    match serde_json::to_string(&summary) {
+        Ok(summary) => println!("{}", summary),
+        Err(err) => {
+            eprintln!("Failed to serialize the summary: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// The game is simulated by the app's own systems, rather than by a server or a duel's rollback ticks
+fn simulates_locally(
+    network_client: Option<Res<NetworkClient>>,
+    rollback_session: Option<Res<RollbackSession>>,
+) -> bool {
+    network_client.is_none() && rollback_session.is_none()
+}
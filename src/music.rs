@@ -0,0 +1,307 @@
+// Adaptive background music.
+// A calm and an intense layer loop together for the whole round and are crossfaded by how intense the game is,
+// which is estimated from the number of asteroids, how close the nearest one is to the player and the wave.
+// Short stingers play when a new wave starts and on game over.
+
+use bevy::{
+    audio::{AudioSink, AudioSinkPlayback, AudioSourceBundle, PlaybackSettings, Volume},
+    prelude::{
+        Assets, Commands, Component, Handle, Query, Res, ResMut, Resource, Time, Transform, With,
+    },
+};
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use crate::{
+    asteroids::{Asteroid, AsteroidWave},
+    common::PLANET_SIZE,
+    player::PlayerCollider,
+    settings::Settings,
+    synth::{envelope, filtered_noise, render, SynthSound},
+};
+
+// CONSTANTS
+
+const BEAT_LENGTH: f32 = 0.5; // 120 BPM
+const BEATS_PER_CHORD: usize = 4;
+const CHORDS: [[f32; 3]; 4] = [
+    [220.0, 261.63, 329.63], // Am
+    [174.61, 220.0, 261.63], // F
+    [261.63, 329.63, 392.0], // C
+    [196.0, 246.94, 293.66], // G
+];
+const LOOP_LENGTH: f32 = BEAT_LENGTH * BEATS_PER_CHORD as f32 * CHORDS.len() as f32;
+const CROSSFADE_SPEED: f32 = 0.5; // Intensity change per second
+const MAX_ASTEROIDS_INTENSITY: f32 = 12.0; // Number of asteroids at which their count is at full intensity
+const MAX_WAVE_INTENSITY: f32 = 10.0;
+const NEAR_DISTANCE: f32 = PLANET_SIZE * 0.25; // Asteroids closer than this are at full intensity
+const FAR_DISTANCE: f32 = PLANET_SIZE * 2.0; // Asteroids further than this don't add intensity
+const STINGER_VOLUME: f32 = 0.8;
+
+// COMPONENTS
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MusicLayerKind {
+    Calm,
+    Intense,
+}
+
+#[derive(Component)]
+pub struct MusicLayer {
+    pub kind: MusicLayerKind,
+}
+
+#[derive(Component)]
+pub struct MusicStinger {}
+
+// RESOURCES
+
+#[derive(Resource)]
+pub struct MusicTracks {
+    pub calm: Handle<SynthSound>,
+    pub intense: Handle<SynthSound>,
+    pub wave_stinger: Handle<SynthSound>,
+    pub game_over_stinger: Handle<SynthSound>,
+}
+
+// Intensity between 0 (calm) and 1 (intense), the current value follows the target to crossfade smoothly
+#[derive(Resource, Default)]
+pub struct MusicIntensity {
+    pub target: f32,
+    pub current: f32,
+}
+
+// STARTUP SYSTEMS
+
+pub fn setup_music_tracks(mut commands: Commands, mut sounds: ResMut<Assets<SynthSound>>) {
+    commands.insert_resource(MusicTracks {
+        calm: sounds.add(synthesize_calm_layer()),
+        intense: sounds.add(synthesize_intense_layer()),
+        wave_stinger: sounds.add(synthesize_wave_stinger()),
+        game_over_stinger: sounds.add(synthesize_game_over_stinger()),
+    });
+}
+
+// Starts both layers at the start of a round, only the calm one is audible
+pub fn start_music(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    music_tracks: Option<Res<MusicTracks>>,
+) {
+    commands.insert_resource(MusicIntensity::default());
+
+    let Some(music_tracks) = music_tracks else {
+        return;
+    };
+
+    for (kind, source) in [
+        (MusicLayerKind::Calm, music_tracks.calm.clone()),
+        (MusicLayerKind::Intense, music_tracks.intense.clone()),
+    ] {
+        commands
+            .spawn(AudioSourceBundle {
+                source,
+                settings: PlaybackSettings {
+                    volume: Volume::new_relative(layer_gain(kind, 0.0) * settings.music_gain()),
+                    ..PlaybackSettings::LOOP
+                },
+            })
+            .insert(MusicLayer { kind });
+    }
+}
+
+// SYSTEMS
+
+// Estimates how intense the game currently is
+pub fn update_music_intensity(
+    mut music_intensity: ResMut<MusicIntensity>,
+    wave: Res<AsteroidWave>,
+    asteroid_query: Query<&Transform, With<Asteroid>>,
+    player_query: Query<&Transform, With<PlayerCollider>>,
+) {
+    // Distance between the closest asteroid and the player it's closest to
+    let closest_distance = player_query
+        .iter()
+        .flat_map(|player_transform| {
+            asteroid_query
+                .iter()
+                .map(|transform| transform.translation.distance(player_transform.translation))
+        })
+        .reduce(f32::min);
+
+    music_intensity.target = intensity(asteroid_query.iter().len(), closest_distance, wave.0);
+}
+
+// Moves the intensity towards its target and sets the volume of the layers
+pub fn crossfade_music(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut music_intensity: ResMut<MusicIntensity>,
+    layer_query: Query<(&MusicLayer, &AudioSink)>,
+) {
+    let max_change = CROSSFADE_SPEED * time.delta_seconds();
+    let change = (music_intensity.target - music_intensity.current).clamp(-max_change, max_change);
+    music_intensity.current += change;
+
+    for (layer, sink) in layer_query.iter() {
+        sink.set_volume(layer_gain(layer.kind, music_intensity.current) * settings.music_gain());
+    }
+}
+
+// Runs only when the wave has changed
+pub fn play_wave_stinger(
+    commands: Commands,
+    settings: Res<Settings>,
+    wave: Res<AsteroidWave>,
+    music_tracks: Option<Res<MusicTracks>>,
+) {
+    // The wave is reset to 0 at the start of a round
+    if wave.0 == 0 {
+        return;
+    }
+
+    if let Some(music_tracks) = music_tracks {
+        spawn_stinger(commands, &settings, music_tracks.wave_stinger.clone());
+    }
+}
+
+pub fn play_game_over_stinger(
+    commands: Commands,
+    settings: Res<Settings>,
+    music_tracks: Option<Res<MusicTracks>>,
+) {
+    if let Some(music_tracks) = music_tracks {
+        spawn_stinger(commands, &settings, music_tracks.game_over_stinger.clone());
+    }
+}
+
+// HELPER FUNCTIONS
+
+// Intensity between 0 and 1, mostly driven by how close the nearest asteroid is
+pub fn intensity(asteroid_count: usize, closest_distance: Option<f32>, wave: u32) -> f32 {
+    let count = (asteroid_count as f32 / MAX_ASTEROIDS_INTENSITY).min(1.0);
+    let proximity = closest_distance.map_or(0.0, |distance| {
+        1.0 - ((distance - NEAR_DISTANCE) / (FAR_DISTANCE - NEAR_DISTANCE)).clamp(0.0, 1.0)
+    });
+    let wave = (wave as f32 / MAX_WAVE_INTENSITY).min(1.0);
+
+    (0.35 * count + 0.45 * proximity + 0.2 * wave).clamp(0.0, 1.0)
+}
+
+// Equal power crossfade so the overall loudness stays the same during the crossfade
+fn layer_gain(kind: MusicLayerKind, intensity: f32) -> f32 {
+    let angle = intensity.clamp(0.0, 1.0) * FRAC_PI_2;
+    match kind {
+        MusicLayerKind::Calm => angle.cos(),
+        MusicLayerKind::Intense => angle.sin(),
+    }
+}
+
+fn spawn_stinger(mut commands: Commands, settings: &Settings, source: Handle<SynthSound>) {
+    commands
+        .spawn(AudioSourceBundle {
+            source,
+            settings: PlaybackSettings {
+                volume: Volume::new_relative(STINGER_VOLUME * settings.music_gain()),
+                ..PlaybackSettings::DESPAWN
+            },
+        })
+        .insert(MusicStinger {});
+}
+
+// Index of the chord playing at the given time of the loop
+fn chord_at(t: f32) -> usize {
+    ((t / (BEAT_LENGTH * BEATS_PER_CHORD as f32)) as usize).min(CHORDS.len() - 1)
+}
+
+// Soft pad playing the chord progression, each chord fades in and out so the loop has no seams
+fn synthesize_calm_layer() -> SynthSound {
+    let chord_length = BEAT_LENGTH * BEATS_PER_CHORD as f32;
+    let fade = 0.4;
+
+    SynthSound::normalized(
+        render(LOOP_LENGTH, |t| {
+            let chord_t = t % chord_length;
+            let fade_gain = (chord_t / fade)
+                .min((chord_length - chord_t) / fade)
+                .min(1.0);
+            let shimmer = 0.85 + 0.15 * (TAU * t / LOOP_LENGTH * 4.0).sin();
+
+            CHORDS[chord_at(t)]
+                .iter()
+                .map(|frequency| {
+                    (TAU * frequency * t).sin() + 0.3 * (TAU * frequency * 2.0 * t).sin()
+                })
+                .sum::<f32>()
+                * fade_gain
+                * shimmer
+        }),
+        0.5,
+    )
+}
+
+// Kick drum on every beat, hi-hats on the off beats and a bass line following the chords
+fn synthesize_intense_layer() -> SynthSound {
+    let mut hat_noise = filtered_noise(7, 9000.0);
+    let eighth = BEAT_LENGTH / 2.0;
+
+    SynthSound::normalized(
+        render(LOOP_LENGTH, |t| {
+            let beat_t = t % BEAT_LENGTH;
+            let eighth_t = t % eighth;
+
+            let kick_frequency = 50.0 + 80.0 * (-beat_t / 0.03).exp();
+            let kick = (TAU * kick_frequency * beat_t).sin() * envelope(beat_t, 0.002, 0.12);
+
+            let hat = if beat_t >= eighth {
+                hat_noise() * envelope(beat_t - eighth, 0.001, 0.03)
+            } else {
+                0.0
+            };
+
+            let root = CHORDS[chord_at(t)][0] / 2.0;
+            let bass = (1..=3)
+                .map(|harmonic| (TAU * root * harmonic as f32 * t).sin() / harmonic as f32)
+                .sum::<f32>()
+                * envelope(eighth_t, 0.005, 0.15)
+                * (1.0 - eighth_t / eighth);
+
+            kick + hat * 6.0 + bass * 0.5
+        }),
+        0.6,
+    )
+}
+
+// Quick rising arpeggio
+fn synthesize_wave_stinger() -> SynthSound {
+    let notes = [440.0, 554.37, 659.25, 880.0];
+    let note_length = 0.1;
+
+    SynthSound::normalized(
+        render(note_length * notes.len() as f32 + 0.5, |t| {
+            notes
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| t >= *index as f32 * note_length)
+                .map(|(index, frequency)| {
+                    let note_t = t - index as f32 * note_length;
+                    (TAU * frequency * note_t).sin() * envelope(note_t, 0.005, 0.25)
+                })
+                .sum()
+        }),
+        0.8,
+    )
+}
+
+// Low minor chord swelling in and slowly fading out
+fn synthesize_game_over_stinger() -> SynthSound {
+    SynthSound::normalized(
+        render(3.0, |t| {
+            [110.0, 130.81, 164.81]
+                .iter()
+                .map(|frequency| (TAU * frequency * t).sin())
+                .sum::<f32>()
+                * envelope(t, 0.4, 0.9)
+        }),
+        0.7,
+    )
+}
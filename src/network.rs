@@ -0,0 +1,343 @@
+// Protocol shared by the dedicated server and its clients.
+// Messages are encoded with bincode and sent over UDP. The server sends snapshots of every replicated entity as deltas
+// against the last snapshot the client acknowledged, so entities that haven't changed aren't sent again, and clients
+// draw the world slightly in the past, interpolating between the two snapshots around that time.
+
+use bevy::{
+    log::warn,
+    prelude::{Component, Quat, Vec3},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::{self, ErrorKind},
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+// CONSTANTS
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const DEFAULT_SERVER_PORT: u16 = 7777;
+pub const MAX_NETWORK_PLAYERS: usize = 8;
+pub const TICK_RATE: f64 = 60.0; // Server updates per second
+pub const SNAPSHOT_INTERVAL: u32 = 2; // Ticks between snapshots
+pub const INTERPOLATION_DELAY: f64 = 0.1; // Seconds clients draw the world behind the latest snapshot
+pub const MAX_EXTRAPOLATION: f64 = 0.25; // Seconds clients keep moving entities past the latest snapshot
+pub const CLIENT_TIMEOUT: f64 = 5.0; // Seconds without messages until a client is dropped
+pub const CONNECT_RETRY_DELAY: f64 = 1.0;
+pub const SNAPSHOT_HISTORY: usize = 64; // Snapshots kept to be used as delta baselines
+const MAX_PACKET_SIZE: usize = 65_507;
+
+// COMPONENTS
+
+// Identifies an entity that is replicated from the server, the same on the server and every client
+#[derive(Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct NetworkId(pub u32);
+
+// MESSAGES
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum ClientMessage {
+    Connect { version: u32 },
+    Input(ClientInput),
+    Ack { tick: u32 }, // Latest snapshot received, used as the baseline of the next delta
+    Disconnect,
+}
+
+// Sent every frame, the shot count only goes up so a lost packet doesn't lose a shot
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct ClientInput {
+    pub aim: [f32; 3], // Tangent the cannon is aimed along, shots go the opposite way
+    pub shots: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum ServerMessage {
+    Welcome { slot: u8, level: String },
+    Rejected { reason: String },
+    Snapshot(SnapshotDelta),
+}
+
+// SNAPSHOTS
+
+// What a replicated entity is, so clients know how to draw it
+#[derive(Component, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NetworkKind {
+    Player,
+    CannonBall,
+    Asteroid { variant: u8 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct PlayerState {
+    pub slot: u8,
+    pub aim: [f32; 3],
+    pub score: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct EntityState {
+    pub kind: NetworkKind,
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub linvel: [f32; 3],
+    pub angvel: [f32; 3],
+    pub player: Option<PlayerState>,
+}
+
+// Everything replicated at one tick
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct Snapshot {
+    pub tick: u32,
+    pub round: u32,
+    pub score: i32,
+    pub wave: u32,
+    pub entities: BTreeMap<u32, EntityState>,
+}
+
+// Snapshot encoded as the changes from an earlier snapshot, or the whole snapshot if there's no baseline
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SnapshotDelta {
+    pub tick: u32,
+    pub baseline: Option<u32>,
+    pub round: u32,
+    pub score: i32,
+    pub wave: u32,
+    pub changed: Vec<(u32, EntityState)>,
+    pub removed: Vec<u32>,
+}
+
+impl EntityState {
+    pub fn translation(&self) -> Vec3 {
+        Vec3::from_array(self.translation)
+    }
+
+    pub fn rotation(&self) -> Quat {
+        Quat::from_array(self.rotation)
+    }
+
+    pub fn linvel(&self) -> Vec3 {
+        Vec3::from_array(self.linvel)
+    }
+
+    // State between this one and the next at t between 0 and 1
+    pub fn interpolate(&self, next: &EntityState, t: f32) -> EntityState {
+        EntityState {
+            translation: self.translation().lerp(next.translation(), t).to_array(),
+            rotation: self.rotation().slerp(next.rotation(), t).to_array(),
+            linvel: self.linvel().lerp(next.linvel(), t).to_array(),
+            angvel: Vec3::from_array(self.angvel)
+                .lerp(Vec3::from_array(next.angvel), t)
+                .to_array(),
+            ..*next
+        }
+    }
+
+    // State after moving at the current velocity for the given time
+    pub fn extrapolate(&self, seconds: f32) -> EntityState {
+        EntityState {
+            translation: (self.translation() + self.linvel() * seconds).to_array(),
+            ..*self
+        }
+    }
+}
+
+impl Snapshot {
+    // Changes from the baseline, entities that are exactly the same as in the baseline are left out
+    pub fn delta_from(&self, baseline: Option<&Snapshot>) -> SnapshotDelta {
+        let empty = BTreeMap::new();
+        let baseline_entities = baseline.map_or(&empty, |baseline| &baseline.entities);
+
+        SnapshotDelta {
+            tick: self.tick,
+            baseline: baseline.map(|baseline| baseline.tick),
+            round: self.round,
+            score: self.score,
+            wave: self.wave,
+            changed: self
+                .entities
+                .iter()
+                .filter(|(id, state)| baseline_entities.get(id) != Some(state))
+                .map(|(id, state)| (*id, *state))
+                .collect(),
+            removed: baseline_entities
+                .keys()
+                .filter(|id| !self.entities.contains_key(id))
+                .copied()
+                .collect(),
+        }
+    }
+
+    // Entity states at t between this snapshot and the next, entities that only exist in one of them are taken from
+    // the next one so they appear and disappear at the same time as on the server
+    pub fn interpolate(&self, next: &Snapshot, t: f32) -> BTreeMap<u32, EntityState> {
+        next.entities
+            .iter()
+            .map(|(id, next_state)| {
+                let state = match self.entities.get(id) {
+                    Some(state) if state.kind == next_state.kind => {
+                        state.interpolate(next_state, t)
+                    }
+                    _ => *next_state,
+                };
+                (*id, state)
+            })
+            .collect()
+    }
+}
+
+impl SnapshotDelta {
+    // Rebuilds the snapshot, returns None if the delta needs a baseline that isn't the one given
+    pub fn apply(&self, baseline: Option<&Snapshot>) -> Option<Snapshot> {
+        let mut entities = match (self.baseline, baseline) {
+            (None, _) => BTreeMap::new(),
+            (Some(tick), Some(baseline)) if baseline.tick == tick => baseline.entities.clone(),
+            _ => return None,
+        };
+
+        for id in self.removed.iter() {
+            entities.remove(id);
+        }
+        entities.extend(self.changed.iter().copied());
+
+        Some(Snapshot {
+            tick: self.tick,
+            round: self.round,
+            score: self.score,
+            wave: self.wave,
+            entities,
+        })
+    }
+}
+
+// SOCKET
+
+// Non-blocking UDP socket sending and receiving bincode encoded messages
+pub struct NetworkSocket {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+}
+
+impl NetworkSocket {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            buffer: vec![0; MAX_PACKET_SIZE],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    // Messages are sent unreliably, so failing to send one is only logged
+    pub fn send<T: Serialize>(&self, message: &T, to: SocketAddr) {
+        let bytes = match bincode::serialize(message) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                warn!("Failed to encode a network message: {}", err);
+                return;
+            }
+        };
+
+        if bytes.len() > MAX_PACKET_SIZE {
+            warn!(
+                "Dropped a network message of {} bytes, which is too large for a packet",
+                bytes.len()
+            );
+            return;
+        }
+
+        if let Err(err) = self.socket.send_to(&bytes, to) {
+            warn!("Failed to send a network message to {}: {}", to, err);
+        }
+    }
+
+    // Every message that has arrived since the last call, packets that can't be decoded are dropped
+    pub fn receive<T: DeserializeOwned>(&mut self) -> Vec<(SocketAddr, T)> {
+        let mut messages = Vec::new();
+
+        loop {
+            match self.socket.recv_from(&mut self.buffer) {
+                Ok((length, from)) => {
+                    if let Ok(message) = bincode::deserialize(&self.buffer[..length]) {
+                        messages.push((from, message));
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                // On some platforms a previous send to a closed port is reported on the next receive
+                Err(err) if err.kind() == ErrorKind::ConnectionReset => continue,
+                Err(err) => {
+                    warn!("Failed to receive network messages: {}", err);
+                    break;
+                }
+            }
+        }
+
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asteroid(x: f32) -> EntityState {
+        EntityState {
+            kind: NetworkKind::Asteroid { variant: 0 },
+            translation: [x, 0.0, 0.0],
+            rotation: Quat::IDENTITY.to_array(),
+            linvel: [1.0, 0.0, 0.0],
+            angvel: [0.0; 3],
+            player: None,
+        }
+    }
+
+    #[test]
+    fn deltas_only_contain_changes_and_rebuild_the_snapshot() {
+        let baseline = Snapshot {
+            tick: 2,
+            entities: BTreeMap::from([(0, asteroid(0.0)), (1, asteroid(1.0)), (2, asteroid(2.0))]),
+            ..Snapshot::default()
+        };
+        let snapshot = Snapshot {
+            tick: 4,
+            score: 3,
+            entities: BTreeMap::from([(0, asteroid(0.0)), (1, asteroid(1.5)), (3, asteroid(3.0))]),
+            ..Snapshot::default()
+        };
+
+        let delta = snapshot.delta_from(Some(&baseline));
+        assert_eq!(delta.baseline, Some(2));
+        assert_eq!(delta.changed, vec![(1, asteroid(1.5)), (3, asteroid(3.0))]);
+        assert_eq!(delta.removed, vec![2]);
+
+        assert_eq!(delta.apply(Some(&baseline)), Some(snapshot.clone()));
+        assert_eq!(delta.apply(Some(&snapshot)), None);
+        assert_eq!(delta.apply(None), None);
+        assert_eq!(snapshot.delta_from(None).apply(None), Some(snapshot));
+    }
+
+    #[test]
+    fn interpolation_blends_snapshots() {
+        let previous = Snapshot {
+            tick: 2,
+            entities: BTreeMap::from([(0, asteroid(0.0)), (1, asteroid(1.0))]),
+            ..Snapshot::default()
+        };
+        let next = Snapshot {
+            tick: 4,
+            entities: BTreeMap::from([(0, asteroid(2.0)), (2, asteroid(5.0))]),
+            ..Snapshot::default()
+        };
+
+        let states = previous.interpolate(&next, 0.25);
+        assert_eq!(states.keys().copied().collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(states[&0].translation, [0.5, 0.0, 0.0]);
+        assert_eq!(states[&2], asteroid(5.0));
+        assert_eq!(asteroid(0.0).extrapolate(2.0).translation, [2.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,88 @@
+// Seeded, smooth 3D noise and helpers for procedural geometry.
+// Sampling the noise at points on a sphere gives seamless bumps over its whole surface, so lumpy spheres like the
+// planet and asteroids are described by their radius in every direction.
+
+use bevy::prelude::Vec3;
+
+// CONSTANTS
+
+const NORMAL_SAMPLE_ANGLE: f32 = 0.002; // Angle between the samples used to find surface normals
+
+// HELPER FUNCTIONS
+
+// Outward normal of a lumpy sphere with the given radius in each direction
+pub fn radial_surface_normal(direction: Vec3, radius_at: impl Fn(Vec3) -> f32) -> Vec3 {
+    let direction = direction.normalize_or_zero();
+    let right = direction.any_orthonormal_vector();
+    let forward = direction.cross(right);
+
+    let sample = |offset: Vec3| {
+        let sample_direction = (direction + offset * NORMAL_SAMPLE_ANGLE).normalize();
+        sample_direction * radius_at(sample_direction)
+    };
+    let along_right = sample(right) - sample(-right);
+    let along_forward = sample(forward) - sample(-forward);
+
+    let normal = along_right.cross(along_forward).normalize_or_zero();
+    if normal == Vec3::ZERO {
+        direction
+    } else {
+        normal
+    }
+}
+
+// Fractal noise between about -1 and 1
+pub fn fbm(p: Vec3, octaves: u32, seed: u32) -> f32 {
+    let mut total = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    let mut max_total = 0.0;
+
+    for octave in 0..octaves.max(1) {
+        total += amplitude * value_noise(p * frequency, seed.wrapping_add(octave));
+        max_total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+
+    total / max_total
+}
+
+// Smoothly interpolated random values between -1 and 1 on an integer lattice
+pub fn value_noise(p: Vec3, seed: u32) -> f32 {
+    let cell = p.floor();
+    let t = p - cell;
+    // Quintic fade so the noise has no visible creases at the lattice
+    let fade = t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        lattice_value(
+            cell.x as i32 + dx,
+            cell.y as i32 + dy,
+            cell.z as i32 + dz,
+            seed,
+        )
+    };
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), fade.x);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), fade.x);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), fade.x);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), fade.x);
+
+    lerp(lerp(x00, x10, fade.y), lerp(x01, x11, fade.y), fade.z)
+}
+
+fn lattice_value(x: i32, y: i32, z: i32, seed: u32) -> f32 {
+    let mut hash = seed
+        ^ (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x7feb_352d);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x846c_a68b);
+    hash ^= hash >> 16;
+
+    hash as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
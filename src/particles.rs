@@ -0,0 +1,491 @@
+// Lightweight CPU particles for muzzle smoke, sparks, debris and dust trails.
+// Emitters are components that spawn particles either in a single burst or continuously. Particles are camera
+// facing quads that are hidden and reused when they die instead of being despawned, and the particle quality
+// setting caps how many of them can be alive at once. Every effect shares a few materials that particles move through as
+// they fade out, so particles don't need materials of their own.
+
+use bevy::{
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::{
+        default, shape, AlphaMode, Assets, Color, Commands, Component, Entity, EventReader,
+        GlobalTransform, Handle, Image, Mesh, PbrBundle, Query, Res, ResMut, Resource,
+        StandardMaterial, Time, Transform, Vec2, Vec3, Visibility, With, Without,
+    },
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use bevy_rapier3d::prelude::Velocity;
+use rand::{rngs::ThreadRng, thread_rng, Rng};
+
+use crate::{
+    asteroids::{Asteroid, ASTEROID_SIZE},
+    common::{AsteroidDestroyedEvent, PrimaryCamera},
+    input::ShootEvent,
+    planet::PlanetShape,
+    settings::Settings,
+};
+
+// CONSTANTS
+
+const PARTICLE_TEXTURE_SIZE: u32 = 32;
+const FADE_STEPS: usize = 8; // Materials each effect fades out through
+const DUST_RATE: f32 = 25.0; // Particles per second at the highest quality
+const DUST_MIN_SPEED: f32 = 2.0; // Asteroids rolling slower than this don't leave a trail
+const DUST_MAX_ALTITUDE: f32 = ASTEROID_SIZE + 0.3; // Asteroids higher than this above the surface aren't rolling
+
+// COMPONENTS
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParticleEffect {
+    MuzzleSmoke,
+    Sparks,
+    Debris,
+    Dust,
+}
+
+// Emits particles from its entity's position. Emitters with only a burst are despawned after emitting it
+#[derive(Component)]
+pub struct ParticleEmitter {
+    pub effect: ParticleEffect,
+    pub direction: Vec3,
+    pub burst: usize,
+    pub rate: f32, // Particles per second
+    pub active: bool,
+    pub accumulator: f32,
+}
+
+#[derive(Component)]
+pub struct Particle {
+    pub velocity: Vec3,
+    pub age: f32,
+    pub lifetime: f32,
+    pub start_size: f32,
+    pub end_size: f32,
+    pub effect: ParticleEffect,
+    pub gravity: f32, // Acceleration towards the centre of the planet
+    pub drag: f32,
+}
+
+// RESOURCES
+
+#[derive(Resource)]
+pub struct ParticleAssets {
+    pub mesh: Handle<Mesh>,
+    pub texture: Handle<Image>,
+    pub materials: Vec<Vec<Handle<StandardMaterial>>>, // By effect, from opaque to almost faded out
+}
+
+// Particles that have died are kept hidden so they can be reused
+#[derive(Resource, Default)]
+pub struct ParticlePool {
+    pub free: Vec<Entity>,
+    pub alive: usize,
+}
+
+impl ParticleEmitter {
+    pub fn burst(effect: ParticleEffect, direction: Vec3, count: usize) -> Self {
+        Self {
+            effect,
+            direction,
+            burst: count,
+            rate: 0.0,
+            active: true,
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn continuous(effect: ParticleEffect, rate: f32) -> Self {
+        Self {
+            effect,
+            direction: Vec3::ZERO,
+            burst: 0,
+            rate,
+            active: false,
+            accumulator: 0.0,
+        }
+    }
+}
+
+impl ParticleAssets {
+    // The material of a particle of the effect this far through its lifetime
+    fn material(&self, effect: ParticleEffect, progress: f32) -> Handle<StandardMaterial> {
+        let step = ((progress * FADE_STEPS as f32) as usize).min(FADE_STEPS - 1);
+        self.materials[effect as usize][step].clone()
+    }
+}
+
+impl ParticleEffect {
+    const ALL: [ParticleEffect; 4] = [
+        ParticleEffect::MuzzleSmoke,
+        ParticleEffect::Sparks,
+        ParticleEffect::Debris,
+        ParticleEffect::Dust,
+    ];
+
+    fn color(&self) -> Color {
+        match self {
+            ParticleEffect::MuzzleSmoke => Color::rgba(0.75, 0.75, 0.75, 0.6),
+            ParticleEffect::Sparks => Color::rgba(1.0, 0.8, 0.3, 1.0),
+            ParticleEffect::Debris => Color::rgba(0.35, 0.28, 0.22, 1.0),
+            ParticleEffect::Dust => Color::rgba(0.55, 0.5, 0.42, 0.4),
+        }
+    }
+
+    // Creates a particle of this effect emitted at the origin in the given direction
+    fn particle(
+        &self,
+        rng: &mut ThreadRng,
+        planet_shape: &PlanetShape,
+        origin: Vec3,
+        direction: Vec3,
+    ) -> (Vec3, Particle) {
+        let normal = origin.normalize_or_zero();
+
+        match self {
+            ParticleEffect::MuzzleSmoke => (
+                origin,
+                Particle {
+                    velocity: direction * rng.gen_range(2.0..6.0) + random_direction(rng) * 1.5,
+                    age: 0.0,
+                    lifetime: rng.gen_range(0.8..1.4),
+                    start_size: 0.4,
+                    end_size: rng.gen_range(1.2..2.0),
+                    effect: *self,
+                    gravity: 0.0,
+                    drag: 2.0,
+                },
+            ),
+            ParticleEffect::Sparks => (
+                origin,
+                Particle {
+                    velocity: random_hemisphere_direction(rng, normal) * rng.gen_range(6.0..14.0),
+                    age: 0.0,
+                    lifetime: rng.gen_range(0.2..0.5),
+                    start_size: 0.25,
+                    end_size: 0.05,
+                    effect: *self,
+                    gravity: 3.0,
+                    drag: 1.0,
+                },
+            ),
+            ParticleEffect::Debris => (
+                origin + random_direction(rng) * ASTEROID_SIZE * 0.5,
+                Particle {
+                    velocity: random_hemisphere_direction(rng, normal) * rng.gen_range(3.0..8.0),
+                    age: 0.0,
+                    lifetime: rng.gen_range(1.0..2.0),
+                    start_size: rng.gen_range(0.3..0.5),
+                    end_size: 0.2,
+                    effect: *self,
+                    gravity: 8.0,
+                    drag: 0.3,
+                },
+            ),
+            // Kicked up where the asteroid touches the surface
+            ParticleEffect::Dust => (
+                planet_shape.surface_point(origin) + normal * 0.1,
+                Particle {
+                    velocity: direction * rng.gen_range(0.5..1.5)
+                        + normal * rng.gen_range(0.5..1.0),
+                    age: 0.0,
+                    lifetime: rng.gen_range(0.6..1.0),
+                    start_size: 0.3,
+                    end_size: 1.0,
+                    effect: *self,
+                    gravity: 0.0,
+                    drag: 1.5,
+                },
+            ),
+        }
+    }
+}
+
+// STARTUP SYSTEMS
+
+pub fn setup_particle_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let texture = images.add(generate_particle_texture());
+    let materials = ParticleEffect::ALL
+        .iter()
+        .map(|effect| {
+            let color = effect.color();
+            (0..FADE_STEPS)
+                .map(|step| {
+                    let fade = 1.0 - step as f32 / FADE_STEPS as f32;
+                    materials.add(StandardMaterial {
+                        base_color: color.with_a(color.a() * fade),
+                        base_color_texture: Some(texture.clone()),
+                        unlit: true,
+                        alpha_mode: AlphaMode::Blend,
+                        ..default()
+                    })
+                })
+                .collect()
+        })
+        .collect();
+
+    commands.insert_resource(ParticleAssets {
+        mesh: meshes.add(Mesh::from(shape::Quad::new(Vec2::ONE))),
+        texture,
+        materials,
+    });
+}
+
+// The pooled particles are despawned with the rest of the round, so each round starts with an empty pool
+pub fn setup_particle_pool(mut commands: Commands) {
+    commands.insert_resource(ParticlePool::default());
+}
+
+// SYSTEMS
+
+// Muzzle smoke when shooting, sparks when a cannon ball hits an asteroid and debris whenever an asteroid is destroyed
+pub fn spawn_particle_bursts(
+    mut commands: Commands,
+    mut ev_shoot: EventReader<ShootEvent>,
+    mut ev_asteroid_destroyed: EventReader<AsteroidDestroyedEvent>,
+) {
+    for ev in ev_shoot.read() {
+        commands
+            .spawn(Transform::from_translation(ev.position))
+            .insert(GlobalTransform::from_translation(ev.position))
+            .insert(ParticleEmitter::burst(
+                ParticleEffect::MuzzleSmoke,
+                ev.direction,
+                14,
+            ));
+    }
+
+    for ev in ev_asteroid_destroyed.read() {
+        let normal = ev.position.normalize_or_zero();
+
+        if ev.points > 0 {
+            commands
+                .spawn(Transform::from_translation(ev.position))
+                .insert(GlobalTransform::from_translation(ev.position))
+                .insert(ParticleEmitter::burst(ParticleEffect::Sparks, normal, 24));
+        }
+
+        commands
+            .spawn(Transform::from_translation(ev.position))
+            .insert(GlobalTransform::from_translation(ev.position))
+            .insert(ParticleEmitter::burst(ParticleEffect::Debris, normal, 16));
+    }
+}
+
+// Asteroids leave a trail of dust while they roll along the surface
+pub fn update_dust_emitters(
+    mut commands: Commands,
+    planet_shape: Res<PlanetShape>,
+    mut asteroid_query: Query<
+        (Entity, &Transform, &Velocity, Option<&mut ParticleEmitter>),
+        With<Asteroid>,
+    >,
+) {
+    for (entity, transform, velocity, emitter) in asteroid_query.iter_mut() {
+        let Some(mut emitter) = emitter else {
+            commands
+                .entity(entity)
+                .insert(ParticleEmitter::continuous(ParticleEffect::Dust, DUST_RATE));
+            continue;
+        };
+
+        let normal = transform.translation.normalize_or_zero();
+        let tangential_velocity = velocity.linvel - normal * velocity.linvel.dot(normal);
+        let altitude = transform.translation.length()
+            - planet_shape.surface_point(transform.translation).length();
+
+        emitter.active =
+            altitude < DUST_MAX_ALTITUDE && tangential_velocity.length() > DUST_MIN_SPEED;
+        emitter.direction = -tangential_velocity.normalize_or_zero();
+    }
+}
+
+// Emits particles from the emitters, reusing dead particles and stopping at the quality setting's cap
+pub fn emit_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    particle_assets: Res<ParticleAssets>,
+    planet_shape: Res<PlanetShape>,
+    mut pool: ResMut<ParticlePool>,
+    mut emitter_query: Query<(Entity, &mut ParticleEmitter, &GlobalTransform)>,
+) {
+    let mut rng = thread_rng();
+    let quality = settings.particle_quality;
+
+    for (entity, mut emitter, emitter_transform) in emitter_query.iter_mut() {
+        let mut count = (emitter.burst as f32 * quality.emission_scale()).round() as usize;
+
+        if emitter.active && emitter.rate > 0.0 {
+            emitter.accumulator += emitter.rate * quality.emission_scale() * time.delta_seconds();
+            count += emitter.accumulator as usize;
+            emitter.accumulator = emitter.accumulator.fract();
+        }
+
+        if emitter.burst > 0 {
+            emitter.burst = 0;
+
+            if emitter.rate <= 0.0 {
+                commands.entity(entity).despawn();
+            }
+        }
+
+        for _ in 0..count {
+            if pool.alive >= quality.max_particles() {
+                break;
+            }
+
+            let (position, particle) = emitter.effect.particle(
+                &mut rng,
+                &planet_shape,
+                emitter_transform.translation(),
+                emitter.direction,
+            );
+            let transform =
+                Transform::from_translation(position).with_scale(Vec3::splat(particle.start_size));
+            let material = particle_assets.material(particle.effect, 0.0);
+
+            match pool.free.pop() {
+                Some(particle_entity) => {
+                    commands.entity(particle_entity).insert((
+                        particle,
+                        transform,
+                        material,
+                        Visibility::Visible,
+                    ));
+                }
+                None => {
+                    commands
+                        .spawn(PbrBundle {
+                            mesh: particle_assets.mesh.clone(),
+                            material,
+                            transform,
+                            ..default()
+                        })
+                        .insert(particle)
+                        .insert(NotShadowCaster)
+                        .insert(NotShadowReceiver);
+                }
+            }
+
+            pool.alive += 1;
+        }
+    }
+}
+
+// Moves, grows and fades the particles and faces them towards the camera
+pub fn update_particles(
+    time: Res<Time>,
+    particle_assets: Res<ParticleAssets>,
+    planet_shape: Res<PlanetShape>,
+    mut pool: ResMut<ParticlePool>,
+    camera_query: Query<&Transform, With<PrimaryCamera>>,
+    mut particle_query: Query<
+        (
+            Entity,
+            &mut Particle,
+            &mut Transform,
+            &mut Visibility,
+            &mut Handle<StandardMaterial>,
+        ),
+        Without<PrimaryCamera>,
+    >,
+) {
+    let delta_seconds = time.delta_seconds();
+    if delta_seconds == 0.0 {
+        return;
+    }
+
+    let camera_rotation = camera_query
+        .get_single()
+        .map(|transform| transform.rotation)
+        .unwrap_or_default();
+
+    for (entity, mut particle, mut transform, mut visibility, mut material) in
+        particle_query.iter_mut()
+    {
+        if *visibility == Visibility::Hidden {
+            continue;
+        }
+
+        particle.age += delta_seconds;
+        if particle.age >= particle.lifetime {
+            *visibility = Visibility::Hidden;
+            pool.free.push(entity);
+            pool.alive = pool.alive.saturating_sub(1);
+            continue;
+        }
+
+        let normal = transform.translation.normalize_or_zero();
+        let acceleration = -normal * particle.gravity;
+        particle.velocity += acceleration * delta_seconds;
+        let damping = 1.0 / (1.0 + particle.drag * delta_seconds);
+        particle.velocity *= damping;
+        transform.translation += particle.velocity * delta_seconds;
+
+        // Particles that fall onto the planet stay where they land
+        let surface = planet_shape.surface_point(transform.translation);
+        if transform.translation.length() < surface.length() {
+            transform.translation = surface;
+            particle.velocity = Vec3::ZERO;
+        }
+
+        let progress = particle.age / particle.lifetime;
+        transform.scale =
+            Vec3::splat(particle.start_size + (particle.end_size - particle.start_size) * progress);
+        transform.rotation = camera_rotation;
+
+        let faded_material = particle_assets.material(particle.effect, progress);
+        if *material != faded_material {
+            *material = faded_material;
+        }
+    }
+}
+
+// HELPER FUNCTIONS
+
+fn random_direction(rng: &mut ThreadRng) -> Vec3 {
+    Vec3::new(
+        rng.gen_range(-1.0..1.0),
+        rng.gen_range(-1.0..1.0),
+        rng.gen_range(-1.0..1.0),
+    )
+    .normalize_or_zero()
+}
+
+// Random direction on the side of the plane the normal points to
+fn random_hemisphere_direction(rng: &mut ThreadRng, normal: Vec3) -> Vec3 {
+    let direction = random_direction(rng);
+    if direction.dot(normal) < 0.0 {
+        -direction
+    } else {
+        direction
+    }
+}
+
+// White circle that fades out towards the edge, tinted by the effects' materials
+fn generate_particle_texture() -> Image {
+    let mut data = Vec::with_capacity((PARTICLE_TEXTURE_SIZE * PARTICLE_TEXTURE_SIZE * 4) as usize);
+    for y in 0..PARTICLE_TEXTURE_SIZE {
+        for x in 0..PARTICLE_TEXTURE_SIZE {
+            let u = (x as f32 + 0.5) / PARTICLE_TEXTURE_SIZE as f32 * 2.0 - 1.0;
+            let v = (y as f32 + 0.5) / PARTICLE_TEXTURE_SIZE as f32 * 2.0 - 1.0;
+            let falloff = (1.0 - (u * u + v * v).sqrt()).clamp(0.0, 1.0);
+            data.extend_from_slice(&[255, 255, 255, (falloff * falloff * 255.0) as u8]);
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: PARTICLE_TEXTURE_SIZE,
+            height: PARTICLE_TEXTURE_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
@@ -0,0 +1,309 @@
+// Procedural planet terrain.
+// The planet is a cube sphere whose surface is raised and lowered by seeded noise and dented by craters.
+// The same triangles are used for the render mesh and the trimesh collider, and the analytic height function is
+// kept as a resource so gameplay can find the surface point and normal in any direction.
+
+use bevy::{
+    log::warn,
+    prelude::{
+        default, AssetServer, Assets, Color, Commands, Component, Image, Mesh, Name, PbrBundle,
+        Res, ResMut, Resource, StandardMaterial, Vec2, Vec3,
+    },
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use bevy_rapier3d::prelude::{
+    CoefficientCombineRule, Collider, Friction, Restitution, TriMeshFlags,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    asset_manifest::{
+        metallic_roughness_from_roughness, noise_texture, AssetManifest, PLANET_DIFFUSE_PATH,
+        PLANET_METALLIC_ROUGHNESS_PATH, PLANET_NORMAL_PATH, PLANET_ROUGHNESS_PATH,
+    },
+    common::PLANET_SIZE,
+    game_assets::GameAssets,
+    level::{LevelConfig, PlanetConfig},
+    noise::{fbm, radial_surface_normal},
+};
+
+// CONSTANTS
+
+// Normal, and the two axes along the face such that their cross product is the normal, of each face of the cube
+const CUBE_FACES: [(Vec3, Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::Y, Vec3::Z),
+    (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+    (Vec3::Y, Vec3::Z, Vec3::X),
+    (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+    (Vec3::Z, Vec3::X, Vec3::Y),
+    (Vec3::NEG_Z, Vec3::Y, Vec3::X),
+];
+const CRATER_RIM_HEIGHT: f32 = 0.3; // Relative to the crater's depth
+const CRATER_RIM_WIDTH: f32 = 0.25; // Relative to the crater's radius
+
+// COMPONENTS
+
+#[derive(Component)]
+pub struct Planet {}
+
+// RESOURCES
+
+#[derive(Clone, Debug)]
+struct Crater {
+    centre: Vec3,
+    angular_radius: f32,
+    depth: f32,
+}
+
+// Height of the planet's surface in every direction, generated from the level's planet config
+#[derive(Resource, Clone, Debug)]
+pub struct PlanetShape {
+    config: PlanetConfig,
+    noise_seed: u32,
+    craters: Vec<Crater>,
+}
+
+impl PlanetShape {
+    pub fn new(config: &PlanetConfig) -> Self {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let noise_seed = rng.gen();
+
+        let max_radius = config.max_crater_radius.max(config.min_crater_radius);
+        let craters = (0..config.craters)
+            .map(|_| {
+                let radius = rng.gen_range(config.min_crater_radius..=max_radius);
+                Crater {
+                    centre: random_direction(&mut rng),
+                    angular_radius: radius / PLANET_SIZE,
+                    depth: config.crater_depth * radius / max_radius,
+                }
+            })
+            .filter(|crater| crater.angular_radius > 0.0)
+            .collect();
+
+        Self {
+            config: config.clone(),
+            noise_seed,
+            craters,
+        }
+    }
+
+    // Height of the surface above PLANET_SIZE in the given direction
+    pub fn height(&self, direction: Vec3) -> f32 {
+        let direction = direction.normalize_or_zero();
+
+        let hills = self.config.amplitude
+            * fbm(
+                direction * self.config.frequency,
+                self.config.octaves,
+                self.noise_seed,
+            );
+        let craters = self
+            .craters
+            .iter()
+            .map(|crater| {
+                let angle = direction.dot(crater.centre).clamp(-1.0, 1.0).acos();
+                crater_profile(angle / crater.angular_radius) * crater.depth
+            })
+            .sum::<f32>();
+
+        hills + craters
+    }
+
+    pub fn surface_point(&self, direction: Vec3) -> Vec3 {
+        direction.normalize_or_zero() * (PLANET_SIZE + self.height(direction))
+    }
+
+    // Outward normal of the surface in the given direction
+    pub fn surface_normal(&self, direction: Vec3) -> Vec3 {
+        radial_surface_normal(direction, |direction| PLANET_SIZE + self.height(direction))
+    }
+
+    // Vertices, normals, texture coordinates and triangles of the cube sphere
+    fn generate(&self) -> (Vec<Vec3>, Vec<Vec3>, Vec<Vec2>, Vec<[u32; 3]>) {
+        let resolution = self.config.resolution.max(1);
+        let face_vertices = (resolution + 1) * (resolution + 1);
+
+        let mut positions = Vec::with_capacity((face_vertices * 6) as usize);
+        let mut normals = Vec::with_capacity((face_vertices * 6) as usize);
+        let mut uvs = Vec::with_capacity((face_vertices * 6) as usize);
+        let mut triangles = Vec::with_capacity((resolution * resolution * 12) as usize);
+
+        for (face, (normal, axis_a, axis_b)) in CUBE_FACES.iter().enumerate() {
+            let first = face as u32 * face_vertices;
+
+            for j in 0..=resolution {
+                for i in 0..=resolution {
+                    let u = i as f32 / resolution as f32;
+                    let v = j as f32 / resolution as f32;
+                    let direction = cube_to_sphere(
+                        *normal + (2.0 * u - 1.0) * *axis_a + (2.0 * v - 1.0) * *axis_b,
+                    );
+
+                    positions.push(self.surface_point(direction));
+                    normals.push(self.surface_normal(direction));
+                    uvs.push(Vec2::new(u, v));
+                }
+            }
+
+            for j in 0..resolution {
+                for i in 0..resolution {
+                    let index = first + j * (resolution + 1) + i;
+                    let right = index + 1;
+                    let up = index + resolution + 1;
+                    let up_right = up + 1;
+
+                    triangles.push([index, right, up_right]);
+                    triangles.push([index, up_right, up]);
+                }
+            }
+        }
+
+        (positions, normals, uvs, triangles)
+    }
+
+    // Render mesh and collider made from the same triangles
+    pub fn mesh_and_collider(&self) -> (Mesh, Collider) {
+        let (positions, normals, uvs, triangles) = self.generate();
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            positions.iter().map(|p| p.to_array()).collect::<Vec<_>>(),
+        );
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_NORMAL,
+            normals.iter().map(|n| n.to_array()).collect::<Vec<_>>(),
+        );
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            uvs.iter().map(|uv| uv.to_array()).collect::<Vec<_>>(),
+        );
+        mesh.set_indices(Some(Indices::U32(
+            triangles.iter().flatten().copied().collect(),
+        )));
+
+        // Tangents are needed for the normal map
+        if let Err(err) = mesh.generate_tangents() {
+            warn!("Failed to generate the planet's tangents: {}", err);
+        }
+
+        // The faces of the cube share the vertices along their edges, merging them makes the collider seamless
+        let collider = Collider::trimesh_with_flags(
+            positions,
+            triangles,
+            TriMeshFlags::MERGE_DUPLICATE_VERTICES,
+        );
+
+        (mesh, collider)
+    }
+}
+
+// STARTUP SYSTEMS
+
+pub fn setup_planet(
+    mut commands: Commands,
+    level: Res<LevelConfig>,
+    game_assets: Res<GameAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let planet_shape = PlanetShape::new(&level.planet);
+    let (mesh, collider) = planet_shape.mesh_and_collider();
+
+    commands
+        .spawn(PbrBundle {
+            mesh: meshes.add(mesh),
+            material: game_assets.planet_material.clone(),
+            ..default()
+        })
+        .insert(Name::new("Planet"))
+        .insert(Planet {})
+        .insert(collider)
+        .insert(Friction {
+            coefficient: 2.0,
+            combine_rule: CoefficientCombineRule::Max,
+        })
+        .insert(Restitution {
+            coefficient: 0.0,
+            combine_rule: CoefficientCombineRule::Max,
+        });
+
+    commands.insert_resource(planet_shape);
+}
+
+// HELPER FUNCTIONS
+
+// The planet's material is shared by every round, missing textures are replaced by fallbacks
+pub fn planet_material(
+    asset_server: &AssetServer,
+    asset_manifest: &AssetManifest,
+    images: &mut Assets<Image>,
+) -> StandardMaterial {
+    StandardMaterial {
+        // base_color: Color::rgb(0.3, 0.5, 0.3),
+        base_color_texture: asset_manifest.texture(
+            asset_server,
+            images,
+            PLANET_DIFFUSE_PATH,
+            |_| {
+                Some(noise_texture(
+                    Color::rgb(0.3, 0.22, 0.16),
+                    Color::rgb(0.55, 0.45, 0.33),
+                    0,
+                ))
+            },
+        ),
+        // A flat surface looks better than a made up normal map
+        normal_map_texture: asset_manifest.texture(
+            asset_server,
+            images,
+            PLANET_NORMAL_PATH,
+            |_| None,
+        ),
+        metallic_roughness_texture: asset_manifest.texture(
+            asset_server,
+            images,
+            PLANET_METALLIC_ROUGHNESS_PATH,
+            |asset_manifest| {
+                metallic_roughness_from_roughness(asset_manifest, PLANET_ROUGHNESS_PATH)
+            },
+        ),
+        perceptual_roughness: 0.8,
+        metallic: 0.4,
+        ..default()
+    }
+}
+
+// Maps a point on the surface of the cube to the unit sphere, spreading the vertices more evenly than normalizing
+fn cube_to_sphere(p: Vec3) -> Vec3 {
+    let (x2, y2, z2) = (p.x * p.x, p.y * p.y, p.z * p.z);
+    Vec3::new(
+        p.x * (1.0 - y2 / 2.0 - z2 / 2.0 + y2 * z2 / 3.0).sqrt(),
+        p.y * (1.0 - z2 / 2.0 - x2 / 2.0 + z2 * x2 / 3.0).sqrt(),
+        p.z * (1.0 - x2 / 2.0 - y2 / 2.0 + x2 * y2 / 3.0).sqrt(),
+    )
+    .normalize()
+}
+
+// Uniformly distributed direction
+pub fn random_direction(rng: &mut StdRng) -> Vec3 {
+    loop {
+        let direction = Vec3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        let length = direction.length();
+        if length > 0.001 && length <= 1.0 {
+            return direction / length;
+        }
+    }
+}
+
+// Height of a crater of depth 1 at the given distance from its centre, relative to its radius
+fn crater_profile(x: f32) -> f32 {
+    let bowl = if x < 1.0 { -(1.0 - x * x).powi(2) } else { 0.0 };
+    let rim = CRATER_RIM_HEIGHT * (-((x - 1.0) / CRATER_RIM_WIDTH).powi(2)).exp();
+
+    bowl + rim
+}
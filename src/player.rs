@@ -1,7 +1,11 @@
+// Players are entities identified by a PlayerId, every player has a collider that is rolled around by the recoil
+// of its cannon and a cannon mesh that sits on the planet's surface under it.
+// With more than one local player they share the planet, and cannon balls knock other players around.
+
 use bevy::{
     prelude::{
-        default, AssetServer, Commands, Component, EventReader, Name, Query, Res, Resource,
-        Transform, Vec3, With,
+        default, Commands, Component, Entity, EventReader, Name, Quat, Query, Res, Transform, Vec3,
+        With,
     },
     scene::SceneBundle,
     transform::TransformBundle,
@@ -11,71 +15,181 @@ use bevy_rapier3d::prelude::{
     ExternalImpulse, Friction, GravityScale, Restitution, RigidBody, Velocity,
 };
 
-use crate::{common::PLANET_SIZE, extensions::TransformExt, input::ShootEvent};
+use std::f32::consts::TAU;
+
+use crate::{
+    extensions::TransformExt, game_assets::GameAssets, input::ShootEvent, planet::PlanetShape,
+    settings::Settings, spherical_frame::project_on_tangent_plane,
+};
 
 // CONSTANTS
 
 pub const PLAYER_SIZE: f32 = 1.0;
 pub const FIRE_DELAY: f32 = 0.5; // Delay in seconds until the next cannon can be fired
 pub const PLAYER_IMPULSE_MAGNITUDE: f32 = 200.0;
+pub const PLAYER_DENSITY: f32 = 1.0;
+pub const PLAYER_LINEAR_DAMPING: f32 = 0.1;
+pub const PLAYER_ANGULAR_DAMPING: f32 = 0.2;
+pub const FRIENDLY_FIRE_IMPULSE: f32 = 150.0; // Knockback when hit by another player's cannon ball
 
 // COMPONENTS
 
+// Identifies a local player, the player's collider, mesh and camera all have the same id
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PlayerId(pub usize);
+
+impl PlayerId {
+    // The first player uses the mouse and the primary camera
+    pub const FIRST: PlayerId = PlayerId(0);
+
+    pub fn label(&self) -> String {
+        format!("P{}", self.0 + 1)
+    }
+}
+
 #[derive(Component)]
 pub struct PlayerMesh {}
 
+// The player entity, it also has the player's PlayerId, input, shoot timer and score
 #[derive(Component)]
 pub struct PlayerCollider {}
 
-// RESOURCES
+// Points scored by a player in the current round
+#[derive(Component, Default)]
+pub struct PlayerScore(pub i32);
 
-#[derive(Resource)]
+#[derive(Component, Clone)]
 pub struct PlayerMeshDesiredTransform {
     pub position: Vec3,
-    pub tangent: Vec3,
-    pub local_up: Vec3,
-    pub local_forward: Vec3,
+    pub tangent: Vec3,       // Direction the cannon is aimed in
+    pub local_up: Vec3,      // Normal of the planet's surface under the player
+    pub local_forward: Vec3, // Camera's up direction moved to the player, used if the tangent is unusable
 }
 
 // STARTUP SYSTEMS
 
-pub fn setup_player(mut commands: Commands, asset_server: Res<AssetServer>) {
-    // Resource to store desired transform of player mesh
-    commands.insert_resource(PlayerMeshDesiredTransform {
-        position: Vec3::new(0.0, 1.0, 1.0).normalize() * (PLANET_SIZE + PLAYER_SIZE),
-        tangent: Vec3::new(0.0, 1.0, 0.0),
-        local_up: Vec3::new(0.0, 0.0, 1.0),
-        local_forward: Vec3::new(0.0, 1.0, 0.0),
-    });
+// Spawns the local players and bots spread out around the planet
+pub fn setup_player(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    planet_shape: Res<PlanetShape>,
+    settings: Res<Settings>,
+) {
+    let player_count = settings.player_count();
+    for index in 0..player_count {
+        let player_id = PlayerId(index as usize);
+        let rotation = Quat::from_rotation_y(TAU * index as f32 / player_count as f32);
+
+        spawn_player_mesh(&mut commands, &game_assets, player_id);
+        spawn_player_collider(&mut commands, &planet_shape, player_id, rotation);
+    }
+}
+
+// SYSTEMS
+
+// Sets each player mesh's transform based on the PlayerMeshDesiredTransform of the player with the same id
+pub fn set_player_mesh_transform(
+    mut player_mesh_query: Query<(&PlayerId, &mut Transform), With<PlayerMesh>>,
+    player_query: Query<(&PlayerId, &PlayerMeshDesiredTransform), With<PlayerCollider>>,
+    planet_shape: Res<PlanetShape>,
+) {
+    for (player_id, player_mesh_desired_transform) in player_query.iter() {
+        let Some((_, mut player_mesh_transform)) = player_mesh_query
+            .iter_mut()
+            .find(|(mesh_player_id, _)| *mesh_player_id == player_id)
+        else {
+            continue;
+        };
+
+        // Player mesh sits on the surface of the planet under the player collider
+        player_mesh_transform.translation =
+            planet_shape.surface_point(player_mesh_desired_transform.position);
+
+        // Rotate player transform such that it's up vector is the normal of the surface under it
+        // and it's facing the tangent, falling back to the local forward when the tangent is parallel to the up vector
+        let forward = project_on_tangent_plane(
+            player_mesh_desired_transform.tangent,
+            player_mesh_desired_transform.local_up,
+        )
+        .unwrap_or(player_mesh_desired_transform.local_forward);
+        let below = player_mesh_transform.translation - player_mesh_desired_transform.local_up;
+        player_mesh_transform.set_down(below, forward);
+    }
+}
+
+// Applies an impulse to the shooting player's collider when a ShootEvent is triggered
+pub fn apply_player_collider_impulse(
+    mut player_collider_query: Query<(&Velocity, &mut ExternalImpulse), With<PlayerCollider>>,
+    mut ev_shoot: EventReader<ShootEvent>,
+) {
+    for ev in ev_shoot.read() {
+        let Ok((player_collider_velocity, mut player_collider_impulse)) =
+            player_collider_query.get_mut(ev.shooter)
+        else {
+            continue;
+        };
+
+        // Apply impulse in the opposite direction of the shoot event
+        // the impulse in the direction of the collider's velocity is ignored
+        let impulse = -ev.direction * PLAYER_IMPULSE_MAGNITUDE;
+        let excess_velocity = player_collider_velocity.linvel;
 
-    // Player mesh
+        player_collider_impulse.impulse = impulse - excess_velocity;
+    }
+}
+
+// HELPER FUNCTIONS
+
+pub fn spawn_player_mesh(
+    commands: &mut Commands,
+    game_assets: &GameAssets,
+    player_id: PlayerId,
+) -> Entity {
     commands
         .spawn(SceneBundle {
-            scene: asset_server.load("models/cannon.glb#Scene0"),
+            scene: game_assets.cannon.clone(),
             transform: Transform::from_scale(Vec3::new(0.25, 0.25, 0.25)),
             ..default()
         })
-        .insert(Name::new("PlayerMesh"))
-        .insert(PlayerMesh {});
+        .insert(Name::new(format!("PlayerMesh_{}", player_id.label())))
+        .insert(PlayerMesh {})
+        .insert(player_id)
+        .id()
+}
 
-    // Player collider
-    let player_collider_translation =
-        Vec3::new(0.0, 0.8, 1.0).normalize() * (PLANET_SIZE + PLAYER_SIZE);
+// Spawns the player entity, the rotation around the planet's Y axis picks where on the planet it starts
+pub fn spawn_player_collider(
+    commands: &mut Commands,
+    planet_shape: &PlanetShape,
+    player_id: PlayerId,
+    rotation: Quat,
+) -> Entity {
+    let start_direction = rotation * Vec3::new(0.0, 1.0, 1.0).normalize();
+    let player_collider_direction = rotation * Vec3::new(0.0, 0.8, 1.0).normalize();
+    let player_collider_translation = planet_shape.surface_point(player_collider_direction)
+        + player_collider_direction * PLAYER_SIZE;
     commands
-        .spawn(TransformBundle::from(Transform::from_xyz(
-            player_collider_translation.x,
-            player_collider_translation.y,
-            player_collider_translation.z,
+        .spawn(TransformBundle::from(Transform::from_translation(
+            player_collider_translation,
         )))
-        .insert(Name::new("PlayerCollider"))
+        .insert(Name::new(format!("PlayerCollider_{}", player_id.label())))
         .insert(PlayerCollider {})
+        .insert(player_id)
+        .insert(PlayerScore::default())
+        // Desired transform of the player's mesh
+        .insert(PlayerMeshDesiredTransform {
+            position: planet_shape.surface_point(start_direction) + start_direction * PLAYER_SIZE,
+            tangent: rotation * Vec3::new(0.0, 1.0, 0.0),
+            local_up: planet_shape.surface_normal(start_direction),
+            local_forward: rotation * Vec3::new(0.0, 1.0, -1.0).normalize(),
+        })
         .insert(Collider::ball(PLAYER_SIZE))
         .insert(RigidBody::Dynamic)
         .insert(Damping {
-            linear_damping: 0.1,
-            angular_damping: 0.2,
+            linear_damping: PLAYER_LINEAR_DAMPING,
+            angular_damping: PLAYER_ANGULAR_DAMPING,
         })
-        .insert(ColliderMassProperties::Density(1.0))
+        .insert(ColliderMassProperties::Density(PLAYER_DENSITY))
         .insert(GravityScale(0.0))
         .insert(Friction {
             coefficient: 2.0,
@@ -96,45 +210,6 @@ pub fn setup_player(mut commands: Commands, asset_server: Res<AssetServer>) {
         .insert(ExternalImpulse {
             impulse: Vec3::ZERO,
             torque_impulse: Vec3::ZERO,
-        });
-}
-
-// SYSTEMS
-
-// Sets the player mesh's transform based on value of PlayerMeshDesiredTransform resource
-pub fn set_player_mesh_transform(
-    mut player_mesh_query: Query<&mut Transform, With<PlayerMesh>>,
-    player_mesh_desired_transform: Res<PlayerMeshDesiredTransform>,
-) {
-    let mut player_mesh_transform = player_mesh_query.single_mut();
-
-    // Player translation should be the same as the player collider translation
-    player_mesh_transform.translation =
-        player_mesh_desired_transform.position.normalize() * PLANET_SIZE;
-
-    // Rotate player transform such that it's up vector is in the same direction as player's translation vector
-    player_mesh_transform.set_down(Vec3::ZERO, player_mesh_desired_transform.local_up);
-
-    let target = player_mesh_transform.translation + player_mesh_desired_transform.tangent;
-
-    // Rotate player transform such that it's looking at the target
-    player_mesh_transform.look_at(target, player_mesh_desired_transform.local_up);
-}
-
-// Applies an impulse to play collider when a ShootEvent is triggered
-pub fn apply_player_collider_impulse(
-    mut player_collider_query: Query<(&Velocity, &mut ExternalImpulse), With<PlayerCollider>>,
-    mut ev_shoot: EventReader<ShootEvent>,
-) {
-    let (player_collider_velocity, mut player_collider_impulse) =
-        player_collider_query.single_mut();
-
-    for ev in ev_shoot.read() {
-        // Apply impulse in the opposite direction of the shoot event
-        // the impulse in the direction of the collider's velocity is ignored
-        let impulse = -ev.direction * PLAYER_IMPULSE_MAGNITUDE;
-        let excess_velocity = player_collider_velocity.linvel;
-
-        player_collider_impulse.impulse = impulse - excess_velocity;
-    }
+        })
+        .id()
 }
@@ -0,0 +1,544 @@
+// Off-screen asteroid indicators and the minimap.
+// Asteroids that are outside the camera's view or hidden behind the planet get an arrow at the edge of the screen,
+// coloured by how soon they are going to hit the player. With split screen they are shown for the first player.
+
+use bevy::{
+    prelude::{
+        default, Added, Assets, BuildChildren, Camera, Color, Commands, Component,
+        DespawnRecursiveExt, Entity, GlobalTransform, Handle, Image, ImageBundle, Name, NodeBundle,
+        Quat, Query, Res, ResMut, Resource, Transform, Vec2, Vec3, Visibility, With, Without,
+    },
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    ui::{BackgroundColor, PositionType, Style, UiImage, UiRect, Val, ZIndex},
+};
+use bevy_rapier3d::prelude::Velocity;
+
+use crate::{
+    asteroids::Asteroid,
+    common::{PrimaryCamera, PLANET_SIZE},
+    planet::PlanetShape,
+    player::{PlayerCollider, PlayerId},
+    settings::Settings,
+    trajectory::{
+        path_position, predicted_asteroid, predicted_player, simulate_trajectory, PredictedBody,
+    },
+};
+
+// CONSTANTS
+
+const INDICATOR_SIZE: f32 = 28.0;
+const INDICATOR_MARGIN: f32 = 24.0; // Distance of the indicators from the edge of the screen
+const MINIMAP_SIZE: f32 = 180.0;
+const MINIMAP_RANGE: f32 = PLANET_SIZE * 2.5; // World distance from the centre to the edge of the minimap
+const BLIP_SIZE: f32 = 8.0;
+const RADAR_IMAGE_SIZE: u32 = 64;
+const IMPACT_IMMINENT_TIME: f32 = 2.0;
+const IMPACT_SOON_TIME: f32 = 5.0;
+const IMPACT_HORIZON: f32 = 8.0; // Asteroids that won't hit the player within this many seconds aren't a threat
+const IMPACT_TIMESTEP: f32 = 1.0 / 30.0;
+const IMPACT_IMMINENT_COLOR: Color = Color::rgb(1.0, 0.15, 0.1);
+const IMPACT_SOON_COLOR: Color = Color::rgb(1.0, 0.55, 0.1);
+const IMPACT_LATER_COLOR: Color = Color::rgb(1.0, 0.95, 0.3);
+const NO_IMPACT_COLOR: Color = Color::rgba(0.8, 0.8, 0.8, 0.6);
+const PLAYER_BLIP_COLOR: Color = Color::rgb(0.3, 1.0, 0.4);
+
+// COMPONENTS
+
+// Arrow at the edge of the screen pointing towards an asteroid
+#[derive(Component)]
+pub struct OffscreenIndicator {
+    pub asteroid: Entity,
+}
+
+#[derive(Component)]
+pub struct MinimapUI {}
+
+#[derive(Component)]
+pub struct MinimapBlip {
+    pub asteroid: Entity,
+}
+
+#[derive(Component)]
+pub struct PlayerBlip {}
+
+// RESOURCES
+
+#[derive(Resource)]
+pub struct RadarImages {
+    pub arrow: Handle<Image>,
+    pub circle: Handle<Image>,
+}
+
+// STARTUP SYSTEMS
+
+// Generates the arrow and circle images used by the indicators and the minimap
+pub fn setup_radar_images(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    // Arrow pointing up
+    let arrow = generate_image(|u, v| {
+        let edge = |a: Vec2, b: Vec2, p: Vec2| (b - a).perp_dot(p - a);
+        let (apex, left, right) = (
+            Vec2::new(0.5, 0.05),
+            Vec2::new(0.1, 0.9),
+            Vec2::new(0.9, 0.9),
+        );
+        let point = Vec2::new(u, v);
+
+        edge(apex, right, point) >= 0.0
+            && edge(right, left, point) >= 0.0
+            && edge(left, apex, point) >= 0.0
+    });
+
+    let circle = generate_image(|u, v| Vec2::new(u - 0.5, v - 0.5).length() <= 0.5);
+
+    commands.insert_resource(RadarImages {
+        arrow: images.add(arrow),
+        circle: images.add(circle),
+    });
+}
+
+pub fn setup_minimap_ui(
+    mut commands: Commands,
+    radar_images: Res<RadarImages>,
+    settings: Res<Settings>,
+) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(15.0),
+                bottom: Val::Px(15.0),
+                width: Val::Px(MINIMAP_SIZE),
+                height: Val::Px(MINIMAP_SIZE),
+                ..default()
+            },
+            visibility: minimap_visibility(&settings),
+            ..default()
+        })
+        .insert(Name::new("Minimap_UI"))
+        .insert(MinimapUI {})
+        .with_children(|parent| {
+            // Planet globe, scaled to the planet's size relative to the minimap range
+            let globe_size = MINIMAP_SIZE * PLANET_SIZE / MINIMAP_RANGE;
+            parent
+                .spawn(ImageBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px((MINIMAP_SIZE - globe_size) / 2.0),
+                        top: Val::Px((MINIMAP_SIZE - globe_size) / 2.0),
+                        width: Val::Px(globe_size),
+                        height: Val::Px(globe_size),
+                        ..default()
+                    },
+                    image: UiImage::new(radar_images.circle.clone()),
+                    background_color: Color::rgba(0.3, 0.4, 0.6, 0.6).into(),
+                    ..default()
+                })
+                .insert(Name::new("Minimap_Globe"));
+
+            // Player blip, placed by update_minimap like the asteroid blips since the camera doesn't always follow
+            // the player
+            parent
+                .spawn(ImageBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(MINIMAP_SIZE / 2.0),
+                        top: Val::Px(MINIMAP_SIZE / 2.0),
+                        width: Val::Px(BLIP_SIZE),
+                        height: Val::Px(BLIP_SIZE),
+                        margin: UiRect::all(Val::Px(-BLIP_SIZE / 2.0)),
+                        ..default()
+                    },
+                    image: UiImage::new(radar_images.circle.clone()),
+                    background_color: PLAYER_BLIP_COLOR.into(),
+                    z_index: ZIndex::Local(1),
+                    ..default()
+                })
+                .insert(Name::new("Player_Blip"))
+                .insert(PlayerBlip {});
+        });
+}
+
+// SYSTEMS
+
+// Spawns an off-screen indicator and a minimap blip for every new asteroid
+pub fn spawn_radar_markers(
+    mut commands: Commands,
+    radar_images: Res<RadarImages>,
+    asteroid_query: Query<Entity, Added<Asteroid>>,
+    minimap_query: Query<Entity, With<MinimapUI>>,
+) {
+    for asteroid in asteroid_query.iter() {
+        commands
+            .spawn(ImageBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Px(INDICATOR_SIZE),
+                    height: Val::Px(INDICATOR_SIZE),
+                    ..default()
+                },
+                image: UiImage::new(radar_images.arrow.clone()),
+                visibility: Visibility::Hidden,
+                ..default()
+            })
+            .insert(Name::new("Offscreen_Indicator"))
+            .insert(OffscreenIndicator { asteroid });
+
+        for minimap in minimap_query.iter() {
+            commands.entity(minimap).with_children(|parent| {
+                parent
+                    .spawn(ImageBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            width: Val::Px(BLIP_SIZE),
+                            height: Val::Px(BLIP_SIZE),
+                            margin: UiRect::all(Val::Px(-BLIP_SIZE / 2.0)),
+                            ..default()
+                        },
+                        image: UiImage::new(radar_images.circle.clone()),
+                        visibility: Visibility::Hidden,
+                        ..default()
+                    })
+                    .insert(Name::new("Asteroid_Blip"))
+                    .insert(MinimapBlip { asteroid });
+            });
+        }
+    }
+}
+
+// Places the indicators of asteroids that can't be seen at the edge of the screen, pointing towards them
+pub fn update_offscreen_indicators(
+    mut commands: Commands,
+    planet_shape: Res<PlanetShape>,
+    camera_query: Query<(&Camera, &GlobalTransform, &PlayerId), With<PrimaryCamera>>,
+    player_query: Query<(&PlayerId, &Transform, &Velocity), With<PlayerCollider>>,
+    asteroid_query: Query<(&Transform, &Velocity), With<Asteroid>>,
+    mut indicator_query: Query<
+        (
+            Entity,
+            &OffscreenIndicator,
+            &mut Style,
+            &mut Transform,
+            &mut BackgroundColor,
+            &mut Visibility,
+        ),
+        (Without<Asteroid>, Without<PlayerCollider>),
+    >,
+) {
+    let Ok((camera, camera_transform, camera_player_id)) = camera_query.get_single() else {
+        return;
+    };
+    let Some((_, player_transform, player_velocity)) = player_query
+        .iter()
+        .find(|(player_id, _, _)| *player_id == camera_player_id)
+    else {
+        return;
+    };
+
+    // The indicators are placed at the edge of the primary camera's viewport, which is the whole window
+    // unless the screen is split between players
+    let Some(viewport) = camera.logical_viewport_rect() else {
+        return;
+    };
+    let screen_size = viewport.size();
+    let screen_center = viewport.center();
+    let world_to_camera = camera_transform.affine().inverse();
+
+    for (entity, indicator, mut style, mut transform, mut color, mut visibility) in
+        indicator_query.iter_mut()
+    {
+        let Ok((asteroid_transform, asteroid_velocity)) = asteroid_query.get(indicator.asteroid)
+        else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        let asteroid_position = asteroid_transform.translation;
+        if is_visible(camera, camera_transform, &planet_shape, asteroid_position) {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+
+        // Direction towards the asteroid in screen space, where y points down
+        let camera_space_position = world_to_camera.transform_point3(asteroid_position);
+        let direction = Vec2::new(camera_space_position.x, -camera_space_position.y)
+            .try_normalize()
+            .unwrap_or(Vec2::Y);
+
+        // Push the indicator out from the centre until it reaches the edge of the screen
+        let half_extents = (screen_size / 2.0 - INDICATOR_MARGIN).max(Vec2::ZERO);
+        let scale = (half_extents.x / direction.x.abs()).min(half_extents.y / direction.y.abs());
+        let position = screen_center + direction * scale;
+
+        style.left = Val::Px(position.x - INDICATOR_SIZE / 2.0);
+        style.top = Val::Px(position.y - INDICATOR_SIZE / 2.0);
+        transform.rotation = Quat::from_rotation_z(direction.x.atan2(-direction.y));
+        *color = threat_color(time_to_impact(
+            &planet_shape,
+            predicted_asteroid(asteroid_position, asteroid_velocity.linvel),
+            predicted_player(player_transform.translation, player_velocity.linvel),
+        ))
+        .into();
+        *visibility = Visibility::Visible;
+    }
+}
+
+// Places the player and asteroid blips on the minimap, which is a top down view of the planet from the camera
+pub fn update_minimap(
+    mut commands: Commands,
+    planet_shape: Res<PlanetShape>,
+    camera_query: Query<(&GlobalTransform, &PlayerId), With<PrimaryCamera>>,
+    player_query: Query<(&PlayerId, &Transform, &Velocity), With<PlayerCollider>>,
+    asteroid_query: Query<(&Transform, &Velocity), With<Asteroid>>,
+    mut blip_query: Query<(
+        Entity,
+        &MinimapBlip,
+        &mut Style,
+        &mut BackgroundColor,
+        &mut Visibility,
+    )>,
+    mut player_blip_query: Query<&mut Style, (With<PlayerBlip>, Without<MinimapBlip>)>,
+) {
+    let Ok((camera_transform, camera_player_id)) = camera_query.get_single() else {
+        return;
+    };
+    let Some((_, player_transform, player_velocity)) = player_query
+        .iter()
+        .find(|(player_id, _, _)| *player_id == camera_player_id)
+    else {
+        return;
+    };
+
+    let right = camera_transform.right();
+    let up = camera_transform.up();
+    let towards_camera = camera_transform.back();
+
+    for mut style in player_blip_query.iter_mut() {
+        let projected = project_on_minimap(player_transform.translation, right, up);
+        style.left = Val::Px(MINIMAP_SIZE / 2.0 + projected.x);
+        style.top = Val::Px(MINIMAP_SIZE / 2.0 + projected.y);
+    }
+
+    for (entity, blip, mut style, mut color, mut visibility) in blip_query.iter_mut() {
+        let Ok((asteroid_transform, asteroid_velocity)) = asteroid_query.get(blip.asteroid) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        let asteroid_position = asteroid_transform.translation;
+
+        let projected = project_on_minimap(asteroid_position, right, up);
+        style.left = Val::Px(MINIMAP_SIZE / 2.0 + projected.x);
+        style.top = Val::Px(MINIMAP_SIZE / 2.0 + projected.y);
+
+        let mut blip_color = threat_color(time_to_impact(
+            &planet_shape,
+            predicted_asteroid(asteroid_position, asteroid_velocity.linvel),
+            predicted_player(player_transform.translation, player_velocity.linvel),
+        ));
+
+        // Asteroids on the far side of the planet are dimmed
+        if asteroid_position.dot(towards_camera) < 0.0 {
+            blip_color.set_a(blip_color.a() * 0.35);
+        }
+
+        *color = blip_color.into();
+        *visibility = Visibility::Inherited;
+    }
+}
+
+// Shows or hides the minimap, runs when the settings have changed
+pub fn apply_minimap_settings(
+    settings: Res<Settings>,
+    mut minimap_query: Query<&mut Visibility, With<MinimapUI>>,
+) {
+    for mut visibility in minimap_query.iter_mut() {
+        *visibility = minimap_visibility(&settings);
+    }
+}
+
+// HELPER FUNCTIONS
+
+// Time until the asteroid touches the player, both falling and rolling along their predicted paths
+// Returns None if it doesn't within IMPACT_HORIZON
+pub fn time_to_impact(
+    planet_shape: &PlanetShape,
+    asteroid: PredictedBody,
+    player: PredictedBody,
+) -> Option<f32> {
+    let steps = (IMPACT_HORIZON / IMPACT_TIMESTEP) as usize;
+    let radius = asteroid.radius + player.radius;
+    let asteroid_path = simulate_trajectory(planet_shape, asteroid, steps, IMPACT_TIMESTEP);
+    let player_path = simulate_trajectory(planet_shape, player, steps, IMPACT_TIMESTEP);
+
+    (0..=steps)
+        .find(|step| {
+            path_position(&asteroid_path, *step)
+                .distance_squared(path_position(&player_path, *step))
+                <= radius * radius
+        })
+        .map(|step| step as f32 * IMPACT_TIMESTEP)
+}
+
+// Offset of a position from the centre of the minimap, projected onto the camera's view plane and clamped to the
+// edge of the minimap
+fn project_on_minimap(position: Vec3, right: Vec3, up: Vec3) -> Vec2 {
+    let projected =
+        Vec2::new(position.dot(right), -position.dot(up)) / MINIMAP_RANGE * (MINIMAP_SIZE / 2.0);
+    projected.clamp_length_max(MINIMAP_SIZE / 2.0)
+}
+
+fn threat_color(time_to_impact: Option<f32>) -> Color {
+    match time_to_impact {
+        Some(t) if t < IMPACT_IMMINENT_TIME => IMPACT_IMMINENT_COLOR,
+        Some(t) if t < IMPACT_SOON_TIME => IMPACT_SOON_COLOR,
+        Some(_) => IMPACT_LATER_COLOR,
+        None => NO_IMPACT_COLOR,
+    }
+}
+
+// Checks if a point is inside the camera's view and not hidden behind the planet
+fn is_visible(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    planet_shape: &PlanetShape,
+    point: Vec3,
+) -> bool {
+    let Some(ndc) = camera.world_to_ndc(camera_transform, point) else {
+        return false;
+    };
+
+    if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 || ndc.z < 0.0 || ndc.z > 1.0 {
+        return false;
+    }
+
+    // Check if the line of sight from the camera passes through the planet (which is at the origin)
+    let origin = camera_transform.translation();
+    let to_point = point - origin;
+    let distance = to_point.length();
+    let direction = to_point / distance;
+    let closest = (-origin).dot(direction);
+
+    let closest_point = origin + direction * closest;
+
+    !(closest > 0.0
+        && closest < distance
+        && closest_point.length() < planet_shape.surface_point(closest_point).length())
+}
+
+fn minimap_visibility(settings: &Settings) -> Visibility {
+    if settings.show_minimap {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    }
+}
+
+// Generates a white square image where pixels for which `inside` returns false are transparent
+fn generate_image(inside: impl Fn(f32, f32) -> bool) -> Image {
+    let mut data = Vec::with_capacity((RADAR_IMAGE_SIZE * RADAR_IMAGE_SIZE * 4) as usize);
+    for y in 0..RADAR_IMAGE_SIZE {
+        for x in 0..RADAR_IMAGE_SIZE {
+            let u = (x as f32 + 0.5) / RADAR_IMAGE_SIZE as f32;
+            let v = (y as f32 + 0.5) / RADAR_IMAGE_SIZE as f32;
+            let alpha = if inside(u, v) { 255 } else { 0 };
+            data.extend_from_slice(&[255, 255, 255, alpha]);
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: RADAR_IMAGE_SIZE,
+            height: RADAR_IMAGE_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asteroids::ASTEROID_SIZE, common::PLANET_SIZE, level::PlanetConfig, player::PLAYER_SIZE,
+    };
+
+    fn flat_planet() -> PlanetShape {
+        PlanetShape::new(&PlanetConfig {
+            amplitude: 0.0,
+            craters: 0,
+            ..PlanetConfig::default()
+        })
+    }
+
+    fn resting_player() -> PredictedBody {
+        predicted_player(Vec3::Y * (PLANET_SIZE + PLAYER_SIZE), Vec3::ZERO)
+    }
+
+    #[test]
+    fn minimap_projects_around_the_planet_centre() {
+        let (right, up) = (Vec3::X, Vec3::Y);
+
+        // A player the camera looks straight down on is at the centre, one it orbits away from is not
+        assert_eq!(
+            project_on_minimap(Vec3::Z * PLANET_SIZE, right, up),
+            Vec2::ZERO
+        );
+        let projected = project_on_minimap(Vec3::X * PLANET_SIZE, right, up);
+        assert_eq!(
+            projected,
+            Vec2::new(PLANET_SIZE / MINIMAP_RANGE * MINIMAP_SIZE / 2.0, 0.0)
+        );
+
+        // Screen space y points down, and far away positions stay on the minimap
+        assert!(project_on_minimap(Vec3::Y, right, up).y < 0.0);
+        let projected = project_on_minimap(Vec3::X * MINIMAP_RANGE * 2.0, right, up);
+        assert_eq!(projected.length(), MINIMAP_SIZE / 2.0);
+    }
+
+    #[test]
+    fn threat_colors_follow_the_time_to_impact() {
+        assert_eq!(threat_color(Some(0.0)), IMPACT_IMMINENT_COLOR);
+        assert_eq!(
+            threat_color(Some(IMPACT_IMMINENT_TIME - 0.01)),
+            IMPACT_IMMINENT_COLOR
+        );
+        assert_eq!(threat_color(Some(IMPACT_IMMINENT_TIME)), IMPACT_SOON_COLOR);
+        assert_eq!(
+            threat_color(Some(IMPACT_SOON_TIME - 0.01)),
+            IMPACT_SOON_COLOR
+        );
+        assert_eq!(threat_color(Some(IMPACT_SOON_TIME)), IMPACT_LATER_COLOR);
+        assert_eq!(threat_color(None), NO_IMPACT_COLOR);
+    }
+
+    #[test]
+    fn asteroids_falling_onto_the_player_are_imminent() {
+        // At rest, so it would never hit the player moving in a straight line
+        let asteroid = predicted_asteroid(Vec3::Y * PLANET_SIZE * 2.0, Vec3::ZERO);
+
+        let time = time_to_impact(&flat_planet(), asteroid, resting_player());
+        assert_eq!(threat_color(time), IMPACT_IMMINENT_COLOR);
+    }
+
+    #[test]
+    fn asteroids_resting_elsewhere_are_no_threat() {
+        let position = Vec3::X * (PLANET_SIZE + ASTEROID_SIZE / 2.0);
+        let asteroid = predicted_asteroid(position, Vec3::ZERO);
+
+        assert_eq!(
+            time_to_impact(&flat_planet(), asteroid, resting_player()),
+            None
+        );
+    }
+
+    #[test]
+    fn asteroids_rolling_towards_the_player_hit_it_before_the_horizon() {
+        let position = Vec3::X * (PLANET_SIZE + ASTEROID_SIZE / 2.0);
+        let asteroid = predicted_asteroid(position, Vec3::Y * 10.0);
+
+        let time = time_to_impact(&flat_planet(), asteroid, resting_player());
+        assert!(time.is_some_and(|time| time > 0.0 && time < IMPACT_HORIZON));
+    }
+}
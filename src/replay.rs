@@ -0,0 +1,308 @@
+// Recording and playing back rounds.
+// A recording is the round's seed and settings and every change of a player's aim and every shot, tick by tick. Played
+// back every player becomes a bot that follows the recording instead of deciding for itself, which reproduces the round
+// as long as the ticks are the same length, so both only happen with fixed ticks. Headless rounds replay exactly.
+
+use bevy::{
+    app::AppExit,
+    log::{info, warn},
+    prelude::{
+        Commands, Component, Entity, EventReader, NextState, Query, Res, ResMut, Resource, With,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::{
+    bot::BotController,
+    common::{GameSeed, GameState},
+    input::{InputDevice, PlayerInput, ShootEvent},
+    player::{PlayerCollider, PlayerId, PlayerMeshDesiredTransform},
+    settings::Settings,
+};
+
+// COMPONENTS
+
+// A player following the recording being played back, bots leave its BotController alone
+#[derive(Component)]
+pub struct ReplayedPlayer;
+
+// RESOURCES
+
+// Records the current round, and saves it to the path once it's over
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    pub path: String,
+    pub replay: Replay,
+    last_tangents: Vec<Option<[f32; 3]>>, // By PlayerId
+}
+
+// Plays back a recording from its first tick
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    pub tick: u32,
+    next_input: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Replay {
+    pub seed: u64,
+    pub settings: Settings,
+    pub ticks: u32, // Ticks recorded
+    pub inputs: Vec<ReplayInput>,
+}
+
+// A player's aim at a tick, and whether it shot
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct ReplayInput {
+    pub tick: u32,
+    pub player: usize,
+    pub tangent: [f32; 3],
+    pub fire: bool,
+}
+
+impl ReplayRecorder {
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            replay: Replay {
+                seed: 0,
+                settings: Settings::default(),
+                ticks: 0,
+                inputs: Vec::new(),
+            },
+            last_tangents: Vec::new(),
+        }
+    }
+
+    pub fn save(&self) {
+        match ron::to_string(&self.replay) {
+            Ok(contents) => match fs::write(&self.path, contents) {
+                Ok(()) => info!("Saved the recording to {}", self.path),
+                Err(err) => warn!("Failed to save the recording to {}: {}", self.path, err),
+            },
+            Err(err) => warn!("Failed to serialize the recording: {}", err),
+        }
+    }
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            tick: 0,
+            next_input: 0,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let replay = ron::from_str::<Replay>(&contents).map_err(|err| err.to_string())?;
+        Ok(Self::new(replay))
+    }
+}
+
+// STARTUP SYSTEMS
+
+// Starts the recording with the round's seed and settings, after setup_round
+pub fn start_replay_recording(
+    game_seed: Res<GameSeed>,
+    settings: Res<Settings>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    recorder.replay = Replay {
+        seed: game_seed.0,
+        settings: settings.clone(),
+        ticks: 0,
+        inputs: Vec::new(),
+    };
+    recorder.last_tangents.clear();
+}
+
+// Hands every player to the recording, after the players' input has been set up
+pub fn setup_replay_players(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    player_query: Query<Entity, With<PlayerCollider>>,
+) {
+    for entity in player_query.iter() {
+        commands.entity(entity).insert((
+            PlayerInput {
+                device: InputDevice::Bot,
+                last_valid_cursor_pos: None,
+            },
+            BotController::new(settings.bot_difficulty, 0),
+            ReplayedPlayer,
+        ));
+    }
+}
+
+// SYSTEMS
+
+// Records the aims that changed and the shots fired this tick, after handle_player_input
+pub fn record_replay_inputs(
+    mut recorder: ResMut<ReplayRecorder>,
+    mut ev_shoot: EventReader<ShootEvent>,
+    player_query: Query<(&PlayerId, &PlayerMeshDesiredTransform), With<PlayerCollider>>,
+) {
+    let tick = recorder.replay.ticks;
+    let shooters = ev_shoot
+        .read()
+        .filter_map(|ev| player_query.get(ev.shooter).ok())
+        .map(|(player_id, _)| *player_id)
+        .collect::<Vec<_>>();
+
+    let mut players = player_query.iter().collect::<Vec<_>>();
+    players.sort_by_key(|(player_id, _)| player_id.0);
+    for (player_id, player_mesh_desired_transform) in players {
+        if recorder.last_tangents.len() <= player_id.0 {
+            recorder.last_tangents.resize(player_id.0 + 1, None);
+        }
+
+        let tangent = player_mesh_desired_transform.tangent.to_array();
+        let fire = shooters.contains(player_id);
+        if fire || recorder.last_tangents[player_id.0] != Some(tangent) {
+            recorder.last_tangents[player_id.0] = Some(tangent);
+            recorder.replay.inputs.push(ReplayInput {
+                tick,
+                player: player_id.0,
+                tangent,
+                fire,
+            });
+        }
+    }
+
+    recorder.replay.ticks += 1;
+}
+
+// Aims the players and has them shoot as recorded for this tick, before handle_player_input
+pub fn play_replay_inputs(
+    mut playback: ResMut<ReplayPlayback>,
+    mut player_query: Query<
+        (
+            &PlayerId,
+            &mut PlayerMeshDesiredTransform,
+            &mut BotController,
+        ),
+        With<ReplayedPlayer>,
+    >,
+) {
+    let tick = playback.tick;
+    while let Some(input) = playback.replay.inputs.get(playback.next_input).copied() {
+        if input.tick > tick {
+            break;
+        }
+        playback.next_input += 1;
+
+        let Some((_, mut player_mesh_desired_transform, mut bot_controller)) = player_query
+            .iter_mut()
+            .find(|(player_id, _, _)| player_id.0 == input.player)
+        else {
+            continue;
+        };
+
+        // The tangent is set as recorded, aiming would project it again and round it differently
+        player_mesh_desired_transform.tangent = input.tangent.into();
+        bot_controller.aim = None;
+        bot_controller.fire = input.fire;
+    }
+
+    playback.tick += 1;
+}
+
+// Skips the main menu to play the recording back
+pub fn start_replay(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Playing);
+}
+
+// CLEANUP SYSTEMS
+
+// Saves the recording once the round is over, only the first round is recorded
+pub fn save_replay(mut commands: Commands, recorder: Res<ReplayRecorder>) {
+    recorder.save();
+    commands.remove_resource::<ReplayRecorder>();
+}
+
+// Saves the recording when the app exits before the round is over
+pub fn save_replay_on_exit(recorder: Res<ReplayRecorder>, mut ev_app_exit: EventReader<AppExit>) {
+    if ev_app_exit.read().count() > 0 {
+        recorder.save();
+    }
+}
+
+// Rounds after the recording are played normally
+pub fn end_replay(mut commands: Commands) {
+    commands.remove_resource::<ReplayPlayback>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::{App, Transform, Vec3};
+    use std::time::Duration;
+
+    use crate::{
+        asteroids::AsteroidSpawnTimer,
+        headless::{headless_app, run_headless},
+        settings::Difficulty,
+    };
+
+    const TICKS: u32 = 1200;
+
+    // Headless round with bots and asteroids coming much faster than usual
+    fn round(settings: Settings, seed: u64, resource: impl Resource) -> App {
+        let mut app = headless_app(settings, seed);
+        app.insert_resource(resource);
+        app.update();
+        app.world
+            .resource_mut::<AsteroidSpawnTimer>()
+            .0
+            .set_duration(Duration::from_millis(500));
+        app
+    }
+
+    fn player_positions(app: &mut App) -> Vec<(usize, Vec3)> {
+        let mut players = app
+            .world
+            .query_filtered::<(&PlayerId, &Transform), With<PlayerCollider>>()
+            .iter(&app.world)
+            .map(|(player_id, transform)| (player_id.0, transform.translation))
+            .collect::<Vec<_>>();
+        players.sort_by_key(|(id, _)| *id);
+        players
+    }
+
+    #[test]
+    fn replays_reproduce_the_recorded_round() {
+        let settings = Settings {
+            difficulty: Difficulty::Hard,
+            bots: 2,
+            bot_difficulty: Difficulty::Hard,
+            ..Settings::default()
+        };
+        let mut recorded = round(
+            settings.clone(),
+            9,
+            ReplayRecorder::new("unused.ron".to_string()),
+        );
+        let recorded_summary = run_headless(&mut recorded, Some(TICKS));
+        let replay = recorded.world.resource::<ReplayRecorder>().replay.clone();
+        assert!(replay.inputs.iter().any(|input| input.fire));
+
+        // The recording survives being saved and loaded
+        let replay = ron::from_str::<Replay>(&ron::to_string(&replay).unwrap()).unwrap();
+        assert_eq!(replay.seed, 9);
+
+        let mut replayed = round(
+            replay.settings.clone(),
+            replay.seed,
+            ReplayPlayback::new(replay.clone()),
+        );
+        assert_eq!(run_headless(&mut replayed, Some(TICKS)), recorded_summary);
+        assert_eq!(
+            player_positions(&mut replayed),
+            player_positions(&mut recorded)
+        );
+    }
+}
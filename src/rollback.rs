@@ -0,0 +1,1363 @@
+// Rollback netcode for one on one duels between two peers, with no server in between.
+// Both peers run the same simulation in fixed ticks and only send each other their inputs. An input that hasn't arrived
+// yet is predicted from the peer's last one, and when it arrives and differs from the prediction the game is restored to
+// the snapshot before it and the ticks since are simulated again. Peers compare checksums of the ticks both inputs are
+// known for, so a simulation that went out of sync is noticed. Both peers need the same level and difficulty.
+
+use bevy::{
+    ecs::{schedule::ScheduleLabel, system::RunSystemOnce},
+    log::{error, info, warn},
+    prelude::{
+        apply_deferred, in_state, not, resource_exists, App, Commands, Component, Entity,
+        EventReader, EventWriter, Events, IntoSystemConfigs, IntoSystemSetConfigs, Mut, NextState,
+        Or, Plugin, PostUpdate, Quat, Query, Res, ResMut, Resource, Schedule, State, Transform,
+        Update, Vec3, Visibility, With, World,
+    },
+    time::{Real, Time, Timer, TimerMode},
+};
+use bevy_rapier3d::{
+    plugin::systems::{init_colliders, init_rigid_bodies, sync_removals, writeback_rigid_bodies},
+    prelude::{
+        NoUserData, PhysicsSet, RapierConfiguration, RapierContext, RapierPhysicsPlugin,
+        RapierRigidBodyHandle, TimestepMode,
+    },
+    rapier::prelude::{
+        BroadPhase, CCDSolver, ColliderSet, ImpulseJointSet, IntegrationParameters, IslandManager,
+        MultibodyJointSet, NarrowPhase, QueryPipeline, RigidBodySet,
+    },
+};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    f32::consts::TAU,
+    hash::{Hash, Hasher},
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    time::Duration,
+};
+
+use crate::{
+    asteroids::{spawn_asteroids, Asteroid, AsteroidSpawnTimer, AsteroidWave},
+    audio::{route_asteroid_sfx, route_shoot_sfx},
+    camera::add_camera_trauma,
+    cannon_ball::{shoot_cannon_ball, CannonBall, CANNON_BALL_INITIAL_OFFSET},
+    client::local_player_id,
+    common::{
+        gravity, handle_collisions, update_combo, AsteroidDestroyedEvent, Combo, DestroyEvent,
+        GameRng, GameSeed, GameState, Score,
+    },
+    game_assets::GameAssets,
+    input::{handle_player_input, InputDevice, PlayerInput, ShootEvent, ShootTimer},
+    network::{NetworkSocket, PROTOCOL_VERSION, TICK_RATE},
+    planet::PlanetShape,
+    player::{
+        apply_player_collider_impulse, spawn_player_collider, spawn_player_mesh, PlayerCollider,
+        PlayerMeshDesiredTransform, PlayerScore, FIRE_DELAY,
+    },
+    spherical_frame::project_on_tangent_plane,
+};
+
+// CONSTANTS
+
+pub const DUEL_PLAYERS: usize = 2;
+pub const HOST_SLOT: u8 = 0; // The hosting peer picks the seed
+pub const DEFAULT_DUEL_PORT: u16 = 7778;
+pub const TICK_DURATION: Duration = Duration::from_nanos((1_000_000_000.0 / TICK_RATE) as u64);
+pub const INPUT_DELAY: u32 = 2; // Ticks local inputs are delayed by, so the peer has to predict fewer of them
+pub const MAX_PREDICTION: u32 = 8; // Ticks the simulation can run ahead of the peer's inputs before it waits for them
+pub const CHECKSUM_INTERVAL: u32 = 30; // Ticks between checksums compared with the peer
+const MAX_TICKS_PER_UPDATE: u32 = 4; // Ticks simulated at most in one update when catching up
+
+// SCHEDULES
+
+// One tick of the duel's simulation, run as many times as needed by advance_rollback
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RollbackSchedule;
+
+// COMPONENTS
+
+// Player taking part in a duel, the slot is the same on both peers
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RollbackPlayer {
+    pub slot: u8,
+}
+
+// Entity destroyed at the tick, it's only despawned once no rollback can bring it back
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Tombstone {
+    pub tick: u32,
+}
+
+// MESSAGES
+
+// Input of a player for one tick
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct RollbackInput {
+    pub aim: [f32; 3], // Tangent the cannon is aimed along, shots go the opposite way
+    pub fire: bool,
+}
+
+// Sent every update, inputs are sent again until the peer acknowledges them
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct PeerMessage {
+    pub version: u32,
+    pub seed: u64,
+    pub first_tick: u32, // Tick of the first input
+    pub inputs: Vec<RollbackInput>,
+    pub ack: u32, // The sender has every input of the receiver up to this tick
+    pub checksum: Option<(u32, u64)>, // Latest checksum of a tick both inputs are known for
+}
+
+// TRANSPORT
+
+// How peers reach each other, the tests use a simulated network
+pub trait PeerTransport: Send + Sync {
+    fn send(&mut self, message: &PeerMessage);
+    fn receive(&mut self) -> Vec<PeerMessage>;
+}
+
+// Peer reached over UDP, the hosting peer learns the address from the first message of its protocol version it
+// receives
+pub struct UdpPeer {
+    socket: NetworkSocket,
+    peer: Option<SocketAddr>,
+}
+
+impl UdpPeer {
+    pub fn host(port: u16) -> io::Result<Self> {
+        Ok(Self {
+            socket: NetworkSocket::bind(("0.0.0.0", port))?,
+            peer: None,
+        })
+    }
+
+    // The port can be left out of the address to use DEFAULT_DUEL_PORT
+    pub fn join(address: &str) -> io::Result<Self> {
+        let peer = match address.to_socket_addrs() {
+            Ok(mut addresses) => addresses.next(),
+            Err(_) => (address, DEFAULT_DUEL_PORT).to_socket_addrs()?.next(),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address found"))?;
+
+        Ok(Self {
+            socket: NetworkSocket::bind("0.0.0.0:0")?,
+            peer: Some(peer),
+        })
+    }
+}
+
+impl PeerTransport for UdpPeer {
+    fn send(&mut self, message: &PeerMessage) {
+        if let Some(peer) = self.peer {
+            self.socket.send(message, peer);
+        }
+    }
+
+    fn receive(&mut self) -> Vec<PeerMessage> {
+        let mut messages = Vec::new();
+        for (from, message) in self.socket.receive::<PeerMessage>() {
+            match self.peer {
+                Some(peer) if peer != from => continue,
+                Some(_) => {}
+                // Messages of other versions are still passed on so the session can warn about them
+                None if message.version == PROTOCOL_VERSION => self.peer = Some(from),
+                None => {}
+            }
+            messages.push(message);
+        }
+        messages
+    }
+}
+
+// RESOURCES
+
+// Tick being simulated and every player's input for it, only exists while advance_rollback runs
+#[derive(Resource)]
+pub struct RollbackTick {
+    pub tick: u32,
+    pub inputs: [RollbackInput; DUEL_PLAYERS],
+}
+
+#[derive(Resource)]
+pub struct RollbackSession {
+    transport: Box<dyn PeerTransport>,
+    pub local_slot: u8,
+    pub seed: u64,                  // The joining peer takes the host's seed
+    pub synchronized: bool,         // A message from the peer has arrived
+    pub local_input: RollbackInput, // Input the local player gives the next tick, a shot is only fired once
+    pub tick: u32,                  // Latest simulated tick
+    pub desync: Option<u32>,        // First tick the peers' checksums differed at
+    pub rollbacks: u32,
+    pub resimulated_ticks: u32,
+    pub checksums: BTreeMap<u32, u64>, // Checksums of ticks both inputs are known for
+    inputs: [BTreeMap<u32, RollbackInput>; DUEL_PLAYERS],
+    confirmed_inputs: Vec<[RollbackInput; DUEL_PLAYERS]>, // Inputs of every confirmed tick, starting at tick 1
+    remote_tick: u32, // Every input of the peer is known up to this tick
+    peer_ack: u32,
+    peer_checksums: BTreeMap<u32, u64>,
+    snapshots: VecDeque<RollbackSnapshot>, // From the latest confirmed tick to the latest tick, oldest first
+    mispredicted_tick: Option<u32>,
+    game_over_tick: Option<u32>, // Tick a player was hit at, final once the tick is confirmed
+    accumulator: Duration,
+}
+
+impl RollbackSession {
+    pub fn new(transport: impl PeerTransport + 'static, local_slot: u8, seed: u64) -> Self {
+        Self {
+            transport: Box::new(transport),
+            local_slot,
+            seed,
+            synchronized: false,
+            local_input: RollbackInput::default(),
+            tick: 0,
+            desync: None,
+            rollbacks: 0,
+            resimulated_ticks: 0,
+            checksums: BTreeMap::new(),
+            inputs: Default::default(),
+            confirmed_inputs: Vec::new(),
+            remote_tick: 0,
+            peer_ack: 0,
+            peer_checksums: BTreeMap::new(),
+            snapshots: VecDeque::new(),
+            mispredicted_tick: None,
+            game_over_tick: None,
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    pub fn remote_slot(&self) -> u8 {
+        1 - self.local_slot
+    }
+
+    // Latest tick that was simulated with both players' real inputs
+    pub fn confirmed_tick(&self) -> u32 {
+        self.tick.min(self.remote_tick)
+    }
+
+    pub fn confirmed_inputs(&self) -> &[[RollbackInput; DUEL_PLAYERS]] {
+        &self.confirmed_inputs
+    }
+
+    // A player was hit at a confirmed tick, the simulation doesn't go on
+    pub fn finished(&self) -> bool {
+        self.game_over_tick
+            .is_some_and(|tick| tick as usize <= self.confirmed_inputs.len())
+    }
+
+    fn started(&self) -> bool {
+        !self.snapshots.is_empty()
+    }
+
+    fn snapshot(&self, tick: u32) -> Option<&RollbackSnapshot> {
+        self.snapshots.iter().find(|snapshot| snapshot.tick == tick)
+    }
+
+    // Known inputs of the tick, a missing input repeats the player's last aim without firing
+    fn inputs_for(&self, tick: u32) -> [RollbackInput; DUEL_PLAYERS] {
+        std::array::from_fn(|slot| match self.inputs[slot].get(&tick) {
+            Some(input) => *input,
+            None => self.inputs[slot]
+                .range(..tick)
+                .next_back()
+                .map(|(_, input)| RollbackInput {
+                    fire: false,
+                    ..*input
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    // The local input is used INPUT_DELAY ticks after the next one
+    fn record_local_input(&mut self) {
+        let tick = self.tick + 1 + INPUT_DELAY;
+        let local_slot = self.local_slot as usize;
+        self.inputs[local_slot].insert(tick, self.local_input);
+        self.local_input.fire = false;
+    }
+
+    fn receive_messages(&mut self) {
+        let remote_slot = self.remote_slot() as usize;
+
+        for message in self.transport.receive() {
+            if message.version != PROTOCOL_VERSION {
+                warn!(
+                    "Ignored a message from a peer using protocol version {}",
+                    message.version
+                );
+                continue;
+            }
+
+            if !self.synchronized {
+                info!("Connected to the duel's other peer");
+                self.synchronized = true;
+            }
+            if self.local_slot != HOST_SLOT && !self.started() {
+                self.seed = message.seed;
+            }
+            self.peer_ack = self.peer_ack.max(message.ack);
+
+            for (index, input) in message.inputs.iter().enumerate() {
+                let tick = message.first_tick + index as u32;
+                if tick <= self.remote_tick || self.inputs[remote_slot].contains_key(&tick) {
+                    continue;
+                }
+
+                // Ticks that were simulated with a different prediction have to be simulated again
+                let mispredicted = self
+                    .snapshot(tick)
+                    .is_some_and(|snapshot| snapshot.inputs[remote_slot] != *input);
+                if mispredicted
+                    && self
+                        .mispredicted_tick
+                        .is_none_or(|earliest| tick < earliest)
+                {
+                    self.mispredicted_tick = Some(tick);
+                }
+                self.inputs[remote_slot].insert(tick, *input);
+            }
+            while self.inputs[remote_slot].contains_key(&(self.remote_tick + 1)) {
+                self.remote_tick += 1;
+            }
+
+            if let Some((tick, checksum)) = message.checksum {
+                self.peer_checksums.insert(tick, checksum);
+            }
+        }
+
+        self.compare_checksums();
+    }
+
+    fn send_messages(&mut self) {
+        let first_tick = self.peer_ack + 1;
+        let message = PeerMessage {
+            version: PROTOCOL_VERSION,
+            seed: self.seed,
+            first_tick,
+            inputs: self.inputs[self.local_slot as usize]
+                .range(first_tick..)
+                .map(|(_, input)| *input)
+                .collect(),
+            ack: self.remote_tick,
+            checksum: self
+                .checksums
+                .last_key_value()
+                .map(|(tick, checksum)| (*tick, *checksum)),
+        };
+        self.transport.send(&message);
+    }
+
+    fn compare_checksums(&mut self) {
+        let peer_checksums = std::mem::take(&mut self.peer_checksums);
+        for (tick, peer_checksum) in peer_checksums {
+            match self.checksums.get(&tick) {
+                Some(checksum) => {
+                    if *checksum != peer_checksum && self.desync.is_none() {
+                        error!(
+                            "The duel went out of sync with the other peer at tick {}",
+                            tick
+                        );
+                        self.desync = Some(tick);
+                    }
+                }
+                None => {
+                    self.peer_checksums.insert(tick, peer_checksum);
+                }
+            }
+        }
+    }
+
+    // Keeps the inputs and checksums of newly confirmed ticks, and drops what no rollback can need anymore
+    fn confirm_ticks(&mut self) {
+        let confirmed_tick = self.confirmed_tick();
+
+        while (self.confirmed_inputs.len() as u32) < confirmed_tick {
+            let tick = self.confirmed_inputs.len() as u32 + 1;
+            let Some(snapshot) = self.snapshot(tick) else {
+                break;
+            };
+            let (inputs, checksum) = (snapshot.inputs, snapshot.checksum);
+            self.confirmed_inputs.push(inputs);
+            if tick.is_multiple_of(CHECKSUM_INTERVAL) {
+                self.checksums.insert(tick, checksum);
+            }
+        }
+        self.compare_checksums();
+
+        while self
+            .snapshots
+            .front()
+            .is_some_and(|snapshot| snapshot.tick < confirmed_tick)
+        {
+            self.snapshots.pop_front();
+        }
+
+        let local_slot = self.local_slot as usize;
+        let sent_tick = confirmed_tick.min(self.peer_ack);
+        self.inputs[local_slot].retain(|tick, _| *tick > sent_tick);
+        let remote_slot = self.remote_slot() as usize;
+        self.inputs[remote_slot].retain(|tick, _| *tick >= confirmed_tick);
+    }
+}
+
+// Everything the simulation changes, saved at the end of a tick
+struct RollbackSnapshot {
+    tick: u32,
+    inputs: [RollbackInput; DUEL_PLAYERS], // Inputs the tick was simulated with, predicted or not
+    checksum: u64,
+    physics: PhysicsSnapshot,
+    entities: Vec<SavedEntity>,
+    score: i32,
+    combo: Combo,
+    rng: StdRng,
+    spawn_timer: Timer,
+    wave: u32,
+    game_over_tick: Option<u32>,
+}
+
+struct PhysicsSnapshot {
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    impulse_joints: ImpulseJointSet,
+    multibody_joints: MultibodyJointSet,
+    ccd_solver: CCDSolver,
+    query_pipeline: QueryPipeline,
+    integration_parameters: IntegrationParameters,
+}
+
+// A player, cannon ball or asteroid, their transforms and velocities are restored from the physics
+struct SavedEntity {
+    entity: Entity,
+    tombstone: Option<Tombstone>,
+    player: Option<(PlayerMeshDesiredTransform, i32)>,
+}
+
+impl PhysicsSnapshot {
+    fn save(context: &RapierContext) -> Self {
+        Self {
+            islands: context.islands.clone(),
+            broad_phase: context.broad_phase.clone(),
+            narrow_phase: context.narrow_phase.clone(),
+            bodies: context.bodies.clone(),
+            colliders: context.colliders.clone(),
+            impulse_joints: context.impulse_joints.clone(),
+            multibody_joints: context.multibody_joints.clone(),
+            ccd_solver: context.ccd_solver.clone(),
+            query_pipeline: context.query_pipeline.clone(),
+            integration_parameters: context.integration_parameters,
+        }
+    }
+
+    fn restore(&self, context: &mut RapierContext) {
+        context.islands = self.islands.clone();
+        context.broad_phase = self.broad_phase.clone();
+        context.narrow_phase = self.narrow_phase.clone();
+        context.bodies = self.bodies.clone();
+        context.colliders = self.colliders.clone();
+        context.impulse_joints = self.impulse_joints.clone();
+        context.multibody_joints = self.multibody_joints.clone();
+        context.ccd_solver = self.ccd_solver.clone();
+        context.query_pipeline = self.query_pipeline.clone();
+        context.integration_parameters = self.integration_parameters;
+    }
+}
+
+// PLUGINS
+
+pub struct RollbackPlugin;
+
+impl Plugin for RollbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_schedule(rollback_schedule());
+
+        // During a duel Rapier only steps in the simulation's ticks
+        app.configure_sets(
+            PostUpdate,
+            (
+                PhysicsSet::SyncBackend,
+                PhysicsSet::StepSimulation,
+                PhysicsSet::Writeback,
+            )
+                .run_if(not(resource_exists::<RollbackSession>())),
+        );
+
+        // The local input is collected before the ticks run, which is before anything reacts to the shots they fire
+        app.add_systems(
+            Update,
+            (
+                collect_rollback_input
+                    .after(handle_player_input)
+                    .run_if(in_state(GameState::Playing)),
+                advance_rollback,
+            )
+                .chain()
+                .before(add_camera_trauma)
+                .before(route_shoot_sfx)
+                .before(route_asteroid_sfx)
+                .run_if(resource_exists::<RollbackSession>()),
+        );
+    }
+}
+
+// Systems of one tick. Rapier creates bodies in the order it finds them, so cannon balls and asteroids are added to it
+// separately to keep the order the same on both peers. New bodies get their spawn impulse right away, which is written
+// back to their velocity so the next tick doesn't reset it to the velocity they were spawned with
+fn rollback_schedule() -> Schedule {
+    let mut schedule = Schedule::new(RollbackSchedule);
+    schedule.add_systems(
+        (
+            (
+                apply_rollback_inputs,
+                apply_player_collider_impulse,
+                shoot_cannon_ball,
+                gravity,
+                apply_deferred,
+            )
+                .chain(),
+            RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackend),
+            RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::StepSimulation),
+            RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::Writeback),
+            (
+                handle_collisions,
+                bury_destroyed,
+                update_combo,
+                spawn_asteroids,
+                apply_deferred,
+            )
+                .chain(),
+            RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackend),
+            writeback_rigid_bodies,
+            update_buried_bodies,
+        )
+            .chain(),
+    );
+    schedule
+}
+
+// STARTUP SYSTEMS
+
+// Spawns both players on opposite sides of the planet, they're added to Rapier before the local player gets its input
+// so their bodies are created in slot order on both peers
+pub fn setup_duel_players(world: &mut World) {
+    world.run_system_once(spawn_duel_players);
+    world.run_system_once(init_rigid_bodies);
+    world.run_system_once(init_colliders);
+    world.run_system_once(setup_local_duel_player);
+}
+
+fn spawn_duel_players(
+    mut commands: Commands,
+    planet_shape: Res<PlanetShape>,
+    game_assets: Res<GameAssets>,
+    session: Res<RollbackSession>,
+) {
+    for slot in 0..DUEL_PLAYERS as u8 {
+        let player_id = local_player_id(slot, Some(session.local_slot));
+        let rotation = Quat::from_rotation_y(TAU * slot as f32 / DUEL_PLAYERS as f32);
+        spawn_player_mesh(&mut commands, &game_assets, player_id);
+        let player = spawn_player_collider(&mut commands, &planet_shape, player_id, rotation);
+        commands.entity(player).insert(RollbackPlayer { slot });
+    }
+}
+
+// The local player aims with the mouse, its shots are rate limited locally before they become inputs
+fn setup_local_duel_player(
+    mut commands: Commands,
+    session: Res<RollbackSession>,
+    player_query: Query<(Entity, &RollbackPlayer)>,
+) {
+    for (entity, player) in player_query.iter() {
+        if player.slot != session.local_slot {
+            continue;
+        }
+
+        let mut timer = Timer::from_seconds(FIRE_DELAY, TimerMode::Once);
+        timer.tick(timer.duration());
+        commands.entity(entity).insert((
+            PlayerInput {
+                device: InputDevice::Mouse,
+                last_valid_cursor_pos: None,
+            },
+            ShootTimer(timer),
+        ));
+    }
+}
+
+// SYSTEMS
+
+// Takes the local player's aim and shots as its input for the next tick. The shots are taken out of the events,
+// the simulation fires them once their tick runs
+pub fn collect_rollback_input(
+    mut session: ResMut<RollbackSession>,
+    mut ev_shoot: ResMut<Events<ShootEvent>>,
+    local_player_query: Query<
+        (Entity, &PlayerMeshDesiredTransform),
+        (With<RollbackPlayer>, With<PlayerInput>),
+    >,
+) {
+    let Ok((local_player, player_mesh_desired_transform)) = local_player_query.get_single() else {
+        return;
+    };
+
+    session.local_input.aim = player_mesh_desired_transform.tangent.to_array();
+    if ev_shoot.drain().any(|ev| ev.shooter == local_player) {
+        session.local_input.fire = true;
+    }
+}
+
+// Exchanges inputs with the peer and simulates the ticks that are due, rolling back first if a prediction was wrong
+pub fn advance_rollback(world: &mut World) {
+    let playing = world
+        .get_resource::<State<GameState>>()
+        .is_some_and(|state| *state.get() == GameState::Playing);
+    let delta = world.resource::<Time<Real>>().delta();
+
+    world.resource_scope(|world, mut session: Mut<RollbackSession>| {
+        session.receive_messages();
+        if playing && session.synchronized && !session.finished() {
+            run_ticks(world, &mut session, delta);
+        }
+        session.send_messages();
+    });
+}
+
+// Aims the duel's players along their inputs and fires their shots, in slot order so cannon balls are spawned in the
+// same order on both peers
+pub fn apply_rollback_inputs(
+    rollback_tick: Res<RollbackTick>,
+    planet_shape: Res<PlanetShape>,
+    mut ev_shoot: EventWriter<ShootEvent>,
+    mut player_query: Query<(
+        Entity,
+        &RollbackPlayer,
+        &Transform,
+        &mut PlayerMeshDesiredTransform,
+    )>,
+) {
+    let mut players = player_query.iter_mut().collect::<Vec<_>>();
+    players.sort_by_key(|(_, player, _, _)| player.slot);
+
+    for (entity, player, transform, mut player_mesh_desired_transform) in players {
+        let Some(input) = rollback_tick.inputs.get(player.slot as usize) else {
+            continue;
+        };
+
+        let surface_normal = planet_shape.surface_normal(transform.translation);
+        player_mesh_desired_transform.position = transform.translation;
+        player_mesh_desired_transform.local_up = surface_normal;
+
+        // Aim is only trusted if it's a direction along the surface
+        let aim = Vec3::from_array(input.aim);
+        if aim.is_finite() {
+            if let Some(tangent) = project_on_tangent_plane(aim, surface_normal) {
+                player_mesh_desired_transform.tangent = tangent;
+            }
+        }
+
+        if input.fire {
+            let tangent = player_mesh_desired_transform.tangent;
+            ev_shoot.send(ShootEvent {
+                shooter: entity,
+                position: transform.translation - (tangent * CANNON_BALL_INITIAL_OFFSET),
+                direction: -tangent,
+            });
+        }
+    }
+}
+
+// Buries the entities destroyed in collisions instead of despawning them, so a rollback to before the tick can bring
+// them back
+pub fn bury_destroyed(
+    mut commands: Commands,
+    rollback_tick: Res<RollbackTick>,
+    mut ev_destroy: EventReader<DestroyEvent>,
+) {
+    for ev in ev_destroy.read() {
+        bury_entity(&mut commands, ev.entity, rollback_tick.tick);
+    }
+}
+
+// Disables the bodies of entities buried in this tick, and removes the ones buried before from Rapier once a step
+// has ended their contacts. Rapier's state is restored by rollbacks, so the bodies are changed in it directly
+pub fn update_buried_bodies(
+    rollback_tick: Res<RollbackTick>,
+    mut context: ResMut<RapierContext>,
+    tombstone_query: Query<(&Tombstone, &RapierRigidBodyHandle)>,
+) {
+    let context = &mut *context;
+    let mut buried = tombstone_query
+        .iter()
+        .filter(|(_, handle)| context.bodies.contains(handle.0))
+        .map(|(tombstone, handle)| (tombstone.tick, handle.0))
+        .collect::<Vec<_>>();
+    buried.sort_by_key(|(tick, handle)| (*tick, handle.into_raw_parts()));
+
+    for (tick, handle) in buried {
+        if tick < rollback_tick.tick {
+            context.bodies.remove(
+                handle,
+                &mut context.islands,
+                &mut context.colliders,
+                &mut context.impulse_joints,
+                &mut context.multibody_joints,
+                true,
+            );
+        } else if let Some(body) = context.bodies.get_mut(handle) {
+            body.set_enabled(false);
+        }
+    }
+}
+
+// CLEANUP SYSTEMS
+
+// The duel ends with its game over screen, later games are played locally
+pub fn end_rollback_session(mut commands: Commands) {
+    commands.remove_resource::<RollbackSession>();
+}
+
+// HELPER FUNCTIONS
+
+// Destroys the entity without despawning it, so a rollback to before the tick can bring it back
+pub fn bury_entity(commands: &mut Commands, entity: Entity, tick: u32) {
+    commands
+        .entity(entity)
+        .insert((Tombstone { tick }, Visibility::Hidden));
+}
+
+fn run_ticks(world: &mut World, session: &mut RollbackSession, delta: Duration) {
+    if !session.started() {
+        start_session(world, session);
+    }
+
+    // Ticks use their own fixed time and physics step, the game's are put back afterwards
+    let time = *world.resource::<Time>();
+    let rapier_configuration = *world.resource::<RapierConfiguration>();
+    world.insert_resource(RapierConfiguration {
+        physics_pipeline_active: true,
+        timestep_mode: TimestepMode::Fixed {
+            dt: TICK_DURATION.as_secs_f32(),
+            substeps: 1,
+        },
+        ..rapier_configuration
+    });
+
+    if let Some(tick) = session.mispredicted_tick.take() {
+        rollback(world, session, tick);
+    }
+
+    session.accumulator = (session.accumulator + delta).min(TICK_DURATION * MAX_TICKS_PER_UPDATE);
+    while session.accumulator >= TICK_DURATION
+        && session.tick < session.remote_tick + MAX_PREDICTION
+    {
+        session.accumulator -= TICK_DURATION;
+        session.record_local_input();
+        simulate_tick(world, session, session.tick + 1);
+    }
+
+    session.confirm_ticks();
+    if session.finished() {
+        world
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::GameOver);
+    }
+
+    // Buried entities are despawned once they're older than every snapshot
+    let oldest_tick = session
+        .snapshots
+        .front()
+        .map_or(0, |snapshot| snapshot.tick);
+    let buried = world
+        .query::<(Entity, &Tombstone)>()
+        .iter(world)
+        .filter(|(_, tombstone)| tombstone.tick < oldest_tick)
+        .map(|(entity, _)| entity)
+        .collect::<Vec<_>>();
+    for entity in buried {
+        world.despawn(entity);
+    }
+
+    world.insert_resource(time);
+    world.insert_resource(rapier_configuration);
+    world.remove_resource::<RollbackTick>();
+}
+
+// Both peers start from the host's seed, and the first local inputs are empty to make up for the input delay
+fn start_session(world: &mut World, session: &mut RollbackSession) {
+    world.insert_resource(GameSeed(session.seed));
+    world.insert_resource(GameRng(StdRng::seed_from_u64(session.seed)));
+
+    let local_slot = session.local_slot as usize;
+    for tick in 1..=INPUT_DELAY {
+        session.inputs[local_slot].insert(tick, RollbackInput::default());
+    }
+
+    let snapshot = save_snapshot(world, 0, [RollbackInput::default(); DUEL_PLAYERS], None);
+    session.snapshots.push_back(snapshot);
+}
+
+fn simulate_tick(world: &mut World, session: &mut RollbackSession, tick: u32) {
+    let inputs = session.inputs_for(tick);
+    world.insert_resource(RollbackTick { tick, inputs });
+
+    let mut time = Time::<()>::default();
+    time.advance_by(TICK_DURATION);
+    world.insert_resource(time);
+
+    world.run_schedule(RollbackSchedule);
+
+    // A player being hit only ends the game once the tick is confirmed
+    let mut next_state = world.resource_mut::<NextState<GameState>>();
+    if next_state.0 == Some(GameState::GameOver) {
+        next_state.0 = None;
+        session.game_over_tick.get_or_insert(tick);
+    }
+
+    session.tick = tick;
+    let snapshot = save_snapshot(world, tick, inputs, session.game_over_tick);
+    session.snapshots.retain(|snapshot| snapshot.tick < tick);
+    session.snapshots.push_back(snapshot);
+}
+
+// Restores the snapshot before the mispredicted tick and simulates the ticks since again, their shots and explosions
+// already happened so their events are dropped
+fn rollback(world: &mut World, session: &mut RollbackSession, mispredicted_tick: u32) {
+    let latest_tick = session.tick;
+    let Some(snapshot) = session.snapshot(mispredicted_tick - 1) else {
+        warn!(
+            "No snapshot to roll back to tick {} from",
+            mispredicted_tick - 1
+        );
+        return;
+    };
+
+    let game_over_tick = snapshot.game_over_tick;
+    restore_snapshot(world, snapshot);
+    session.game_over_tick = game_over_tick;
+    session.tick = mispredicted_tick - 1;
+
+    for tick in mispredicted_tick..=latest_tick {
+        simulate_tick(world, session, tick);
+        world.resource_mut::<Events<ShootEvent>>().clear();
+        world
+            .resource_mut::<Events<AsteroidDestroyedEvent>>()
+            .clear();
+    }
+
+    session.rollbacks += 1;
+    session.resimulated_ticks += latest_tick + 1 - mispredicted_tick;
+}
+
+fn save_snapshot(
+    world: &mut World,
+    tick: u32,
+    inputs: [RollbackInput; DUEL_PLAYERS],
+    game_over_tick: Option<u32>,
+) -> RollbackSnapshot {
+    let entities = world
+        .query_filtered::<(
+            Entity,
+            Option<&Tombstone>,
+            Option<&PlayerMeshDesiredTransform>,
+            Option<&PlayerScore>,
+        ), Or<(With<PlayerCollider>, With<CannonBall>, With<Asteroid>)>>()
+        .iter(world)
+        .map(
+            |(entity, tombstone, player_mesh_desired_transform, player_score)| SavedEntity {
+                entity,
+                tombstone: tombstone.copied(),
+                player: player_mesh_desired_transform
+                    .zip(player_score)
+                    .map(|(transform, score)| (transform.clone(), score.0)),
+            },
+        )
+        .collect();
+
+    RollbackSnapshot {
+        tick,
+        inputs,
+        checksum: checksum(world, game_over_tick),
+        physics: PhysicsSnapshot::save(world.resource::<RapierContext>()),
+        entities,
+        score: world.resource::<Score>().0,
+        combo: world.resource::<Combo>().clone(),
+        rng: world.resource::<GameRng>().0.clone(),
+        spawn_timer: world.resource::<AsteroidSpawnTimer>().0.clone(),
+        wave: world.resource::<AsteroidWave>().0,
+        game_over_tick,
+    }
+}
+
+fn restore_snapshot(world: &mut World, snapshot: &RollbackSnapshot) {
+    snapshot
+        .physics
+        .restore(&mut world.resource_mut::<RapierContext>());
+
+    // Entities spawned after the snapshot are despawned, the ones destroyed since come back
+    let saved = snapshot
+        .entities
+        .iter()
+        .map(|saved| (saved.entity, saved))
+        .collect::<HashMap<_, _>>();
+    let spawned = world
+        .query_filtered::<Entity, Or<(With<PlayerCollider>, With<CannonBall>, With<Asteroid>)>>()
+        .iter(world)
+        .filter(|entity| !saved.contains_key(entity))
+        .collect::<Vec<_>>();
+    for entity in spawned {
+        world.despawn(entity);
+    }
+
+    for saved in snapshot.entities.iter() {
+        // Buried entities older than every snapshot have been despawned for good
+        let Some(mut entity) = world.get_entity_mut(saved.entity) else {
+            continue;
+        };
+
+        if saved.tombstone.is_none() && entity.contains::<Tombstone>() {
+            entity.remove::<Tombstone>();
+            if let Some(mut visibility) = entity.get_mut::<Visibility>() {
+                *visibility = Visibility::Inherited;
+            }
+        }
+        if let Some((player_mesh_desired_transform, score)) = &saved.player {
+            entity.insert(player_mesh_desired_transform.clone());
+            if let Some(mut player_score) = entity.get_mut::<PlayerScore>() {
+                player_score.0 = *score;
+            }
+        }
+    }
+
+    world.resource_mut::<Score>().0 = snapshot.score;
+    world.insert_resource(snapshot.combo.clone());
+    world.insert_resource(GameRng(snapshot.rng.clone()));
+    world.resource_mut::<AsteroidSpawnTimer>().0 = snapshot.spawn_timer.clone();
+    world.resource_mut::<AsteroidWave>().0 = snapshot.wave;
+
+    // Rapier's entity maps forget the despawned entities before their handles are reused, and the transforms and
+    // velocities are written back from the restored bodies
+    world.run_system_once(sync_removals);
+    world.run_system_once(writeback_rigid_bodies);
+}
+
+// Hash of the simulation's state, bodies are hashed in Rapier's order which is the same on both peers
+fn checksum(world: &mut World, game_over_tick: Option<u32>) -> u64 {
+    let mut hasher = FnvHasher::default();
+
+    let context = world.resource::<RapierContext>();
+    for (handle, body) in context.bodies.iter() {
+        handle.into_raw_parts().hash(&mut hasher);
+        body.is_enabled().hash(&mut hasher);
+        let position = body.position();
+        for value in position
+            .translation
+            .vector
+            .iter()
+            .chain(position.rotation.coords.iter())
+            .chain(body.linvel().iter())
+            .chain(body.angvel().iter())
+        {
+            value.to_bits().hash(&mut hasher);
+        }
+    }
+
+    world.resource::<Score>().0.hash(&mut hasher);
+    world.resource::<AsteroidWave>().0.hash(&mut hasher);
+    world.resource::<Combo>().hits.hash(&mut hasher);
+    world
+        .resource::<GameRng>()
+        .0
+        .clone()
+        .next_u64()
+        .hash(&mut hasher);
+    game_over_tick.hash(&mut hasher);
+
+    let mut player_scores = world
+        .query::<(&RollbackPlayer, &PlayerScore)>()
+        .iter(world)
+        .map(|(player, score)| (player.slot, score.0))
+        .collect::<Vec<_>>();
+    player_scores.sort();
+    player_scores.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+// 64 bit FNV-1a, unlike the standard library's hasher it gives the same hashes with every Rust version. Integers are
+// hashed as little endian 64 bit values so peers on different platforms agree too
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as i64 as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asteroids::setup_asteroids,
+        common::setup_round,
+        level::{setup_level, Levels, LEVELS_DIRECTORY},
+        planet::setup_planet,
+        server::setup_server_assets,
+        settings::{Difficulty, Settings},
+    };
+    use bevy::{
+        asset::{AssetApp, AssetPlugin},
+        hierarchy::HierarchyPlugin,
+        prelude::{Mesh, MinimalPlugins, OnEnter, StandardMaterial, TransformPlugin},
+        scene::ScenePlugin,
+        time::TimeUpdateStrategy,
+    };
+    use rand::Rng;
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    const SEED: u64 = 7;
+    const ASTEROID_SPAWN_INTERVAL: Duration = Duration::from_millis(300);
+    const MAX_UPDATES: usize = 1000;
+
+    // Network between the two peers, messages arrive some updates after they were sent unless they're lost
+    struct SimulatedNetwork {
+        now: u32,
+        latency: u32,
+        jitter: u32,
+        loss: f64,
+        queues: [Vec<(u32, PeerMessage)>; DUEL_PLAYERS], // Messages on their way to each side and when they arrive
+        rng: StdRng,
+    }
+
+    // One side's end of the simulated network
+    struct SimulatedLink {
+        network: Arc<Mutex<SimulatedNetwork>>,
+        side: usize,
+    }
+
+    impl PeerTransport for SimulatedLink {
+        fn send(&mut self, message: &PeerMessage) {
+            let mut network = self.network.lock().unwrap();
+            let loss = network.loss;
+            if network.rng.gen_bool(loss) {
+                return;
+            }
+            let jitter = network.jitter;
+            let arrival = network.now + network.latency + network.rng.gen_range(0..=jitter);
+            network.queues[1 - self.side].push((arrival, message.clone()));
+        }
+
+        fn receive(&mut self) -> Vec<PeerMessage> {
+            let mut network = self.network.lock().unwrap();
+            let now = network.now;
+            let (arrived, on_their_way) = std::mem::take(&mut network.queues[self.side])
+                .into_iter()
+                .partition::<Vec<_>, _>(|(arrival, _)| *arrival <= now);
+            network.queues[self.side] = on_their_way;
+            arrived.into_iter().map(|(_, message)| message).collect()
+        }
+    }
+
+    fn simulated_network(latency: u32, jitter: u32, loss: f64) -> Arc<Mutex<SimulatedNetwork>> {
+        Arc::new(Mutex::new(SimulatedNetwork {
+            now: 0,
+            latency,
+            jitter,
+            loss,
+            queues: Default::default(),
+            rng: StdRng::seed_from_u64(SEED),
+        }))
+    }
+
+    // Headless peer advancing exactly one tick every update
+    fn duel_app(transport: impl PeerTransport + 'static, local_slot: u8) -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            HierarchyPlugin,
+            AssetPlugin::default(),
+            ScenePlugin,
+        ))
+        .init_asset::<Mesh>()
+        .init_asset::<StandardMaterial>()
+        .add_plugins((RapierPhysicsPlugin::<NoUserData>::default(), RollbackPlugin))
+        .add_event::<ShootEvent>()
+        .add_event::<AsteroidDestroyedEvent>()
+        .add_event::<DestroyEvent>()
+        .insert_resource(Score(0))
+        .insert_resource(Settings {
+            difficulty: Difficulty::Hard,
+            ..Settings::default()
+        })
+        .insert_resource(Levels::load(LEVELS_DIRECTORY))
+        .insert_resource(RollbackSession::new(
+            transport,
+            local_slot,
+            SEED + local_slot as u64,
+        ))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(TICK_DURATION))
+        .add_state::<GameState>()
+        .insert_resource(NextState(Some(GameState::Playing)))
+        .add_systems(
+            OnEnter(GameState::Playing),
+            (
+                setup_server_assets,
+                setup_level,
+                apply_deferred,
+                setup_planet,
+                setup_round,
+                setup_asteroids,
+                apply_deferred,
+                setup_duel_players,
+            )
+                .chain(),
+        );
+
+        // Inputs are given to the session by the tests rather than collected from the local player, and asteroids come
+        // often so cannon balls and asteroids get destroyed
+        app.update();
+        app.world
+            .resource_mut::<AsteroidSpawnTimer>()
+            .0
+            .set_duration(ASTEROID_SPAWN_INTERVAL);
+        let local_players = app
+            .world
+            .query_filtered::<Entity, With<PlayerInput>>()
+            .iter(&app.world)
+            .collect::<Vec<_>>();
+        for entity in local_players {
+            app.world.entity_mut(entity).remove::<PlayerInput>();
+        }
+        app
+    }
+
+    fn duel_pair(network: &Arc<Mutex<SimulatedNetwork>>) -> [App; DUEL_PLAYERS] {
+        std::array::from_fn(|side| {
+            let link = SimulatedLink {
+                network: network.clone(),
+                side,
+            };
+            duel_app(link, side as u8)
+        })
+    }
+
+    // Aim sweeps around the player and shots are fired every so often, differently for each slot
+    fn scripted_input(slot: u8, tick: u32) -> RollbackInput {
+        let angle = tick as f32 * 0.05 + slot as f32 * 2.0;
+        RollbackInput {
+            aim: [angle.cos(), 0.3, angle.sin()],
+            fire: (tick + slot as u32 * 20).is_multiple_of(90),
+        }
+    }
+
+    fn session(peer: &App) -> &RollbackSession {
+        peer.world.resource::<RollbackSession>()
+    }
+
+    // Updates both peers in turn, giving the local players their scripted inputs
+    fn run_scripted(
+        peers: &mut [App; DUEL_PLAYERS],
+        network: &Arc<Mutex<SimulatedNetwork>>,
+        updates: usize,
+    ) {
+        for _ in 0..updates {
+            for (slot, peer) in peers.iter_mut().enumerate() {
+                let mut session = peer.world.resource_mut::<RollbackSession>();
+                session.local_input = scripted_input(slot as u8, session.tick);
+                peer.update();
+            }
+            network.lock().unwrap().now += 1;
+        }
+    }
+
+    #[test]
+    fn lossy_peers_stay_in_sync_with_a_perfect_replay() {
+        let network = simulated_network(4, 3, 0.2);
+        let mut peers = duel_pair(&network);
+        run_scripted(&mut peers, &network, 600);
+
+        // The scripted shots end the duel after a few seconds, at the same tick for both peers
+        let host_game_over_tick = session(&peers[0]).game_over_tick;
+        for peer in peers.iter() {
+            let session = session(peer);
+            assert_eq!(session.desync, None);
+            assert!(session.finished());
+            assert_eq!(session.game_over_tick, host_game_over_tick);
+            assert!(
+                session.seed == SEED,
+                "the joining peer takes the host's seed"
+            );
+        }
+        assert!(peers.iter().any(|peer| session(peer).rollbacks > 0));
+        assert!(peers.iter().any(|peer| session(peer).resimulated_ticks > 0));
+
+        // Both peers computed the same checksums for the ticks they both confirmed
+        let [host, joiner] = [session(&peers[0]), session(&peers[1])];
+        let common_ticks = host
+            .checksums
+            .keys()
+            .filter(|tick| joiner.checksums.contains_key(tick))
+            .copied()
+            .collect::<Vec<_>>();
+        assert!(common_ticks.len() >= 4);
+        for tick in common_ticks.iter() {
+            assert_eq!(host.checksums[tick], joiner.checksums[tick]);
+        }
+
+        // Replaying the confirmed inputs without predictions or rollbacks gives the same checksums
+        let confirmed_inputs = host.confirmed_inputs().to_vec();
+        let unconnected = SimulatedLink {
+            network: simulated_network(0, 0, 1.0),
+            side: 0,
+        };
+        let mut replay = duel_app(unconnected, HOST_SLOT);
+        {
+            let mut session = replay.world.resource_mut::<RollbackSession>();
+            session.synchronized = true;
+            for (index, inputs) in confirmed_inputs.iter().enumerate() {
+                session.inputs[1].insert(index as u32 + 1, inputs[1]);
+            }
+            session.remote_tick = confirmed_inputs.len() as u32;
+        }
+        for _ in 0..MAX_UPDATES {
+            if session(&replay).finished() {
+                break;
+            }
+            let mut session = replay.world.resource_mut::<RollbackSession>();
+            let input_tick = (session.tick + INPUT_DELAY) as usize;
+            session.local_input = confirmed_inputs
+                .get(input_tick)
+                .map_or_else(RollbackInput::default, |inputs| inputs[0]);
+            replay.update();
+        }
+
+        let replay = session(&replay);
+        assert_eq!(replay.rollbacks, 0);
+        assert_eq!(replay.game_over_tick, host.game_over_tick);
+        for tick in common_ticks.iter() {
+            assert_eq!(replay.checksums.get(tick), Some(&host.checksums[tick]));
+        }
+    }
+
+    #[test]
+    fn diverging_peers_detect_the_desync() {
+        let network = simulated_network(1, 0, 0.0);
+        let mut peers = duel_pair(&network);
+        run_scripted(&mut peers, &network, 3 * CHECKSUM_INTERVAL as usize);
+        assert!(peers.iter().all(|peer| session(peer).desync.is_none()));
+
+        peers[1].world.resource_mut::<Score>().0 += 100;
+        run_scripted(&mut peers, &network, 3 * CHECKSUM_INTERVAL as usize);
+        assert!(peers.iter().any(|peer| session(peer).desync.is_some()));
+    }
+
+    #[test]
+    fn udp_peers_find_each_other() {
+        let host = UdpPeer::host(0).unwrap();
+        let port = host.socket.local_addr().unwrap().port();
+        let joiner = UdpPeer::join(&format!("127.0.0.1:{}", port)).unwrap();
+
+        let mut peers = [duel_app(host, HOST_SLOT), duel_app(joiner, 1 - HOST_SLOT)];
+        for _ in 0..MAX_UPDATES {
+            for peer in peers.iter_mut() {
+                peer.update();
+            }
+            if peers.iter().all(|peer| session(peer).tick > 0) {
+                assert_eq!(session(&peers[1]).seed, SEED);
+                return;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        panic!("Peers didn't start the duel after {} updates", MAX_UPDATES);
+    }
+
+    #[test]
+    fn udp_host_ignores_other_versions_when_learning_the_peer() {
+        let mut host = UdpPeer::host(0).unwrap();
+        let address = format!("127.0.0.1:{}", host.socket.local_addr().unwrap().port());
+        let mut stranger = UdpPeer::join(&address).unwrap();
+        let mut joiner = UdpPeer::join(&address).unwrap();
+
+        let message = PeerMessage {
+            version: PROTOCOL_VERSION,
+            seed: SEED,
+            first_tick: 0,
+            inputs: Vec::new(),
+            ack: 0,
+            checksum: None,
+        };
+        stranger.send(&PeerMessage {
+            version: PROTOCOL_VERSION + 1,
+            ..message.clone()
+        });
+        thread::sleep(Duration::from_millis(10));
+        joiner.send(&message);
+
+        for _ in 0..MAX_UPDATES {
+            host.receive();
+            if let Some(peer) = host.peer {
+                assert_eq!(peer.port(), joiner.socket.local_addr().unwrap().port());
+                return;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        panic!(
+            "The host didn't learn its peer after {} updates",
+            MAX_UPDATES
+        );
+    }
+
+    #[test]
+    fn checksum_hasher_is_fnv_1a() {
+        let mut hasher = FnvHasher::default();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+
+        let mut hasher = FnvHasher::default();
+        hasher.write(b"foobar");
+        assert_eq!(hasher.finish(), 0x8594_4171_f739_67e8);
+    }
+}
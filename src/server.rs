@@ -0,0 +1,629 @@
+// Authoritative simulation for networked games, run by the headless server binary.
+// Clients connect over UDP and each get a player on the shared planet, their aim and shots are applied to it, and
+// every SNAPSHOT_INTERVAL ticks each client is sent the changes since the last snapshot it acknowledged.
+// When a player is hit the round restarts for everyone.
+
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    asset::{AssetApp, AssetPlugin},
+    hierarchy::HierarchyPlugin,
+    log::info,
+    prelude::{
+        apply_deferred, in_state, Added, App, Assets, Commands, DespawnRecursiveExt, Entity,
+        EventWriter, Handle, IntoSystemConfigs, Mesh, MinimalPlugins, NextState, OnEnter, Or,
+        PluginGroup, Quat, Query, Res, ResMut, Resource, StandardMaterial, Startup, Transform,
+        TransformPlugin, Update, Vec3, With,
+    },
+    scene::ScenePlugin,
+    time::{Time, Timer, TimerMode},
+};
+use bevy_rapier3d::prelude::{NoUserData, RapierPhysicsPlugin, Velocity};
+use std::{
+    collections::{HashMap, VecDeque},
+    f32::consts::TAU,
+    net::SocketAddr,
+    time::Duration,
+};
+
+use crate::{
+    asteroids::{setup_asteroids, spawn_asteroids, Asteroid, AsteroidWave},
+    cannon_ball::{shoot_cannon_ball, CannonBall, CANNON_BALL_INITIAL_OFFSET},
+    common::{
+        despawn_destroyed, gravity, handle_collisions, setup_round, update_combo,
+        AsteroidDestroyedEvent, DestroyEvent, GameState, Score,
+    },
+    game_assets::GameAssets,
+    input::{ShootEvent, ShootTimer},
+    level::{setup_level, Levels, LEVELS_DIRECTORY},
+    network::{
+        ClientInput, ClientMessage, EntityState, NetworkId, NetworkKind, NetworkSocket,
+        PlayerState, ServerMessage, Snapshot, CLIENT_TIMEOUT, MAX_NETWORK_PLAYERS,
+        PROTOCOL_VERSION, SNAPSHOT_HISTORY, SNAPSHOT_INTERVAL, TICK_RATE,
+    },
+    planet::{setup_planet, PlanetShape},
+    player::{
+        apply_player_collider_impulse, spawn_player_collider, PlayerCollider, PlayerId,
+        PlayerMeshDesiredTransform, PlayerScore, FIRE_DELAY,
+    },
+    settings::Settings,
+    spherical_frame::project_on_tangent_plane,
+};
+
+// RESOURCES
+
+#[derive(Resource)]
+pub struct NetworkServer {
+    pub socket: NetworkSocket,
+    pub clients: HashMap<SocketAddr, ServerClient>,
+    pub tick: u32,
+    pub round: u32,
+    pub history: VecDeque<Snapshot>, // Latest snapshots, oldest first
+}
+
+pub struct ServerClient {
+    pub slot: u8, // Position around the planet, also the player's PlayerId
+    pub player: Option<Entity>,
+    pub input: ClientInput,
+    pub shots_fired: u32, // Shots of the client's input that have been fired
+    pub acked_tick: Option<u32>,
+    pub last_heard: f64,
+}
+
+// Network id of the next replicated entity
+#[derive(Resource, Default)]
+pub struct NextNetworkId(pub u32);
+
+impl NetworkServer {
+    pub fn new(socket: NetworkSocket) -> Self {
+        Self {
+            socket,
+            clients: HashMap::new(),
+            tick: 0,
+            round: 0,
+            history: VecDeque::new(),
+        }
+    }
+
+    // Lowest slot that isn't taken by a client
+    fn free_slot(&self) -> Option<u8> {
+        (0..MAX_NETWORK_PLAYERS as u8)
+            .find(|slot| self.clients.values().all(|client| client.slot != *slot))
+    }
+}
+
+// PLUGINS
+
+// Headless app running the simulation for the clients connected to the socket, ticking at TICK_RATE when run
+pub fn server_app(socket: NetworkSocket, level: String) -> App {
+    let mut app = App::new();
+
+    // Rapier's systems need the mesh and scene assets even though nothing is drawn
+    app.add_plugins((
+        MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+            1.0 / TICK_RATE,
+        ))),
+        TransformPlugin,
+        HierarchyPlugin,
+        AssetPlugin::default(),
+        ScenePlugin,
+    ))
+    .init_asset::<Mesh>()
+    .init_asset::<StandardMaterial>()
+    .add_plugins(RapierPhysicsPlugin::<NoUserData>::default());
+
+    // Events
+    app.add_event::<ShootEvent>();
+    app.add_event::<AsteroidDestroyedEvent>();
+    app.add_event::<DestroyEvent>();
+
+    // Resources
+    app.insert_resource(NetworkServer::new(socket));
+    app.insert_resource(Score(0));
+    app.insert_resource(Settings {
+        level,
+        ..Settings::default()
+    });
+    app.insert_resource(Levels::load(LEVELS_DIRECTORY));
+    app.init_resource::<NextNetworkId>();
+
+    // State, the server is always playing and a game over restarts the round
+    app.add_state::<GameState>();
+    app.insert_resource(NextState(Some(GameState::Playing)));
+
+    // Startup systems
+    app.add_systems(
+        Startup,
+        (
+            setup_server_assets,
+            setup_level,
+            apply_deferred,
+            setup_planet,
+            setup_round,
+            setup_asteroids,
+        )
+            .chain(),
+    );
+
+    // Systems
+    app.add_systems(
+        Update,
+        (
+            receive_client_messages,
+            (
+                apply_network_inputs,
+                apply_player_collider_impulse,
+                shoot_cannon_ball,
+                gravity,
+                handle_collisions,
+                despawn_destroyed,
+                spawn_asteroids,
+                update_combo,
+            )
+                .chain()
+                .run_if(in_state(GameState::Playing)),
+            assign_network_ids,
+            broadcast_snapshots,
+            drop_timed_out_clients,
+        )
+            .chain(),
+    );
+
+    // GameState::GameOver systems
+    app.add_systems(
+        OnEnter(GameState::GameOver),
+        (restart_round, setup_round, setup_asteroids),
+    );
+
+    app
+}
+
+// STARTUP SYSTEMS
+
+pub fn setup_server_assets(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(GameAssets::headless(&mut meshes));
+}
+
+// SYSTEMS
+
+// Handles connections and keeps the latest input of every client
+pub fn receive_client_messages(
+    mut commands: Commands,
+    mut server: ResMut<NetworkServer>,
+    settings: Res<Settings>,
+    planet_shape: Res<PlanetShape>,
+    time: Res<Time>,
+) {
+    server.tick += 1;
+    let now = time.elapsed_seconds_f64();
+
+    for (address, message) in server.socket.receive::<ClientMessage>() {
+        if let Some(client) = server.clients.get_mut(&address) {
+            client.last_heard = now;
+        }
+
+        match message {
+            ClientMessage::Connect { version } if version != PROTOCOL_VERSION => {
+                let reason = format!(
+                    "Server uses protocol version {}, the client uses {}",
+                    PROTOCOL_VERSION, version
+                );
+                server
+                    .socket
+                    .send(&ServerMessage::Rejected { reason }, address);
+            }
+            ClientMessage::Connect { .. } => {
+                // The welcome is sent again if it was lost
+                let slot = match server.clients.get(&address) {
+                    Some(client) => client.slot,
+                    None => {
+                        let Some(slot) = server.free_slot() else {
+                            let reason = "Server is full".to_string();
+                            server
+                                .socket
+                                .send(&ServerMessage::Rejected { reason }, address);
+                            continue;
+                        };
+
+                        info!("{} joined as {}", address, PlayerId(slot as usize).label());
+                        let player = spawn_network_player(&mut commands, &planet_shape, slot);
+                        server.clients.insert(
+                            address,
+                            ServerClient {
+                                slot,
+                                player: Some(player),
+                                input: ClientInput::default(),
+                                shots_fired: 0,
+                                acked_tick: None,
+                                last_heard: now,
+                            },
+                        );
+                        slot
+                    }
+                };
+
+                let welcome = ServerMessage::Welcome {
+                    slot,
+                    level: settings.level.clone(),
+                };
+                server.socket.send(&welcome, address);
+            }
+            ClientMessage::Input(input) => {
+                if let Some(client) = server.clients.get_mut(&address) {
+                    client.input = ClientInput {
+                        shots: input.shots.max(client.input.shots),
+                        ..input
+                    };
+                }
+            }
+            ClientMessage::Ack { tick } => {
+                if let Some(client) = server.clients.get_mut(&address) {
+                    if client.acked_tick.is_none_or(|acked| tick > acked) {
+                        client.acked_tick = Some(tick);
+                    }
+                }
+            }
+            ClientMessage::Disconnect => {
+                if let Some(client) = server.clients.remove(&address) {
+                    info!("{} left", address);
+                    if let Some(player) = client.player {
+                        commands.entity(player).despawn_recursive();
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Aims the clients' players and fires the shots they haven't fired yet once their cannon has reloaded
+pub fn apply_network_inputs(
+    mut server: ResMut<NetworkServer>,
+    time: Res<Time>,
+    planet_shape: Res<PlanetShape>,
+    mut ev_shoot: EventWriter<ShootEvent>,
+    mut player_query: Query<
+        (&Transform, &mut PlayerMeshDesiredTransform, &mut ShootTimer),
+        With<PlayerCollider>,
+    >,
+) {
+    for client in server.clients.values_mut() {
+        let Some(player) = client.player else {
+            continue;
+        };
+        let Ok((player_transform, mut player_mesh_desired_transform, mut shoot_timer)) =
+            player_query.get_mut(player)
+        else {
+            continue;
+        };
+
+        if !shoot_timer.0.finished() {
+            shoot_timer.0.tick(time.delta());
+        }
+
+        let surface_normal = planet_shape.surface_normal(player_transform.translation);
+        player_mesh_desired_transform.position = player_transform.translation;
+        player_mesh_desired_transform.local_up = surface_normal;
+
+        // Aim is only trusted if it's a direction along the surface
+        let aim = Vec3::from_array(client.input.aim);
+        if aim.is_finite() {
+            if let Some(tangent) = project_on_tangent_plane(aim, surface_normal) {
+                player_mesh_desired_transform.tangent = tangent;
+            }
+        }
+
+        if client.input.shots > client.shots_fired && shoot_timer.0.finished() {
+            client.shots_fired = client.input.shots;
+            shoot_timer.0.reset();
+
+            let tangent = player_mesh_desired_transform.tangent;
+            ev_shoot.send(ShootEvent {
+                shooter: player,
+                position: player_transform.translation - (tangent * CANNON_BALL_INITIAL_OFFSET),
+                direction: -tangent,
+            });
+        }
+    }
+}
+
+// Gives every new player, cannon ball and asteroid an id so it can be replicated
+pub fn assign_network_ids(
+    mut commands: Commands,
+    mut next_network_id: ResMut<NextNetworkId>,
+    game_assets: Res<GameAssets>,
+    new_entity_query: Query<
+        (
+            Entity,
+            Option<&Handle<Mesh>>,
+            Option<&PlayerCollider>,
+            Option<&CannonBall>,
+        ),
+        (Or<(Added<PlayerCollider>, Added<CannonBall>, Added<Asteroid>)>,),
+    >,
+) {
+    for (entity, mesh, player, cannon_ball) in new_entity_query.iter() {
+        let kind = if player.is_some() {
+            NetworkKind::Player
+        } else if cannon_ball.is_some() {
+            NetworkKind::CannonBall
+        } else {
+            // The asteroid's rock is the variant with the same mesh
+            let variant = game_assets
+                .asteroid_variants
+                .iter()
+                .position(|(variant_mesh, _)| Some(variant_mesh) == mesh)
+                .unwrap_or(0);
+            NetworkKind::Asteroid {
+                variant: variant as u8,
+            }
+        };
+
+        commands
+            .entity(entity)
+            .insert(NetworkId(next_network_id.0))
+            .insert(kind);
+        next_network_id.0 += 1;
+    }
+}
+
+// Sends every client the changes since the last snapshot it acknowledged
+pub fn broadcast_snapshots(
+    mut server: ResMut<NetworkServer>,
+    score: Res<Score>,
+    wave: Option<Res<AsteroidWave>>,
+    entity_query: Query<(
+        &NetworkId,
+        &NetworkKind,
+        &Transform,
+        Option<&Velocity>,
+        Option<(&PlayerId, &PlayerMeshDesiredTransform, &PlayerScore)>,
+    )>,
+) {
+    if !server.tick.is_multiple_of(SNAPSHOT_INTERVAL) {
+        return;
+    }
+
+    let snapshot = Snapshot {
+        tick: server.tick,
+        round: server.round,
+        score: score.0,
+        wave: wave.map_or(0, |wave| wave.0),
+        entities: entity_query
+            .iter()
+            .map(|(network_id, kind, transform, velocity, player)| {
+                let velocity = velocity.copied().unwrap_or_default();
+                let state = EntityState {
+                    kind: *kind,
+                    translation: transform.translation.to_array(),
+                    rotation: transform.rotation.to_array(),
+                    linvel: velocity.linvel.to_array(),
+                    angvel: velocity.angvel.to_array(),
+                    player: player.map(|(player_id, desired_transform, player_score)| {
+                        PlayerState {
+                            slot: player_id.0 as u8,
+                            aim: desired_transform.tangent.to_array(),
+                            score: player_score.0,
+                        }
+                    }),
+                };
+                (network_id.0, state)
+            })
+            .collect(),
+    };
+
+    for (address, client) in server.clients.iter() {
+        let baseline = client.acked_tick.and_then(|acked_tick| {
+            server
+                .history
+                .iter()
+                .find(|baseline| baseline.tick == acked_tick)
+        });
+        let delta = snapshot.delta_from(baseline);
+        server
+            .socket
+            .send(&ServerMessage::Snapshot(delta), *address);
+    }
+
+    server.history.push_back(snapshot);
+    if server.history.len() > SNAPSHOT_HISTORY {
+        server.history.pop_front();
+    }
+}
+
+// Removes the players of clients that haven't sent anything for a while
+pub fn drop_timed_out_clients(
+    mut commands: Commands,
+    mut server: ResMut<NetworkServer>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds_f64();
+
+    server.clients.retain(|address, client| {
+        let timed_out = now - client.last_heard > CLIENT_TIMEOUT;
+        if timed_out {
+            info!("{} timed out", address);
+            if let Some(player) = client.player {
+                commands.entity(player).despawn_recursive();
+            }
+        }
+        !timed_out
+    });
+}
+
+// Clears the planet and puts every player back at its start for the next round
+pub fn restart_round(
+    mut commands: Commands,
+    mut server: ResMut<NetworkServer>,
+    mut score: ResMut<Score>,
+    mut next_state: ResMut<NextState<GameState>>,
+    planet_shape: Res<PlanetShape>,
+    entity_query: Query<Entity, Or<(With<PlayerCollider>, With<CannonBall>, With<Asteroid>)>>,
+) {
+    for entity in entity_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    for client in server.clients.values_mut() {
+        client.player = Some(spawn_network_player(
+            &mut commands,
+            &planet_shape,
+            client.slot,
+        ));
+        client.shots_fired = client.input.shots;
+    }
+
+    server.round += 1;
+    info!("Starting round {}", server.round);
+    score.0 = 0;
+    next_state.set(GameState::Playing);
+}
+
+// HELPER FUNCTIONS
+
+// Spawns the player of the client in the slot, spread out evenly around the planet for the most players
+fn spawn_network_player(commands: &mut Commands, planet_shape: &PlanetShape, slot: u8) -> Entity {
+    let rotation = Quat::from_rotation_y(TAU * slot as f32 / MAX_NETWORK_PLAYERS as f32);
+    let player = spawn_player_collider(commands, planet_shape, PlayerId(slot as usize), rotation);
+
+    // The first cannon ball can be fired right away
+    let mut timer = Timer::from_seconds(FIRE_DELAY, TimerMode::Once);
+    timer.tick(timer.duration());
+    commands.entity(player).insert(ShootTimer(timer));
+
+    player
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::{NetworkClient, NetworkClientPlugin, NetworkPlayer},
+        level::LevelConfig,
+    };
+    use bevy::app::AppExit;
+    use std::{net::SocketAddr, thread};
+
+    const MAX_UPDATES: usize = 1000;
+
+    fn loopback_server() -> (App, SocketAddr) {
+        let socket = NetworkSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        (server_app(socket, LevelConfig::default().name), address)
+    }
+
+    // Client without any rendering, it only replicates the server's entities
+    fn loopback_client(server: SocketAddr) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, NetworkClientPlugin))
+            .add_state::<GameState>()
+            .insert_resource(NextState(Some(GameState::Playing)))
+            .insert_resource(NetworkClient::connect(&server.to_string()).unwrap());
+        app
+    }
+
+    // Updates the server and the clients until the condition holds for every client
+    fn run_until(server: &mut App, clients: &mut [App], condition: impl Fn(&mut App) -> bool) {
+        for _ in 0..MAX_UPDATES {
+            server.update();
+            for client in clients.iter_mut() {
+                client.update();
+            }
+            if clients.iter_mut().all(&condition) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        panic!("Condition not met after {} updates", MAX_UPDATES);
+    }
+
+    fn replicated_players(client: &mut App) -> Vec<(u8, Vec3)> {
+        client
+            .world
+            .query::<(&NetworkPlayer, &Transform)>()
+            .iter(&client.world)
+            .map(|(network_player, transform)| (network_player.0.slot, transform.translation))
+            .collect()
+    }
+
+    fn replicated_kinds(client: &mut App) -> Vec<NetworkKind> {
+        client
+            .world
+            .query::<&NetworkKind>()
+            .iter(&client.world)
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn clients_join_and_see_each_other() {
+        let (mut server, address) = loopback_server();
+        let mut clients = [loopback_client(address), loopback_client(address)];
+
+        run_until(&mut server, &mut clients, |client| {
+            replicated_players(client).len() == 2
+        });
+
+        let slots = clients
+            .iter()
+            .map(|client| client.world.resource::<NetworkClient>().slot())
+            .collect::<Vec<_>>();
+        assert_eq!(slots, vec![Some(0), Some(1)]);
+        assert_eq!(server.world.resource::<NetworkServer>().clients.len(), 2);
+
+        // Replicated players are drawn close to where they are on the server
+        let server_players = server
+            .world
+            .query_filtered::<(&PlayerId, &Transform), With<PlayerCollider>>()
+            .iter(&server.world)
+            .map(|(player_id, transform)| (player_id.0 as u8, transform.translation))
+            .collect::<HashMap<_, _>>();
+        for client in clients.iter_mut() {
+            for (slot, translation) in replicated_players(client) {
+                assert!(translation.distance(server_players[&slot]) < 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn shots_are_simulated_by_the_server_and_replicated() {
+        let (mut server, address) = loopback_server();
+        let mut clients = [loopback_client(address), loopback_client(address)];
+
+        run_until(&mut server, &mut clients, |client| {
+            replicated_players(client).len() == 2
+        });
+
+        clients[0].world.resource_mut::<NetworkClient>().input = ClientInput {
+            aim: Vec3::X.to_array(),
+            shots: 1,
+        };
+        run_until(&mut server, &mut clients, |client| {
+            replicated_kinds(client).contains(&NetworkKind::CannonBall)
+        });
+
+        let server_client = server
+            .world
+            .resource::<NetworkServer>()
+            .clients
+            .values()
+            .find(|client| client.slot == 0)
+            .unwrap();
+        assert_eq!(server_client.shots_fired, 1);
+    }
+
+    #[test]
+    fn disconnected_players_are_removed() {
+        let (mut server, address) = loopback_server();
+        let mut clients = [loopback_client(address), loopback_client(address)];
+
+        run_until(&mut server, &mut clients, |client| {
+            replicated_players(client).len() == 2
+        });
+
+        clients[1].world.send_event(AppExit);
+        clients[1].update();
+        run_until(&mut server, &mut clients[..1], |client| {
+            replicated_players(client).len() == 1
+        });
+        assert_eq!(server.world.resource::<NetworkServer>().clients.len(), 1);
+    }
+}
@@ -0,0 +1,539 @@
+// Settings are stored in a local file and applied on startup.
+// They can be changed from the settings menu, which is reachable from the main menu and the pause menu.
+
+use bevy::{
+    log::warn,
+    prelude::{DirectionalLight, Query, Res, Resource, States, With},
+    window::{PresentMode, PrimaryWindow, Window, WindowMode},
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::{
+    common::{format_duration, Sun, CAMERA_DELAY},
+    level::Levels,
+};
+
+// CONSTANTS
+
+pub const SETTINGS_PATH: &str = "settings.ron";
+pub const RESOLUTIONS: [(u32, u32); 5] = [
+    (1280, 720),
+    (1366, 768),
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1440),
+];
+pub const MIN_MOUSE_SENSITIVITY: f32 = 0.1;
+pub const MAX_MOUSE_SENSITIVITY: f32 = 3.0;
+pub const MIN_CAMERA_SMOOTHING: f32 = 0.05;
+pub const MAX_LOCAL_PLAYERS: u32 = 2;
+pub const MAX_BOTS: u32 = 3;
+pub const VERSUS_RULES: [VersusRules; 5] = [
+    VersusRules::Off,
+    VersusRules::Timed(120),
+    VersusRules::Timed(300),
+    VersusRules::FirstTo(5),
+    VersusRules::FirstTo(10),
+];
+const VOLUME_STEP: f32 = 0.1;
+const MOUSE_SENSITIVITY_STEP: f32 = 0.1;
+const CAMERA_SMOOTHING_STEP: f32 = 0.05;
+
+// STATES
+
+// Settings menu is an overlay so it can be opened on top of both the main menu and the pause menu
+#[derive(States, Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub enum SettingsMenuState {
+    #[default]
+    Closed,
+    Open,
+}
+
+// RESOURCES
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WindowModeSetting {
+    Windowed,
+    BorderlessFullscreen,
+    Fullscreen,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+// How a versus round between local players ends, with the rules off they play together for the shared score
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VersusRules {
+    Off,
+    Timed(u32),   // Seconds until the round ends
+    FirstTo(u32), // Points needed to win the round
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParticleQuality {
+    Off,
+    Low,
+    Medium,
+    High,
+}
+
+// File the settings are loaded from and saved to
+#[derive(Resource)]
+pub struct SettingsPath(pub String);
+
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct Settings {
+    pub window_mode: WindowModeSetting,
+    pub resolution: (u32, u32),
+    pub vsync: bool,
+    pub shadows: bool,
+    pub particle_quality: ParticleQuality,
+    pub show_minimap: bool,
+    pub show_trajectory: bool,
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub mouse_sensitivity: f32, // Speed of dragging the orbit camera around, the cursor aims at where it points
+    pub camera_smoothing: f32,
+    pub difficulty: Difficulty,
+    pub level: String,
+    pub players: u32, // Local players sharing the planet, each with their own camera and input device
+    pub versus: VersusRules,
+    pub bots: u32, // Bot players joining the local players
+    pub bot_difficulty: Difficulty,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window_mode: WindowModeSetting::Windowed,
+            resolution: (1280, 720),
+            vsync: true,
+            shadows: true,
+            particle_quality: ParticleQuality::Medium,
+            show_minimap: true,
+            show_trajectory: true,
+            master_volume: 1.0,
+            music_volume: 0.7,
+            sfx_volume: 1.0,
+            mouse_sensitivity: 1.0,
+            camera_smoothing: CAMERA_DELAY,
+            difficulty: Difficulty::Normal,
+            level: "Corona".to_string(),
+            players: 1,
+            versus: VersusRules::Off,
+            bots: 0,
+            bot_difficulty: Difficulty::Normal,
+        }
+    }
+}
+
+impl Settings {
+    // Loads the settings, falling back to the defaults for anything missing or invalid
+    pub fn load(path: &str) -> Self {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return Self::default(),
+        };
+
+        match ron::from_str::<Settings>(&contents) {
+            Ok(settings) => settings.sanitized(),
+            Err(err) => {
+                warn!(
+                    "Failed to parse settings from {}, using defaults: {}",
+                    path, err
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &str) {
+        match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(contents) => {
+                if let Err(err) = fs::write(path, contents) {
+                    warn!("Failed to save settings to {}: {}", path, err);
+                }
+            }
+            Err(err) => warn!("Failed to serialize settings: {}", err),
+        }
+    }
+
+    // Applies the window mode, resolution and vsync settings to a window
+    pub fn apply_to_window(&self, window: &mut Window) {
+        window.mode = match self.window_mode {
+            WindowModeSetting::Windowed => WindowMode::Windowed,
+            WindowModeSetting::BorderlessFullscreen => WindowMode::BorderlessFullscreen,
+            WindowModeSetting::Fullscreen => WindowMode::Fullscreen,
+        };
+        window
+            .resolution
+            .set(self.resolution.0 as f32, self.resolution.1 as f32);
+        window.present_mode = if self.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        };
+    }
+
+    // Every player sharing the planet, the local players come first and the bots after them
+    pub fn player_count(&self) -> u32 {
+        self.players + self.bots
+    }
+
+    pub fn music_gain(&self) -> f32 {
+        self.master_volume * self.music_volume
+    }
+
+    pub fn sfx_gain(&self) -> f32 {
+        self.master_volume * self.sfx_volume
+    }
+
+    fn sanitized(mut self) -> Self {
+        let defaults = Self::default();
+
+        for (value, default, min, max) in [
+            (&mut self.master_volume, defaults.master_volume, 0.0, 1.0),
+            (&mut self.music_volume, defaults.music_volume, 0.0, 1.0),
+            (&mut self.sfx_volume, defaults.sfx_volume, 0.0, 1.0),
+            (
+                &mut self.mouse_sensitivity,
+                defaults.mouse_sensitivity,
+                MIN_MOUSE_SENSITIVITY,
+                MAX_MOUSE_SENSITIVITY,
+            ),
+            (
+                &mut self.camera_smoothing,
+                defaults.camera_smoothing,
+                MIN_CAMERA_SMOOTHING,
+                1.0,
+            ),
+        ] {
+            *value = if value.is_finite() {
+                value.clamp(min, max)
+            } else {
+                default
+            };
+        }
+
+        if self.resolution.0 == 0 || self.resolution.1 == 0 {
+            self.resolution = defaults.resolution;
+        }
+
+        self.players = self.players.clamp(1, MAX_LOCAL_PLAYERS);
+        self.bots = self.bots.min(MAX_BOTS);
+
+        if matches!(self.versus, VersusRules::Timed(0) | VersusRules::FirstTo(0)) {
+            self.versus = defaults.versus;
+        }
+
+        self
+    }
+}
+
+impl Difficulty {
+    // Multiplier for the delay between asteroid waves
+    pub fn spawn_delay_scale(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 1.5,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 0.5,
+        }
+    }
+
+    // Multiplier for the impulse asteroids are spawned with
+    pub fn impulse_scale(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.75,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 1.5,
+        }
+    }
+
+    // Seconds between a bot's decisions
+    pub fn bot_reaction_time(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.6,
+            Difficulty::Normal => 0.35,
+            Difficulty::Hard => 0.15,
+        }
+    }
+
+    // Largest angle in radians a bot's aim is off by
+    pub fn bot_aim_error(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.35,
+            Difficulty::Normal => 0.15,
+            Difficulty::Hard => 0.05,
+        }
+    }
+}
+
+impl VersusRules {
+    pub fn label(&self) -> String {
+        match self {
+            VersusRules::Off => "Off".to_string(),
+            VersusRules::Timed(seconds) => format_duration(*seconds as f32),
+            VersusRules::FirstTo(points) => format!("First to {}", points),
+        }
+    }
+}
+
+impl ParticleQuality {
+    // Maximum number of particles alive at the same time
+    pub fn max_particles(&self) -> usize {
+        match self {
+            ParticleQuality::Off => 0,
+            ParticleQuality::Low => 150,
+            ParticleQuality::Medium => 400,
+            ParticleQuality::High => 1000,
+        }
+    }
+
+    // Multiplier for the number of particles emitted
+    pub fn emission_scale(&self) -> f32 {
+        match self {
+            ParticleQuality::Off => 0.0,
+            ParticleQuality::Low => 0.35,
+            ParticleQuality::Medium => 0.7,
+            ParticleQuality::High => 1.0,
+        }
+    }
+}
+
+// Settings that can be changed from the settings menu
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SettingKind {
+    WindowMode,
+    Resolution,
+    VSync,
+    Shadows,
+    Particles,
+    Minimap,
+    TrajectoryPreview,
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    MouseSensitivity,
+    CameraSmoothing,
+    Difficulty,
+    Level,
+    Players,
+    Versus,
+    Bots,
+    BotDifficulty,
+}
+
+impl SettingKind {
+    pub const ALL: [SettingKind; 18] = [
+        SettingKind::WindowMode,
+        SettingKind::Resolution,
+        SettingKind::VSync,
+        SettingKind::Shadows,
+        SettingKind::Particles,
+        SettingKind::Minimap,
+        SettingKind::TrajectoryPreview,
+        SettingKind::MasterVolume,
+        SettingKind::MusicVolume,
+        SettingKind::SfxVolume,
+        SettingKind::MouseSensitivity,
+        SettingKind::CameraSmoothing,
+        SettingKind::Difficulty,
+        SettingKind::Level,
+        SettingKind::Players,
+        SettingKind::Versus,
+        SettingKind::Bots,
+        SettingKind::BotDifficulty,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SettingKind::WindowMode => "Window Mode",
+            SettingKind::Resolution => "Resolution",
+            SettingKind::VSync => "VSync",
+            SettingKind::Shadows => "Shadows",
+            SettingKind::Particles => "Particles",
+            SettingKind::Minimap => "Minimap",
+            SettingKind::TrajectoryPreview => "Aim Preview",
+            SettingKind::MasterVolume => "Master Volume",
+            SettingKind::MusicVolume => "Music Volume",
+            SettingKind::SfxVolume => "SFX Volume",
+            SettingKind::MouseSensitivity => "Mouse Sensitivity",
+            SettingKind::CameraSmoothing => "Camera Smoothing",
+            SettingKind::Difficulty => "Difficulty",
+            SettingKind::Level => "Level",
+            SettingKind::Players => "Players",
+            SettingKind::Versus => "Versus",
+            SettingKind::Bots => "Bots",
+            SettingKind::BotDifficulty => "Bot Difficulty",
+        }
+    }
+
+    pub fn value_text(&self, settings: &Settings) -> String {
+        let on_off = |value: bool| if value { "On" } else { "Off" }.to_string();
+        let percent = |value: f32| format!("{}%", (value * 100.0).round());
+
+        match self {
+            SettingKind::WindowMode => format!("{:?}", settings.window_mode),
+            SettingKind::Resolution => {
+                format!("{}x{}", settings.resolution.0, settings.resolution.1)
+            }
+            SettingKind::VSync => on_off(settings.vsync),
+            SettingKind::Shadows => on_off(settings.shadows),
+            SettingKind::Particles => format!("{:?}", settings.particle_quality),
+            SettingKind::Minimap => on_off(settings.show_minimap),
+            SettingKind::TrajectoryPreview => on_off(settings.show_trajectory),
+            SettingKind::MasterVolume => percent(settings.master_volume),
+            SettingKind::MusicVolume => percent(settings.music_volume),
+            SettingKind::SfxVolume => percent(settings.sfx_volume),
+            SettingKind::MouseSensitivity => format!("{:.1}", settings.mouse_sensitivity),
+            SettingKind::CameraSmoothing => format!("{:.2}", settings.camera_smoothing),
+            SettingKind::Difficulty => format!("{:?}", settings.difficulty),
+            SettingKind::Level => settings.level.clone(),
+            SettingKind::Players => settings.players.to_string(),
+            SettingKind::Versus => settings.versus.label(),
+            SettingKind::Bots => settings.bots.to_string(),
+            SettingKind::BotDifficulty => format!("{:?}", settings.bot_difficulty),
+        }
+    }
+
+    // Changes the setting by one step in the given direction (-1 or 1)
+    pub fn adjust(&self, settings: &mut Settings, levels: &Levels, direction: i32) {
+        let step = |value: f32, step: f32, min: f32, max: f32| {
+            // Round to the step to avoid accumulating floating point error
+            let value = ((value + step * direction as f32) / step).round() * step;
+            value.clamp(min, max)
+        };
+
+        match self {
+            SettingKind::WindowMode => {
+                settings.window_mode = cycle(
+                    &[
+                        WindowModeSetting::Windowed,
+                        WindowModeSetting::BorderlessFullscreen,
+                        WindowModeSetting::Fullscreen,
+                    ],
+                    settings.window_mode,
+                    direction,
+                )
+            }
+            SettingKind::Resolution => {
+                settings.resolution = cycle(&RESOLUTIONS, settings.resolution, direction)
+            }
+            SettingKind::VSync => settings.vsync = !settings.vsync,
+            SettingKind::Shadows => settings.shadows = !settings.shadows,
+            SettingKind::Particles => {
+                settings.particle_quality = cycle(
+                    &[
+                        ParticleQuality::Off,
+                        ParticleQuality::Low,
+                        ParticleQuality::Medium,
+                        ParticleQuality::High,
+                    ],
+                    settings.particle_quality,
+                    direction,
+                )
+            }
+            SettingKind::Minimap => settings.show_minimap = !settings.show_minimap,
+            SettingKind::TrajectoryPreview => settings.show_trajectory = !settings.show_trajectory,
+            SettingKind::MasterVolume => {
+                settings.master_volume = step(settings.master_volume, VOLUME_STEP, 0.0, 1.0)
+            }
+            SettingKind::MusicVolume => {
+                settings.music_volume = step(settings.music_volume, VOLUME_STEP, 0.0, 1.0)
+            }
+            SettingKind::SfxVolume => {
+                settings.sfx_volume = step(settings.sfx_volume, VOLUME_STEP, 0.0, 1.0)
+            }
+            SettingKind::MouseSensitivity => {
+                settings.mouse_sensitivity = step(
+                    settings.mouse_sensitivity,
+                    MOUSE_SENSITIVITY_STEP,
+                    MIN_MOUSE_SENSITIVITY,
+                    MAX_MOUSE_SENSITIVITY,
+                )
+            }
+            SettingKind::CameraSmoothing => {
+                settings.camera_smoothing = step(
+                    settings.camera_smoothing,
+                    CAMERA_SMOOTHING_STEP,
+                    MIN_CAMERA_SMOOTHING,
+                    1.0,
+                )
+            }
+            SettingKind::Difficulty => {
+                settings.difficulty = cycle(
+                    &[Difficulty::Easy, Difficulty::Normal, Difficulty::Hard],
+                    settings.difficulty,
+                    direction,
+                )
+            }
+            SettingKind::Level => settings.level = levels.cycle(&settings.level, direction),
+            SettingKind::Players => {
+                settings.players = (settings.players as i32 - 1 + direction)
+                    .rem_euclid(MAX_LOCAL_PLAYERS as i32) as u32
+                    + 1
+            }
+            SettingKind::Versus => {
+                settings.versus = cycle(&VERSUS_RULES, settings.versus, direction)
+            }
+            SettingKind::Bots => {
+                settings.bots =
+                    (settings.bots as i32 + direction).rem_euclid(MAX_BOTS as i32 + 1) as u32
+            }
+            SettingKind::BotDifficulty => {
+                settings.bot_difficulty = cycle(
+                    &[Difficulty::Easy, Difficulty::Normal, Difficulty::Hard],
+                    settings.bot_difficulty,
+                    direction,
+                )
+            }
+        }
+    }
+}
+
+// SYSTEMS
+
+// Applies the window settings to the primary window, runs when the settings have changed
+pub fn apply_window_settings(
+    settings: Res<Settings>,
+    mut primary_window_query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    for mut window in primary_window_query.iter_mut() {
+        settings.apply_to_window(&mut window);
+    }
+}
+
+// Toggles the sun's shadows, runs when the settings have changed
+pub fn apply_shadow_settings(
+    settings: Res<Settings>,
+    mut sun_query: Query<&mut DirectionalLight, With<Sun>>,
+) {
+    for mut sun in sun_query.iter_mut() {
+        sun.shadows_enabled = settings.shadows;
+    }
+}
+
+// CLEANUP SYSTEMS
+
+pub fn save_settings(settings: Res<Settings>, settings_path: Res<SettingsPath>) {
+    settings.save(&settings_path.0);
+}
+
+// HELPER FUNCTIONS
+
+// Returns the option that is `direction` steps away from `current`, wrapping around
+fn cycle<T: Copy + PartialEq>(options: &[T], current: T, direction: i32) -> T {
+    let index = options
+        .iter()
+        .position(|option| *option == current)
+        .unwrap_or(0) as i32;
+
+    options[(index + direction).rem_euclid(options.len() as i32) as usize]
+}
@@ -0,0 +1,269 @@
+// Background of the level, either a skybox cubemap assembled from six face images or a procedural starfield.
+// The skybox faces load asynchronously, so the cubemap is assembled once all of them have loaded and falls back to
+// the starfield if any of them fail. Assembled cubemaps are cached so they're only built once.
+
+use bevy::{
+    asset::LoadState,
+    core_pipeline::Skybox,
+    log::warn,
+    prelude::{
+        AssetServer, Assets, Commands, Entity, Handle, Image, Query, Res, ResMut, Resource, Vec3,
+        With,
+    },
+    render::render_resource::{
+        Extent3d, TextureDimension, TextureFormat, TextureViewDescriptor, TextureViewDimension,
+    },
+    utils::HashMap,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    camera::CameraController,
+    level::{Background, LevelConfig, DEFAULT_STARS, DEFAULT_STARS_SEED},
+};
+
+// CONSTANTS
+
+// Order of the cubemap layers: +X, -X, +Y, -Y, +Z, -Z
+pub const SKYBOX_FACE_SUFFIXES: [&str; 6] = ["ft", "bk", "up", "dn", "rt", "lf"];
+const STARFIELD_FACE_SIZE: u32 = 512;
+
+// RESOURCES
+
+// Faces of a skybox that are still loading
+#[derive(Resource)]
+pub struct PendingSkybox {
+    pub faces: String,
+    pub handles: Vec<Handle<Image>>,
+}
+
+// Cubemaps that have already been assembled, by the path of their faces
+#[derive(Resource, Default)]
+pub struct SkyboxCache(pub HashMap<String, Handle<Image>>);
+
+// STARTUP SYSTEMS
+
+// Starts loading the level's skybox, or generates its starfield
+pub fn setup_skybox(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    level: Res<LevelConfig>,
+    mut images: ResMut<Assets<Image>>,
+    mut skybox_cache: ResMut<SkyboxCache>,
+    camera_query: Query<Entity, With<CameraController>>,
+) {
+    match &level.background {
+        Background::Skybox { faces } => match skybox_cache.0.get(faces) {
+            Some(cubemap) => attach_skybox(&mut commands, &camera_query, cubemap.clone()),
+            None => commands.insert_resource(PendingSkybox {
+                faces: faces.clone(),
+                handles: SKYBOX_FACE_SUFFIXES
+                    .iter()
+                    .map(|suffix| asset_server.load(format!("{}_{}.png", faces, suffix)))
+                    .collect(),
+            }),
+        },
+        Background::Starfield { stars, seed } => {
+            let cubemap = cached_starfield(&mut images, &mut skybox_cache, *stars, *seed);
+            attach_skybox(&mut commands, &camera_query, cubemap);
+        }
+    }
+}
+
+// SYSTEMS
+
+// Runs while a skybox is loading, assembles it into a cubemap once all of its faces have loaded
+pub fn assemble_skybox(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    pending_skybox: Res<PendingSkybox>,
+    mut images: ResMut<Assets<Image>>,
+    mut skybox_cache: ResMut<SkyboxCache>,
+    camera_query: Query<Entity, With<CameraController>>,
+) {
+    let failed = pending_skybox
+        .handles
+        .iter()
+        .any(|handle| asset_server.get_load_state(handle) == Some(LoadState::Failed));
+
+    let cubemap = if failed {
+        warn!(
+            "Failed to load the skybox {}, using a starfield instead",
+            pending_skybox.faces
+        );
+        None
+    } else {
+        let faces = pending_skybox
+            .handles
+            .iter()
+            .map(|handle| images.get(handle))
+            .collect::<Option<Vec<_>>>();
+        let Some(faces) = faces else {
+            return;
+        };
+
+        let cubemap = stack_cubemap(&faces);
+        if cubemap.is_none() {
+            warn!(
+                "The faces of the skybox {} don't match, using a starfield instead",
+                pending_skybox.faces
+            );
+        }
+        cubemap
+    };
+
+    match cubemap {
+        Some(cubemap) => {
+            let cubemap = images.add(cubemap);
+            skybox_cache
+                .0
+                .insert(pending_skybox.faces.clone(), cubemap.clone());
+            attach_skybox(&mut commands, &camera_query, cubemap);
+        }
+        // The fallback is cached as a starfield, the skybox's faces are tried again next round
+        None => {
+            let cubemap = cached_starfield(
+                &mut images,
+                &mut skybox_cache,
+                DEFAULT_STARS,
+                DEFAULT_STARS_SEED,
+            );
+            attach_skybox(&mut commands, &camera_query, cubemap);
+        }
+    }
+    commands.remove_resource::<PendingSkybox>();
+}
+
+// HELPER FUNCTIONS
+
+// The skybox is only a background, the scene keeps its ambient and sun lighting since the cubemaps aren't
+// prefiltered for use as environment maps
+fn attach_skybox(
+    commands: &mut Commands,
+    camera_query: &Query<Entity, With<CameraController>>,
+    cubemap: Handle<Image>,
+) {
+    for camera in camera_query.iter() {
+        commands.entity(camera).insert(Skybox(cubemap.clone()));
+    }
+}
+
+// Generates a starfield the first time it's used
+fn cached_starfield(
+    images: &mut Assets<Image>,
+    skybox_cache: &mut SkyboxCache,
+    stars: usize,
+    seed: u64,
+) -> Handle<Image> {
+    let key = format!("starfield_{}_{}", stars, seed);
+    skybox_cache
+        .0
+        .entry(key)
+        .or_insert_with(|| images.add(generate_starfield(stars, seed)))
+        .clone()
+}
+
+// Stacks six square faces of the same size and format into a cubemap
+fn stack_cubemap(faces: &[&Image]) -> Option<Image> {
+    let first = faces.first()?;
+    let size = first.texture_descriptor.size;
+    let format = first.texture_descriptor.format;
+
+    if size.width != size.height
+        || faces.iter().any(|face| {
+            face.texture_descriptor.size != size || face.texture_descriptor.format != format
+        })
+    {
+        return None;
+    }
+
+    let data = faces
+        .iter()
+        .flat_map(|face| face.data.iter().copied())
+        .collect();
+    Some(cubemap_image(size.width, data, format))
+}
+
+// Scatters stars of random brightness and colour over the faces of a cubemap
+fn generate_starfield(stars: usize, seed: u64) -> Image {
+    let size = STARFIELD_FACE_SIZE as usize;
+    let mut data = vec![0; size * size * 4 * 6];
+    for pixel in data.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..stars {
+        let direction = Vec3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        )
+        .normalize_or_zero();
+        if direction == Vec3::ZERO {
+            continue;
+        }
+
+        // Most stars are faint, a few are bright
+        let brightness = rng.gen_range(0.1_f32..1.0).powi(3);
+        let tint = rng.gen_range(0.8..1.0);
+        let color = [brightness, brightness * tint, brightness * tint * tint];
+
+        let (face, u, v) = cubemap_face_uv(direction);
+        let x = ((u * size as f32) as usize).min(size - 1);
+        let y = ((v * size as f32) as usize).min(size - 1);
+        let index = ((face * size + y) * size + x) * 4;
+
+        for (channel, value) in color.iter().enumerate() {
+            let value = (value * 255.0) as u8;
+            data[index + channel] = data[index + channel].max(value);
+        }
+    }
+
+    cubemap_image(STARFIELD_FACE_SIZE, data, TextureFormat::Rgba8UnormSrgb)
+}
+
+// Face index and texture coordinates of a direction on a cubemap, following the wgpu cubemap conventions
+fn cubemap_face_uv(direction: Vec3) -> (usize, f32, f32) {
+    let abs = direction.abs();
+    let (face, major, u, v) = if abs.x >= abs.y && abs.x >= abs.z {
+        if direction.x > 0.0 {
+            (0, abs.x, -direction.z, -direction.y)
+        } else {
+            (1, abs.x, direction.z, -direction.y)
+        }
+    } else if abs.y >= abs.z {
+        if direction.y > 0.0 {
+            (2, abs.y, direction.x, direction.z)
+        } else {
+            (3, abs.y, direction.x, -direction.z)
+        }
+    } else if direction.z > 0.0 {
+        (4, abs.z, direction.x, -direction.y)
+    } else {
+        (5, abs.z, -direction.x, -direction.y)
+    };
+
+    (face, (u / major + 1.0) / 2.0, (v / major + 1.0) / 2.0)
+}
+
+fn cubemap_image(face_size: u32, data: Vec<u8>, format: TextureFormat) -> Image {
+    let mut image = Image::new(
+        Extent3d {
+            width: face_size,
+            height: face_size * 6,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        format,
+    );
+
+    image.reinterpret_stacked_2d_as_array(6);
+    image.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::Cube),
+        ..Default::default()
+    });
+
+    image
+}
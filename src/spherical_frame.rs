@@ -0,0 +1,214 @@
+// Orientation frame for things moving over the planet's surface, like the camera and the player.
+// Instead of rebuilding the orientation from a fixed or previous up vector, which rolls and can flip when
+// passing over the poles, the frame's forward direction is parallel transported as its normal moves.
+
+use bevy::prelude::{Mat3, Quat, Vec3};
+
+// CONSTANTS
+
+const MIN_PROJECTED_LENGTH: f32 = 1e-4;
+
+// A right handed frame on the sphere, the normal points away from the centre of the planet
+// and the forward direction is tangent to the sphere
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SphericalFrame {
+    pub normal: Vec3,
+    pub forward: Vec3,
+}
+
+impl Default for SphericalFrame {
+    fn default() -> Self {
+        Self {
+            normal: Vec3::Z,
+            forward: Vec3::Y,
+        }
+    }
+}
+
+impl SphericalFrame {
+    // Creates a frame at the normal with the forward direction as close to the hint as possible
+    pub fn new(normal: Vec3, forward_hint: Vec3) -> Self {
+        let normal = normal.normalize();
+        let forward = project_on_tangent_plane(forward_hint, normal)
+            .unwrap_or_else(|| normal.any_orthonormal_vector());
+
+        Self { normal, forward }
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.forward.cross(self.normal)
+    }
+
+    // Moves the frame to a new normal by rotating it along the shortest arc, which doesn't introduce any roll
+    pub fn transported(&self, normal: Vec3) -> Self {
+        let normal = normal.normalize_or_zero();
+        if normal == Vec3::ZERO {
+            return *self;
+        }
+
+        let forward = Quat::from_rotation_arc(self.normal, normal) * self.forward;
+
+        // Remove the error accumulated over many small transports
+        Self::new(
+            normal,
+            project_on_tangent_plane(forward, normal).unwrap_or(forward),
+        )
+    }
+
+    // Rotates the whole frame, used to turn it around its normal or to move it in a given direction
+    pub fn rotated(&self, rotation: Quat) -> Self {
+        Self::new(rotation * self.normal, rotation * self.forward)
+    }
+
+    // Rotation of a camera at the frame looking at the centre of the planet with the forward direction as its up
+    pub fn camera_rotation(&self) -> Quat {
+        Quat::from_mat3(&Mat3::from_cols(self.right(), self.forward, self.normal))
+    }
+
+    // Rotation of an object standing on the surface at the frame facing the forward direction
+    pub fn surface_rotation(&self) -> Quat {
+        Quat::from_mat3(&Mat3::from_cols(self.right(), self.normal, -self.forward))
+    }
+}
+
+// HELPER FUNCTIONS
+
+// Projects the vector on the plane perpendicular to the normal, None if it's (almost) parallel to the normal
+pub fn project_on_tangent_plane(vector: Vec3, normal: Vec3) -> Option<Vec3> {
+    let projected = vector - normal * vector.dot(normal);
+    if projected.length() < MIN_PROJECTED_LENGTH {
+        None
+    } else {
+        Some(projected.normalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::TransformExt;
+    use bevy::prelude::Transform;
+    use std::f32::consts::PI;
+
+    const STEPS: usize = 720;
+
+    // Great circles through the poles, along the equator and at an angle, starting at different points
+    fn great_circles() -> Vec<(Vec3, Vec3)> {
+        vec![
+            (Vec3::Z, Vec3::Y),
+            (Vec3::Z, Vec3::X),
+            (Vec3::Y, Vec3::Z),
+            (Vec3::new(0.0, 1.0, 1.0).normalize(), Vec3::X),
+            (
+                Vec3::new(1.0, 2.0, 3.0).normalize(),
+                Vec3::new(1.0, 2.0, 3.0)
+                    .normalize()
+                    .any_orthonormal_vector(),
+            ),
+        ]
+    }
+
+    // Normals along the great circle starting at the start point and heading in the direction
+    fn great_circle_path(start: Vec3, direction: Vec3) -> impl Iterator<Item = Vec3> {
+        let axis = start.cross(direction).normalize();
+        (0..=STEPS).map(move |step| {
+            Quat::from_axis_angle(axis, step as f32 / STEPS as f32 * 2.0 * PI) * start
+        })
+    }
+
+    fn assert_orthonormal(frame: &SphericalFrame) {
+        assert!((frame.normal.length() - 1.0).abs() < 1e-4);
+        assert!((frame.forward.length() - 1.0).abs() < 1e-4);
+        assert!(frame.normal.dot(frame.forward).abs() < 1e-4);
+    }
+
+    #[test]
+    fn transport_has_no_orientation_discontinuity() {
+        let step_angle = 2.0 * PI / STEPS as f32;
+
+        for (start, direction) in great_circles() {
+            // Start with the forward direction both along and across the path
+            for forward_hint in [direction, start.cross(direction)] {
+                let mut frame = SphericalFrame::new(start, forward_hint);
+                let mut previous_rotation = frame.camera_rotation();
+
+                for normal in great_circle_path(start, direction).skip(1) {
+                    frame = frame.transported(normal);
+                    assert_orthonormal(&frame);
+
+                    // The camera only ever turns by as much as it moved around the planet
+                    let rotation = frame.camera_rotation();
+                    let turned = rotation.angle_between(previous_rotation);
+                    assert!(
+                        turned < step_angle * 1.01 + 1e-4,
+                        "camera turned by {} over a step of {} at {:?}",
+                        turned,
+                        step_angle,
+                        normal
+                    );
+                    previous_rotation = rotation;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transport_around_great_circle_has_no_roll() {
+        for (start, direction) in great_circles() {
+            let initial = SphericalFrame::new(start, direction.cross(start));
+            let mut frame = initial;
+            for normal in great_circle_path(start, direction).skip(1) {
+                frame = frame.transported(normal);
+            }
+
+            // Great circles are geodesics, so going all the way around brings back the same frame
+            assert!(frame.normal.distance(initial.normal) < 1e-3);
+            assert!(
+                frame.forward.distance(initial.forward) < 1e-3,
+                "forward {:?} came back as {:?}",
+                initial.forward,
+                frame.forward
+            );
+        }
+    }
+
+    #[test]
+    fn camera_rotation_looks_at_centre() {
+        let frame = SphericalFrame::new(Vec3::new(1.0, -2.0, 0.5), Vec3::Y);
+        let transform = Transform::from_rotation(frame.camera_rotation());
+
+        assert!(transform.back().distance(frame.normal) < 1e-5);
+        assert!(transform.up().distance(frame.forward) < 1e-5);
+    }
+
+    #[test]
+    fn set_down_is_continuous_over_great_circles() {
+        let step_angle = 2.0 * PI / STEPS as f32;
+
+        for (start, direction) in great_circles() {
+            let mut transform = Transform::from_translation(start);
+            transform.set_down(Vec3::ZERO, direction);
+            let mut previous_rotation = transform.rotation;
+
+            // Heading along the path and then with a heading parallel to the normal, which can't be used
+            let axis = start.cross(direction).normalize();
+            for heading_along_path in [true, false] {
+                for normal in great_circle_path(start, direction).skip(1) {
+                    let heading = if heading_along_path {
+                        axis.cross(normal)
+                    } else {
+                        normal
+                    };
+                    transform.translation = normal;
+                    transform.set_down(Vec3::ZERO, heading);
+
+                    assert!(transform.up().distance(normal) < 1e-4);
+
+                    let turned = transform.rotation.angle_between(previous_rotation);
+                    assert!(turned < step_angle * 1.01 + 1e-4);
+                    previous_rotation = transform.rotation;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+// Sounds synthesized at startup instead of loaded from files.
+// SynthSound is a mono sample buffer that can be played with AudioSourceBundle like any other audio source.
+
+use bevy::{
+    audio::{Decodable, Source},
+    prelude::Asset,
+    reflect::TypePath,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{f32::consts::TAU, sync::Arc, time::Duration};
+
+// CONSTANTS
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+// ASSETS
+
+#[derive(Asset, TypePath, Clone)]
+pub struct SynthSound {
+    pub samples: Arc<[f32]>,
+}
+
+impl SynthSound {
+    pub fn new(samples: Vec<f32>) -> Self {
+        Self {
+            samples: samples.into(),
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f32(self.samples.len() as f32 / SAMPLE_RATE as f32)
+    }
+
+    // Scales the samples so the loudest one has the given amplitude
+    pub fn normalized(samples: Vec<f32>, peak: f32) -> Self {
+        let max = samples
+            .iter()
+            .fold(0.0_f32, |max, sample| max.max(sample.abs()));
+        let scale = if max > 0.0 { peak / max } else { 1.0 };
+
+        Self::new(samples.into_iter().map(|sample| sample * scale).collect())
+    }
+}
+
+// Iterates over the samples of a SynthSound
+pub struct SynthDecoder {
+    samples: Arc<[f32]>,
+    index: usize,
+}
+
+impl Iterator for SynthDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.samples.get(self.index).copied();
+        self.index += 1;
+        sample
+    }
+}
+
+impl Source for SynthDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.samples.len().saturating_sub(self.index))
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(
+            self.samples.len() as f32 / SAMPLE_RATE as f32,
+        ))
+    }
+}
+
+impl Decodable for SynthSound {
+    type DecoderItem = f32;
+    type Decoder = SynthDecoder;
+
+    fn decoder(&self) -> SynthDecoder {
+        SynthDecoder {
+            samples: self.samples.clone(),
+            index: 0,
+        }
+    }
+}
+
+// HELPER FUNCTIONS
+
+// Fills a buffer of the given length by calling the generator with the time of each sample
+pub fn render(duration: f32, mut generator: impl FnMut(f32) -> f32) -> Vec<f32> {
+    let sample_count = (duration * SAMPLE_RATE as f32) as usize;
+    (0..sample_count)
+        .map(|index| generator(index as f32 / SAMPLE_RATE as f32).clamp(-1.0, 1.0))
+        .collect()
+}
+
+// Low pass filtered white noise, a smaller cutoff gives a deeper rumble
+pub fn filtered_noise(seed: u64, cutoff: f32) -> impl FnMut() -> f32 {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let alpha = (TAU * cutoff / SAMPLE_RATE as f32).min(1.0);
+    let mut value = 0.0;
+
+    move || {
+        value += (rng.gen_range(-1.0..1.0) - value) * alpha;
+        value
+    }
+}
+
+// Sine oscillator whose frequency can change every sample without clicks
+pub fn oscillator() -> impl FnMut(f32) -> f32 {
+    let mut phase = 0.0;
+
+    move |frequency| {
+        phase = (phase + frequency / SAMPLE_RATE as f32).fract();
+        (phase * TAU).sin()
+    }
+}
+
+// Fast attack followed by an exponential decay
+pub fn envelope(t: f32, attack: f32, decay: f32) -> f32 {
+    if t < attack {
+        t / attack
+    } else {
+        (-(t - attack) / decay).exp()
+    }
+}
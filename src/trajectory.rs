@@ -0,0 +1,278 @@
+// Aim preview showing where the next cannon ball will go and where the recoil will push the player.
+// Both are predicted by stepping the same gravity model the physics uses, with a simple approximation of
+// landing on and rolling along the planet's surface. Bots aim with the same predictions.
+
+use bevy::{
+    gizmos::gizmos::Gizmos,
+    prelude::{Color, Query, Res, Transform, Vec3, With},
+};
+use bevy_rapier3d::prelude::Velocity;
+use std::f32::consts::PI;
+
+use crate::{
+    asteroids::{ASTEROID_DENSITY, ASTEROID_SIZE},
+    cannon_ball::{
+        CANNON_BALL_ANGULAR_DAMPING, CANNON_BALL_DENSITY, CANNON_BALL_INITIAL_OFFSET,
+        CANNON_BALL_LINEAR_DAMPING, CANNON_BALL_RADIUS,
+    },
+    common::gravity_force,
+    input::ShootTimer,
+    planet::PlanetShape,
+    player::{
+        PlayerCollider, PlayerMeshDesiredTransform, PLAYER_ANGULAR_DAMPING, PLAYER_DENSITY,
+        PLAYER_IMPULSE_MAGNITUDE, PLAYER_LINEAR_DAMPING, PLAYER_SIZE,
+    },
+    settings::Settings,
+    spherical_frame::project_on_tangent_plane,
+};
+
+// CONSTANTS
+
+pub const TRAJECTORY_STEPS: usize = 180;
+pub const TRAJECTORY_TIMESTEP: f32 = 1.0 / 60.0;
+const TRAJECTORY_DOT_SPACING: usize = 3; // Number of steps per dot of the dotted arc
+const ROLLING_STOP_SPEED: f32 = 0.5;
+const INTERCEPT_ITERATIONS: usize = 4; // Times the aim is corrected by how far the predicted shot misses
+const TRAJECTORY_COLOR: Color = Color::rgba(1.0, 0.9, 0.3, 0.9);
+const RELOADING_TRAJECTORY_COLOR: Color = Color::rgba(1.0, 0.9, 0.3, 0.3);
+const RECOIL_COLOR: Color = Color::rgba(0.3, 1.0, 0.4, 0.9);
+
+// A ball shaped body whose motion can be predicted
+pub struct PredictedBody {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub radius: f32,
+    pub density: f32,
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+}
+
+impl PredictedBody {
+    pub fn mass(&self) -> f32 {
+        self.density * 4.0 / 3.0 * PI * self.radius.powi(3)
+    }
+}
+
+// SYSTEMS
+
+// Draws the predicted path of each player's next shot as a dotted arc and marks where the recoil will move them
+pub fn draw_trajectory_preview(
+    mut gizmos: Gizmos,
+    settings: Res<Settings>,
+    planet_shape: Res<PlanetShape>,
+    player_collider_query: Query<
+        (
+            &Transform,
+            &Velocity,
+            &PlayerMeshDesiredTransform,
+            &ShootTimer,
+        ),
+        With<PlayerCollider>,
+    >,
+) {
+    if !settings.show_trajectory {
+        return;
+    }
+
+    for (
+        player_collider_transform,
+        player_collider_velocity,
+        player_mesh_desired_transform,
+        shoot_timer,
+    ) in player_collider_query.iter()
+    {
+        draw_player_trajectory(
+            &mut gizmos,
+            &planet_shape,
+            player_collider_transform,
+            player_collider_velocity,
+            player_mesh_desired_transform,
+            shoot_timer,
+        );
+    }
+}
+
+// HELPER FUNCTIONS
+
+fn draw_player_trajectory(
+    gizmos: &mut Gizmos,
+    planet_shape: &PlanetShape,
+    player_collider_transform: &Transform,
+    player_collider_velocity: &Velocity,
+    player_mesh_desired_transform: &PlayerMeshDesiredTransform,
+    shoot_timer: &ShootTimer,
+) {
+    let direction = -player_mesh_desired_transform.tangent;
+    let cannon_ball = predicted_cannon_ball(player_collider_transform.translation, direction);
+
+    let color = if shoot_timer.0.finished() {
+        TRAJECTORY_COLOR
+    } else {
+        RELOADING_TRAJECTORY_COLOR
+    };
+
+    let path = simulate_trajectory(
+        planet_shape,
+        cannon_ball,
+        TRAJECTORY_STEPS,
+        TRAJECTORY_TIMESTEP,
+    );
+    for (index, segment) in path.windows(2).enumerate() {
+        if (index / TRAJECTORY_DOT_SPACING).is_multiple_of(2) {
+            gizmos.line(segment[0], segment[1], color);
+        }
+    }
+
+    // Same impulse as apply_player_collider_impulse
+    let player = predicted_player(
+        player_collider_transform.translation,
+        player_collider_velocity.linvel,
+    );
+    let impulse = -direction * PLAYER_IMPULSE_MAGNITUDE - player.velocity;
+    let player = PredictedBody {
+        velocity: player.velocity + impulse / player.mass(),
+        ..player
+    };
+
+    let recoil_path =
+        simulate_trajectory(planet_shape, player, TRAJECTORY_STEPS, TRAJECTORY_TIMESTEP);
+    if let Some(destination) = recoil_path.last() {
+        gizmos.linestrip(recoil_path.iter().copied(), RECOIL_COLOR.with_a(0.3));
+        gizmos.circle(
+            *destination,
+            planet_shape.surface_normal(*destination),
+            PLAYER_SIZE,
+            RECOIL_COLOR,
+        );
+    }
+}
+
+// Cannon ball a player at the position shoots in the direction, at the same position and speed as shoot_cannon_ball
+pub fn predicted_cannon_ball(position: Vec3, direction: Vec3) -> PredictedBody {
+    let cannon_ball = PredictedBody {
+        position: position + direction * CANNON_BALL_INITIAL_OFFSET,
+        velocity: Vec3::ZERO,
+        radius: CANNON_BALL_RADIUS,
+        density: CANNON_BALL_DENSITY,
+        linear_damping: CANNON_BALL_LINEAR_DAMPING,
+        angular_damping: CANNON_BALL_ANGULAR_DAMPING,
+    };
+    PredictedBody {
+        velocity: direction * PLAYER_IMPULSE_MAGNITUDE / cannon_ball.mass(),
+        ..cannon_ball
+    }
+}
+
+// Player collider with the damping it's spawned with
+pub fn predicted_player(position: Vec3, velocity: Vec3) -> PredictedBody {
+    PredictedBody {
+        position,
+        velocity,
+        radius: PLAYER_SIZE,
+        density: PLAYER_DENSITY,
+        linear_damping: PLAYER_LINEAR_DAMPING,
+        angular_damping: PLAYER_ANGULAR_DAMPING,
+    }
+}
+
+// Asteroid as the ball its lumpy rock is shaped around, asteroids aren't damped
+pub fn predicted_asteroid(position: Vec3, velocity: Vec3) -> PredictedBody {
+    PredictedBody {
+        position,
+        velocity,
+        radius: ASTEROID_SIZE / 2.0,
+        density: ASTEROID_DENSITY,
+        linear_damping: 0.0,
+        angular_damping: 0.0,
+    }
+}
+
+// Direction along the surface to shoot in from the position so the cannon ball meets the target, both following their
+// predicted paths, and how far apart they'll be when they come closest. The first shot is aimed at where the target is,
+// and each following one is aimed further by how far the previous one missed. None if the target is straight above
+// or below
+pub fn intercept_direction(
+    planet_shape: &PlanetShape,
+    position: Vec3,
+    surface_normal: Vec3,
+    target: PredictedBody,
+) -> Option<(Vec3, f32)> {
+    let target_path =
+        simulate_trajectory(planet_shape, target, TRAJECTORY_STEPS, TRAJECTORY_TIMESTEP);
+    let mut aim_point = target_path[0];
+    let mut best: Option<(Vec3, f32)> = None;
+
+    for _ in 0..INTERCEPT_ITERATIONS {
+        let direction = project_on_tangent_plane(aim_point - position, surface_normal)?;
+        let path = simulate_trajectory(
+            planet_shape,
+            predicted_cannon_ball(position, direction),
+            TRAJECTORY_STEPS,
+            TRAJECTORY_TIMESTEP,
+        );
+
+        let miss = (0..=TRAJECTORY_STEPS)
+            .map(|step| path_position(&target_path, step) - path_position(&path, step))
+            .min_by(|a, b| a.length_squared().total_cmp(&b.length_squared()))
+            .unwrap_or(Vec3::ZERO);
+
+        if best.is_none_or(|(_, best_miss)| miss.length() < best_miss) {
+            best = Some((direction, miss.length()));
+        }
+        aim_point += miss;
+    }
+
+    best
+}
+
+// Position on a path after the given number of steps. Paths stop early once their body comes to rest, where it stays
+pub fn path_position(path: &[Vec3], step: usize) -> Vec3 {
+    path[step.min(path.len() - 1)]
+}
+
+// Steps a body under the planet's gravity and returns its positions, stopping early if it comes to rest.
+// Integration matches Rapier's (semi-implicit Euler with damping applied to the velocity).
+// Contact with the planet's terrain is approximated: the body loses its velocity into the surface on landing, a solid
+// ball loses 2/7 of its remaining velocity when it starts rolling, and afterwards the angular damping slows it down.
+pub fn simulate_trajectory(
+    planet_shape: &PlanetShape,
+    mut body: PredictedBody,
+    steps: usize,
+    timestep: f32,
+) -> Vec<Vec3> {
+    let mass = body.mass();
+    let mut rolling = false;
+    let mut path = Vec::with_capacity(steps + 1);
+    path.push(body.position);
+
+    for _ in 0..steps {
+        body.velocity += gravity_force(body.position) / mass * timestep;
+        body.velocity *= 1.0 / (1.0 + timestep * body.linear_damping);
+        if rolling {
+            body.velocity *= 1.0 / (1.0 + timestep * body.angular_damping);
+        }
+
+        body.position += body.velocity * timestep;
+
+        // Keep the body on the surface and remove the velocity into the planet
+        let surface_distance = planet_shape.surface_point(body.position).length() + body.radius;
+        if body.position.length() < surface_distance {
+            let normal = planet_shape.surface_normal(body.position);
+            body.position = body.position.normalize() * surface_distance;
+            body.velocity -= normal * body.velocity.dot(normal).min(0.0);
+
+            if !rolling {
+                body.velocity *= 5.0 / 7.0;
+                rolling = true;
+            }
+        }
+
+        path.push(body.position);
+
+        if rolling && body.velocity.length() < ROLLING_STOP_SPEED {
+            break;
+        }
+    }
+
+    path
+}
@@ -1,113 +1,432 @@
 use bevy::{
+    app::AppExit,
+    hierarchy::ChildBuilder,
     prelude::{
-        AssetServer, BuildChildren, ButtonBundle, Camera, Camera3dBundle, Changed, Color, Commands,
-        Component, Name, NextState, NodeBundle, Query, Res, ResMut, TextBundle, Transform,
-        Visibility, With,
+        BuildChildren, ButtonBundle, Camera, Camera3dBundle, Changed, Color, Commands, Component,
+        DespawnRecursiveExt, Entity, EventWriter, Font, Handle, Name, NextState, NodeBundle, Query,
+        Res, ResMut, TextBundle, Transform, With,
     },
     text::{Text, TextStyle},
     ui::{
-        AlignItems, BackgroundColor, FlexDirection, Interaction, JustifyContent, Style, UiRect, Val,
+        AlignItems, BackgroundColor, FlexDirection, FlexWrap, FocusPolicy, Interaction,
+        JustifyContent, PositionType, Style, UiRect, Val, ZIndex,
     },
     utils::default,
 };
 
-use crate::common::{GameState, PrimaryCamera, Score};
+use crate::{
+    common::{GameState, PauseState, PrimaryCamera, Score},
+    game_assets::{GameAssets, LoadingProgress},
+    high_scores::{HighScores, PendingHighScore},
+    level::Levels,
+    settings::{SettingKind, Settings, SettingsMenuState},
+    versus::VersusScoreboard,
+};
 
 // CONSTANTS
 
 const NORMAL_BUTTON: Color = Color::rgb(1.0, 1.0, 1.0);
 const HOVERED_BUTTON: Color = Color::rgb(0.9, 0.9, 0.9);
 const PRESSED_BUTTON: Color = Color::rgb(0.8, 0.8, 0.8);
+const LOADING_BAR_WIDTH: f32 = 400.0;
+const LOADING_BAR_HEIGHT: f32 = 20.0;
 
 // COMPONENTS
 
 #[derive(Component)]
-pub struct ScoreUI {}
+pub struct LoadingBarUI {}
+
+#[derive(Component)]
+pub struct NameInputUI {}
+
+#[derive(Component)]
+pub struct PauseUI {}
+
+#[derive(Component)]
+pub struct SettingsUI {}
+
+// Buttons used to navigate between menus
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MenuButton {
+    Play,
+    HighScores,
+    Settings,
+    CloseSettings,
+    Resume,
+    MainMenu,
+    Quit,
+}
 
+// Buttons that change a setting by one step in the given direction
 #[derive(Component)]
-pub struct ReloadUI {}
+pub struct SettingsButton {
+    pub kind: SettingKind,
+    pub direction: i32,
+}
 
 #[derive(Component)]
-pub struct RestartButton {}
+pub struct SettingValueUI {
+    pub kind: SettingKind,
+}
 
 // STARTUP SYSTEMS
 
-pub fn setup_game_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
-    // In Game UI
+// Title and a bar filling up as the game's assets load
+pub fn setup_loading_ui(mut commands: Commands) {
+    spawn_ui_camera(&mut commands);
+
     commands
         .spawn(NodeBundle {
             style: Style {
-                flex_direction: FlexDirection::Row,
-                justify_content: JustifyContent::SpaceBetween,
-                align_items: AlignItems::FlexStart,
-                padding: UiRect::all(Val::Px(15.0)),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
                 ..default()
             },
+            background_color: Color::rgb(0.25, 0.25, 0.25).into(),
             ..default()
         })
-        .insert(Name::new("In_Game_UI"))
+        .insert(Name::new("Loading_UI"))
         .with_children(|parent| {
-            // Score
+            // The game's font is still loading, so the text uses the default font
             parent
                 .spawn(
                     TextBundle::from_section(
-                        "Score: 0",
+                        "Loading",
                         TextStyle {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                            font_size: 30.0,
+                            font_size: 50.0,
                             color: Color::WHITE,
+                            ..default()
                         },
                     )
                     .with_style(Style {
-                        margin: UiRect::all(Val::Px(5.0)),
-                        flex_shrink: 1.0,
+                        margin: UiRect::all(Val::Px(20.0)),
                         ..default()
                     }),
                 )
-                .insert(Name::new("Score_Indicator"))
-                .insert(ScoreUI {});
+                .insert(Name::new("Loading_Text"));
 
-            // Reload indicator
+            // Loading bar background
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(LOADING_BAR_WIDTH),
+                        height: Val::Px(LOADING_BAR_HEIGHT),
+                        ..default()
+                    },
+                    background_color: Color::rgba(0.0, 0.0, 0.0, 0.5).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                width: Val::Percent(0.0),
+                                height: Val::Percent(100.0),
+                                ..default()
+                            },
+                            background_color: Color::WHITE.into(),
+                            ..default()
+                        })
+                        .insert(Name::new("Loading_Bar"))
+                        .insert(LoadingBarUI {});
+                });
+        });
+}
+
+pub fn setup_game_over_ui(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    score: Res<Score>,
+    pending_high_score: Option<Res<PendingHighScore>>,
+    versus_scoreboard: Option<Res<VersusScoreboard>>,
+) {
+    spawn_ui_camera(&mut commands);
+
+    // A versus round announces its winner instead of the game being over
+    let title = match versus_scoreboard.as_deref() {
+        Some(scoreboard) => match scoreboard.winner() {
+            Some(winner) => format!("{} Wins!", winner.label()),
+            None => "Draw".to_string(),
+        },
+        None => "Game Over".to_string(),
+    };
+
+    // Game over text and restart button - Game Over UI
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            background_color: Color::rgb(0.25, 0.25, 0.25).into(),
+            ..default()
+        })
+        .insert(Name::new("Game_Over_UI"))
+        .with_children(|parent| {
+            // Game over text
             parent
                 .spawn(
                     TextBundle::from_section(
-                        "Reloading...",
+                        title,
                         TextStyle {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                            font_size: 30.0,
+                            font: game_assets.font.clone(),
+                            font_size: 50.0,
                             color: Color::WHITE,
                         },
                     )
                     .with_style(Style {
-                        margin: UiRect::all(Val::Px(5.0)),
-                        flex_shrink: 1.0,
+                        margin: UiRect::all(Val::Px(20.0)),
                         ..default()
                     }),
                 )
-                .insert(Name::new("Reload_Indicator"))
-                .insert(ReloadUI {})
-                .insert(Visibility::Hidden);
+                .insert(Name::new("Game_Over_Text"));
+
+            // Per-player results of a versus round, or the final score
+            if let Some(scoreboard) = versus_scoreboard.as_deref() {
+                // Each column is a fixed width so the rows line up
+                let columns = [
+                    ("#", 50.0),
+                    ("Player", 120.0),
+                    ("Points", 100.0),
+                    ("Knocks", 100.0),
+                    ("Hits Taken", 150.0),
+                ];
+
+                let mut rows = vec![(columns.map(|(header, _)| header.to_string()), Color::YELLOW)];
+                for (index, standing) in scoreboard.ranking().into_iter().enumerate() {
+                    rows.push((
+                        [
+                            (index + 1).to_string(),
+                            standing.player.label(),
+                            standing.points.to_string(),
+                            standing.knocks.to_string(),
+                            standing.asteroid_hits.to_string(),
+                        ],
+                        Color::WHITE,
+                    ));
+                }
+
+                for (cells, color) in rows {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Row,
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .insert(Name::new("Results_Row"))
+                        .with_children(|parent| {
+                            for (cell, (_, width)) in cells.into_iter().zip(columns) {
+                                parent.spawn(
+                                    TextBundle::from_section(
+                                        cell,
+                                        TextStyle {
+                                            font: game_assets.font.clone(),
+                                            font_size: 30.0,
+                                            color,
+                                        },
+                                    )
+                                    .with_style(Style {
+                                        width: Val::Px(width),
+                                        margin: UiRect::all(Val::Px(4.0)),
+                                        ..default()
+                                    }),
+                                );
+                            }
+                        });
+                }
+            } else {
+                parent
+                    .spawn(
+                        TextBundle::from_section(
+                            format!("Final Score: {}", score.0),
+                            TextStyle {
+                                font: game_assets.font.clone(),
+                                font_size: 50.0,
+                                color: Color::WHITE,
+                            },
+                        )
+                        .with_style(Style {
+                            margin: UiRect::all(Val::Px(20.0)),
+                            ..default()
+                        }),
+                    )
+                    .insert(Name::new("Game_Over_Text"));
+            }
+
+            // Name entry for a new high score
+            if pending_high_score.is_some() {
+                parent
+                    .spawn(
+                        TextBundle::from_section(
+                            "New High Score! Type your name and press Enter",
+                            TextStyle {
+                                font: game_assets.font.clone(),
+                                font_size: 30.0,
+                                color: Color::WHITE,
+                            },
+                        )
+                        .with_style(Style {
+                            margin: UiRect::all(Val::Px(10.0)),
+                            ..default()
+                        }),
+                    )
+                    .insert(Name::new("New_High_Score_Text"));
+
+                parent
+                    .spawn(
+                        TextBundle::from_section(
+                            "_",
+                            TextStyle {
+                                font: game_assets.font.clone(),
+                                font_size: 40.0,
+                                color: Color::YELLOW,
+                            },
+                        )
+                        .with_style(Style {
+                            margin: UiRect::all(Val::Px(10.0)),
+                            ..default()
+                        }),
+                    )
+                    .insert(Name::new("Name_Input"))
+                    .insert(NameInputUI {});
+            }
+
+            let font = game_assets.font.clone();
+            spawn_menu_button(parent, font.clone(), "Restart", MenuButton::Play);
+            spawn_menu_button(parent, font.clone(), "High Scores", MenuButton::HighScores);
+            spawn_menu_button(parent, font, "Main Menu", MenuButton::MainMenu);
         });
 }
 
-pub fn setup_game_over_ui(
+pub fn setup_high_scores_ui(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    score: Res<Score>,
+    game_assets: Res<GameAssets>,
+    high_scores: Res<HighScores>,
 ) {
-    // Camera for UI
+    spawn_ui_camera(&mut commands);
+
+    // High score table and play button - High Scores UI
     commands
-        .spawn(Camera3dBundle {
-            camera: Camera {
-                order: 5,
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
                 ..default()
             },
-            transform: Transform::default(),
+            background_color: Color::rgb(0.25, 0.25, 0.25).into(),
             ..default()
         })
-        .insert(PrimaryCamera {});
+        .insert(Name::new("High_Scores_UI"))
+        .with_children(|parent| {
+            // Title
+            parent
+                .spawn(
+                    TextBundle::from_section(
+                        "High Scores",
+                        TextStyle {
+                            font: game_assets.font.clone(),
+                            font_size: 50.0,
+                            color: Color::WHITE,
+                        },
+                    )
+                    .with_style(Style {
+                        margin: UiRect::all(Val::Px(20.0)),
+                        ..default()
+                    }),
+                )
+                .insert(Name::new("High_Scores_Title"));
 
-    // Game over text and restart button - Game Over UI
+            // Table rows, each column is a fixed width so they line up
+            let columns = [
+                ("#", 50.0),
+                ("Name", 220.0),
+                ("Score", 100.0),
+                ("Wave", 80.0),
+                ("Time", 80.0),
+                ("Date", 150.0),
+                ("Seed", 220.0),
+            ];
+
+            let mut rows = vec![(columns.map(|(header, _)| header.to_string()), Color::YELLOW)];
+            for (index, entry) in high_scores.entries.iter().enumerate() {
+                rows.push((
+                    [
+                        (index + 1).to_string(),
+                        entry.name.clone(),
+                        entry.score.to_string(),
+                        entry.wave.to_string(),
+                        entry.formatted_duration(),
+                        entry.formatted_date(),
+                        entry.seed.to_string(),
+                    ],
+                    Color::WHITE,
+                ));
+            }
+
+            for (cells, color) in rows {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .insert(Name::new("High_Scores_Row"))
+                    .with_children(|parent| {
+                        for (cell, (_, width)) in cells.into_iter().zip(columns) {
+                            parent.spawn(
+                                TextBundle::from_section(
+                                    cell,
+                                    TextStyle {
+                                        font: game_assets.font.clone(),
+                                        font_size: 24.0,
+                                        color,
+                                    },
+                                )
+                                .with_style(Style {
+                                    width: Val::Px(width),
+                                    margin: UiRect::all(Val::Px(2.0)),
+                                    ..default()
+                                }),
+                            );
+                        }
+                    });
+            }
+
+            if high_scores.entries.is_empty() {
+                parent.spawn(TextBundle::from_section(
+                    "No high scores yet",
+                    TextStyle {
+                        font: game_assets.font.clone(),
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                    },
+                ));
+            }
+
+            let font = game_assets.font.clone();
+            spawn_menu_button(parent, font.clone(), "Play", MenuButton::Play);
+            spawn_menu_button(parent, font, "Main Menu", MenuButton::MainMenu);
+        });
+}
+
+pub fn setup_main_menu_ui(mut commands: Commands, game_assets: Res<GameAssets>) {
+    spawn_ui_camera(&mut commands);
+
+    // Title and menu buttons - Main Menu UI
     commands
         .spawn(NodeBundle {
             style: Style {
@@ -121,15 +440,61 @@ pub fn setup_game_over_ui(
             background_color: Color::rgb(0.25, 0.25, 0.25).into(),
             ..default()
         })
-        .insert(Name::new("Game_Over_UI"))
+        .insert(Name::new("Main_Menu_UI"))
         .with_children(|parent| {
-            // Game over text
+            // Title
             parent
                 .spawn(
                     TextBundle::from_section(
-                        "Game Over",
+                        "Loose Cannon",
                         TextStyle {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font: game_assets.font.clone(),
+                            font_size: 70.0,
+                            color: Color::WHITE,
+                        },
+                    )
+                    .with_style(Style {
+                        margin: UiRect::all(Val::Px(30.0)),
+                        ..default()
+                    }),
+                )
+                .insert(Name::new("Title_Text"));
+
+            let font = game_assets.font.clone();
+            spawn_menu_button(parent, font.clone(), "Play", MenuButton::Play);
+            spawn_menu_button(parent, font.clone(), "High Scores", MenuButton::HighScores);
+            spawn_menu_button(parent, font.clone(), "Settings", MenuButton::Settings);
+            spawn_menu_button(parent, font, "Quit", MenuButton::Quit);
+        });
+}
+
+pub fn setup_pause_ui(mut commands: Commands, game_assets: Res<GameAssets>) {
+    // Pause menu is drawn over the game
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            focus_policy: FocusPolicy::Block,
+            ..default()
+        })
+        .insert(Name::new("Pause_UI"))
+        .insert(PauseUI {})
+        .with_children(|parent| {
+            // Paused text
+            parent
+                .spawn(
+                    TextBundle::from_section(
+                        "Paused",
+                        TextStyle {
+                            font: game_assets.font.clone(),
                             font_size: 50.0,
                             color: Color::WHITE,
                         },
@@ -139,15 +504,47 @@ pub fn setup_game_over_ui(
                         ..default()
                     }),
                 )
-                .insert(Name::new("Game_Over_Text"));
+                .insert(Name::new("Paused_Text"));
 
-            // Final score
+            let font = game_assets.font.clone();
+            spawn_menu_button(parent, font.clone(), "Resume", MenuButton::Resume);
+            spawn_menu_button(parent, font.clone(), "Settings", MenuButton::Settings);
+            spawn_menu_button(parent, font, "Main Menu", MenuButton::MainMenu);
+        });
+}
+
+pub fn setup_settings_ui(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    settings: Res<Settings>,
+) {
+    // Settings menu is drawn over whichever menu opened it
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            background_color: Color::rgb(0.2, 0.2, 0.2).into(),
+            focus_policy: FocusPolicy::Block,
+            z_index: ZIndex::Global(10),
+            ..default()
+        })
+        .insert(Name::new("Settings_UI"))
+        .insert(SettingsUI {})
+        .with_children(|parent| {
+            // Title
             parent
                 .spawn(
                     TextBundle::from_section(
-                        format!("Final Score: {}", score.0),
+                        "Settings",
                         TextStyle {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font: game_assets.font.clone(),
                             font_size: 50.0,
                             color: Color::WHITE,
                         },
@@ -157,53 +554,138 @@ pub fn setup_game_over_ui(
                         ..default()
                     }),
                 )
-                .insert(Name::new("Game_Over_Text"));
+                .insert(Name::new("Settings_Title"));
 
-            // Restart button
+            // One row per setting with a label, a decrease button, the value and an increase button,
+            // the rows wrap into two columns so all of them fit on small windows
             parent
-                .spawn(ButtonBundle {
+                .spawn(NodeBundle {
                     style: Style {
+                        flex_direction: FlexDirection::Row,
+                        flex_wrap: FlexWrap::Wrap,
                         justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        padding: UiRect::all(Val::Px(10.0)),
-                        margin: UiRect::all(Val::Px(20.0)),
+                        max_width: Val::Px(1150.0),
                         ..default()
                     },
                     ..default()
                 })
-                .insert(Name::new("Restart_Button"))
-                .insert(RestartButton {})
+                .insert(Name::new("Setting_Rows"))
                 .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section(
-                        "Restart",
-                        TextStyle {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                            font_size: 30.0,
-                            color: Color::BLACK,
-                        },
-                    ));
+                    for kind in SettingKind::ALL {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    flex_direction: FlexDirection::Row,
+                                    align_items: AlignItems::Center,
+                                    margin: UiRect::all(Val::Px(4.0)),
+                                    ..default()
+                                },
+                                ..default()
+                            })
+                            .insert(Name::new("Setting_Row"))
+                            .with_children(|parent| {
+                                parent.spawn(
+                                    TextBundle::from_section(
+                                        kind.label(),
+                                        TextStyle {
+                                            font: game_assets.font.clone(),
+                                            font_size: 26.0,
+                                            color: Color::WHITE,
+                                        },
+                                    )
+                                    .with_style(Style {
+                                        width: Val::Px(230.0),
+                                        ..default()
+                                    }),
+                                );
+
+                                spawn_settings_button(parent, game_assets.font.clone(), kind, -1);
+
+                                parent
+                                    .spawn(
+                                        TextBundle::from_section(
+                                            kind.value_text(&settings),
+                                            TextStyle {
+                                                font: game_assets.font.clone(),
+                                                font_size: 26.0,
+                                                color: Color::YELLOW,
+                                            },
+                                        )
+                                        .with_style(
+                                            Style {
+                                                width: Val::Px(170.0),
+                                                justify_content: JustifyContent::Center,
+                                                margin: UiRect::horizontal(Val::Px(10.0)),
+                                                ..default()
+                                            },
+                                        ),
+                                    )
+                                    .insert(SettingValueUI { kind });
+
+                                spawn_settings_button(parent, game_assets.font.clone(), kind, 1);
+                            });
+                    }
                 });
+
+            spawn_menu_button(
+                parent,
+                game_assets.font.clone(),
+                "Back",
+                MenuButton::CloseSettings,
+            );
         });
 }
 
 // SYSTEMS
 
-// This system runs only when state is set to Playing
-pub fn update_score_ui(score: Res<Score>, mut score_ui_query: Query<&mut Text, With<ScoreUI>>) {
-    let mut score_ui = score_ui_query.single_mut();
+// Handles all menu navigation buttons
+pub fn menu_button_system(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+    mut next_settings_menu_state: ResMut<NextState<SettingsMenuState>>,
+    mut ev_app_exit: EventWriter<AppExit>,
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &MenuButton),
+        Changed<Interaction>,
+    >,
+) {
+    for (interaction, mut color, menu_button) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
 
-    score_ui.sections[0].value = format!("Score: {}", score.0);
+                match menu_button {
+                    MenuButton::Play => next_state.set(GameState::Playing),
+                    MenuButton::HighScores => next_state.set(GameState::HighScores),
+                    MenuButton::Settings => next_settings_menu_state.set(SettingsMenuState::Open),
+                    MenuButton::CloseSettings => {
+                        next_settings_menu_state.set(SettingsMenuState::Closed)
+                    }
+                    MenuButton::Resume => next_pause_state.set(PauseState::Running),
+                    MenuButton::MainMenu => next_state.set(GameState::MainMenu),
+                    MenuButton::Quit => ev_app_exit.send(AppExit),
+                }
+            }
+        }
+    }
 }
 
-// This system runs only when state is set to GameOver
-pub fn restart_button_system(
-    mut next_state: ResMut<NextState<GameState>>,
+// This system runs only when the settings menu is open
+pub fn settings_button_system(
+    mut settings: ResMut<Settings>,
+    levels: Res<Levels>,
     mut interaction_query: Query<
-        (&Interaction, &mut BackgroundColor),
-        (Changed<Interaction>, With<RestartButton>),
+        (&Interaction, &mut BackgroundColor, &SettingsButton),
+        Changed<Interaction>,
     >,
 ) {
-    for (interaction, mut color) in interaction_query.iter_mut() {
+    for (interaction, mut color, settings_button) in interaction_query.iter_mut() {
         match *interaction {
             Interaction::None => {
                 *color = NORMAL_BUTTON.into();
@@ -213,8 +695,124 @@ pub fn restart_button_system(
             }
             Interaction::Pressed => {
                 *color = PRESSED_BUTTON.into();
-                next_state.set(GameState::Playing);
+                settings_button
+                    .kind
+                    .adjust(&mut settings, &levels, settings_button.direction);
             }
         }
     }
 }
+
+// Updates the displayed setting values, runs when the settings have changed
+pub fn update_settings_ui(
+    settings: Res<Settings>,
+    mut setting_value_ui_query: Query<(&mut Text, &SettingValueUI)>,
+) {
+    for (mut text, setting_value_ui) in setting_value_ui_query.iter_mut() {
+        text.sections[0].value = setting_value_ui.kind.value_text(&settings);
+    }
+}
+
+pub fn update_loading_ui(
+    loading_progress: Res<LoadingProgress>,
+    mut loading_bar_ui_query: Query<&mut Style, With<LoadingBarUI>>,
+) {
+    for mut style in loading_bar_ui_query.iter_mut() {
+        style.width = Val::Percent(loading_progress.fraction() * 100.0);
+    }
+}
+
+// CLEANUP SYSTEMS
+
+pub fn teardown_pause_ui(mut commands: Commands, pause_ui_query: Query<Entity, With<PauseUI>>) {
+    for entity in pause_ui_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub fn teardown_settings_ui(
+    mut commands: Commands,
+    settings_ui_query: Query<Entity, With<SettingsUI>>,
+) {
+    for entity in settings_ui_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// HELPER FUNCTIONS
+
+// Spawns a camera for menus, the primary camera is despawned along with the menu on teardown
+fn spawn_ui_camera(commands: &mut Commands) {
+    commands
+        .spawn(Camera3dBundle {
+            camera: Camera {
+                order: 5,
+                ..default()
+            },
+            transform: Transform::default(),
+            ..default()
+        })
+        .insert(PrimaryCamera {});
+}
+
+fn spawn_menu_button(
+    parent: &mut ChildBuilder,
+    font: Handle<Font>,
+    label: &str,
+    menu_button: MenuButton,
+) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                padding: UiRect::all(Val::Px(10.0)),
+                margin: UiRect::all(Val::Px(10.0)),
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Name::new(format!("{}_Button", label.replace(' ', "_"))))
+        .insert(menu_button)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font,
+                    font_size: 30.0,
+                    color: Color::BLACK,
+                },
+            ));
+        });
+}
+
+fn spawn_settings_button(
+    parent: &mut ChildBuilder,
+    font: Handle<Font>,
+    kind: SettingKind,
+    direction: i32,
+) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                width: Val::Px(40.0),
+                padding: UiRect::all(Val::Px(4.0)),
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Name::new("Setting_Button"))
+        .insert(SettingsButton { kind, direction })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                if direction < 0 { "<" } else { ">" },
+                TextStyle {
+                    font,
+                    font_size: 26.0,
+                    color: Color::BLACK,
+                },
+            ));
+        });
+}
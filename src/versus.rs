@@ -0,0 +1,194 @@
+// Versus rounds between the local players and bots sharing the planet.
+// Players score by knocking opponents with cannon balls and by getting them hit by asteroids, an asteroid hit goes to
+// whoever knocked the player last if that was recent, otherwise to every opponent. Being hit doesn't end the round,
+// it ends after a fixed time or once a player reaches the points needed, and the scoreboard is kept for the results.
+
+use bevy::prelude::{Commands, NextState, Res, ResMut, Resource, Time};
+use std::cmp::Reverse;
+
+use crate::{
+    client::NetworkClient,
+    common::GameState,
+    player::PlayerId,
+    rollback::RollbackSession,
+    settings::{Settings, VersusRules},
+};
+
+// CONSTANTS
+
+pub const KNOCK_POINTS: i32 = 1;
+pub const ASTEROID_HIT_POINTS: i32 = 3;
+pub const KNOCK_CREDIT_WINDOW: f32 = 4.0; // Seconds an asteroid hit is credited to the player that knocked the victim
+pub const HIT_GRACE_PERIOD: f32 = 1.0; // Seconds after being hit until another asteroid hit counts
+
+// RESOURCES
+
+// Only exists during versus rounds and on the game over screen after one
+#[derive(Resource, Clone, Debug)]
+pub struct VersusScoreboard {
+    pub rules: VersusRules,
+    pub elapsed: f32, // Seconds since the round started
+    pub standings: Vec<VersusStanding>,
+}
+
+// A player's results in the round, standings are kept in PlayerId order
+#[derive(Clone, Debug)]
+pub struct VersusStanding {
+    pub player: PlayerId,
+    pub points: i32,
+    pub knocks: u32,        // Opponents knocked with cannon balls
+    pub asteroid_hits: u32, // Times the player was hit by an asteroid
+    last_knocked: Option<(PlayerId, f32)>,
+    hit_at: Option<f32>,
+}
+
+impl VersusScoreboard {
+    pub fn new(rules: VersusRules, players: u32) -> Self {
+        Self {
+            rules,
+            elapsed: 0.0,
+            standings: (0..players as usize)
+                .map(|index| VersusStanding {
+                    player: PlayerId(index),
+                    points: 0,
+                    knocks: 0,
+                    asteroid_hits: 0,
+                    last_knocked: None,
+                    hit_at: None,
+                })
+                .collect(),
+        }
+    }
+
+    // The shooter's cannon ball hit the victim
+    pub fn knock(&mut self, shooter: PlayerId, victim: PlayerId) {
+        let elapsed = self.elapsed;
+        if let Some(standing) = self.standings.get_mut(shooter.0) {
+            standing.points += KNOCK_POINTS;
+            standing.knocks += 1;
+        }
+        if let Some(standing) = self.standings.get_mut(victim.0) {
+            standing.last_knocked = Some((shooter, elapsed));
+        }
+    }
+
+    // An asteroid hit the victim, returns false if it was too soon after the last hit to count
+    pub fn asteroid_hit(&mut self, victim: PlayerId) -> bool {
+        let elapsed = self.elapsed;
+        let Some(standing) = self.standings.get_mut(victim.0) else {
+            return false;
+        };
+        if standing
+            .hit_at
+            .is_some_and(|hit_at| elapsed - hit_at < HIT_GRACE_PERIOD)
+        {
+            return false;
+        }
+
+        standing.asteroid_hits += 1;
+        standing.hit_at = Some(elapsed);
+        let knocker = standing
+            .last_knocked
+            .take()
+            .filter(|(_, knocked_at)| elapsed - knocked_at <= KNOCK_CREDIT_WINDOW)
+            .map(|(knocker, _)| knocker);
+
+        for standing in self.standings.iter_mut() {
+            let credited = match knocker {
+                Some(knocker) => standing.player == knocker,
+                None => standing.player != victim,
+            };
+            if credited {
+                standing.points += ASTEROID_HIT_POINTS;
+            }
+        }
+        true
+    }
+
+    // Seconds left in a timed round
+    pub fn remaining_seconds(&self) -> Option<f32> {
+        match self.rules {
+            VersusRules::Timed(seconds) => Some((seconds as f32 - self.elapsed).max(0.0)),
+            _ => None,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        match self.rules {
+            VersusRules::Off => false,
+            VersusRules::Timed(_) => self.remaining_seconds() == Some(0.0),
+            VersusRules::FirstTo(points) => self
+                .standings
+                .iter()
+                .any(|standing| standing.points >= points as i32),
+        }
+    }
+
+    // Standings from first to last place, ties are broken by who was hit fewer times
+    pub fn ranking(&self) -> Vec<&VersusStanding> {
+        let mut ranking = self.standings.iter().collect::<Vec<_>>();
+        ranking.sort_by_key(|standing| {
+            (
+                Reverse(standing.points),
+                standing.asteroid_hits,
+                standing.player.0,
+            )
+        });
+        ranking
+    }
+
+    // None if the round ended in a draw
+    pub fn winner(&self) -> Option<PlayerId> {
+        match self.ranking().as_slice() {
+            [first, second, ..]
+                if (first.points, first.asteroid_hits) == (second.points, second.asteroid_hits) =>
+            {
+                None
+            }
+            [first, ..] => Some(first.player),
+            [] => None,
+        }
+    }
+}
+
+// STARTUP SYSTEMS
+
+// Starts a versus round when the rules are on and more than one local player or bot shares the planet, networked games
+// keep their own rules
+pub fn setup_versus_round(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    network_client: Option<Res<NetworkClient>>,
+    rollback_session: Option<Res<RollbackSession>>,
+) {
+    let local = network_client.is_none() && rollback_session.is_none();
+    if local && settings.player_count() > 1 && settings.versus != VersusRules::Off {
+        commands.insert_resource(VersusScoreboard::new(
+            settings.versus,
+            settings.player_count(),
+        ));
+    } else {
+        commands.remove_resource::<VersusScoreboard>();
+    }
+}
+
+// SYSTEMS
+
+// Runs the round's clock and ends the round once its rules say so
+pub fn update_versus_round(
+    time: Res<Time>,
+    mut scoreboard: ResMut<VersusScoreboard>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    scoreboard.elapsed += time.delta_seconds();
+
+    if scoreboard.finished() {
+        next_state.set(GameState::GameOver);
+    }
+}
+
+// CLEANUP SYSTEMS
+
+pub fn end_versus_round(mut commands: Commands) {
+    commands.remove_resource::<VersusScoreboard>();
+}